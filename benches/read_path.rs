@@ -0,0 +1,203 @@
+//! Steady-state read path benchmark for `WebSocketClient`.
+//!
+//! Streams OKX-like order book frames from a local plain-TCP server and
+//! counts heap allocations made while reading them. The read path, including
+//! the pong sent in reply to each server ping, is expected to allocate
+//! nothing once the receive and scratch buffers are warmed up. The same holds
+//! for the non-blocking busy-poll path through `try_read_message`.
+//!
+//! Run with `cargo bench --bench read_path`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::prelude::*;
+use sha1::{Digest, Sha1};
+
+#[path = "../src/timestamping.rs"]
+mod timestamping;
+#[path = "../src/websocket.rs"]
+mod websocket;
+
+use websocket::{WebSocketClient, WebSocketConfig, WebSocketMessageRef};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const WARMUP_MESSAGES: usize = 10_000;
+const MEASURED_MESSAGES: usize = 200_000;
+
+const BOOK_UPDATE: &str = r#"{"arg":{"channel":"books5","instId":"BTC-USDT"},"data":[{"asks":[["67012.1","0.5","0","3"],["67012.2","1.2","0","5"],["67012.5","0.01","0","1"],["67013","2","0","4"],["67013.4","0.3","0","2"]],"bids":[["67012","0.8","0","6"],["67011.9","0.02","0","1"],["67011.5","1.5","0","3"],["67011","0.4","0","2"],["67010.2","3","0","7"]],"instId":"BTC-USDT","ts":"1718000000000","seqId":123456789}]}"#;
+
+fn encode_frame(out: &mut Vec<u8>, fin: bool, opcode: u8, payload: &[u8]) {
+    out.push(if fin { 0x80 | opcode } else { opcode });
+    if payload.len() < 126 {
+        out.push(payload.len() as u8);
+    } else if payload.len() < 65536 {
+        out.push(126);
+        out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        out.push(127);
+        out.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+    out.extend_from_slice(payload);
+}
+
+/// One batch of traffic: plain text updates, a binary frame, a ping and a
+/// message fragmented across three frames.
+fn traffic_batch() -> (Vec<u8>, usize) {
+    let mut batch = Vec::new();
+    let mut messages = 0;
+
+    for _ in 0..64 {
+        encode_frame(&mut batch, true, 0x1, BOOK_UPDATE.as_bytes());
+        messages += 1;
+    }
+
+    encode_frame(&mut batch, true, 0x2, &[0xab; 512]);
+    messages += 1;
+
+    encode_frame(&mut batch, true, 0x9, b"keepalive");
+    messages += 1;
+
+    let bytes = BOOK_UPDATE.as_bytes();
+    let third = bytes.len() / 3;
+    encode_frame(&mut batch, false, 0x1, &bytes[..third]);
+    encode_frame(&mut batch, false, 0x0, &bytes[third..2 * third]);
+    encode_frame(&mut batch, true, 0x0, &bytes[2 * third..]);
+    messages += 1;
+
+    (batch, messages)
+}
+
+fn spawn_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind local server");
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("accept client");
+        stream.set_nodelay(true).unwrap();
+
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut buf).expect("read handshake");
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let request = String::from_utf8(request).unwrap();
+        let key = request
+            .lines()
+            .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+            .expect("client sent Sec-WebSocket-Key");
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key.trim()).as_bytes());
+        let accept = BASE64_STANDARD.encode(hasher.finalize());
+
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\
+             \r\n",
+            accept
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+
+        let (batch, _) = traffic_batch();
+        // Runs until the client disconnects
+        while stream.write_all(&batch).is_ok() {}
+    });
+
+    port
+}
+
+fn message_len(message: WebSocketMessageRef<'_>) -> usize {
+    match message {
+        WebSocketMessageRef::Text(text) => text.len(),
+        WebSocketMessageRef::Binary(data) => data.len(),
+        WebSocketMessageRef::Ping(data) => data.len(),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+fn read_messages(client: &mut WebSocketClient, count: usize) -> usize {
+    let mut bytes = 0;
+    for _ in 0..count {
+        bytes += message_len(client.read_message_ref().expect("read message"));
+    }
+    bytes
+}
+
+fn spin_messages(client: &mut WebSocketClient, count: usize) -> usize {
+    let mut bytes = 0;
+    let mut received = 0;
+    while received < count {
+        if let Some(message) = client.try_read_message().expect("read message") {
+            bytes += message_len(message);
+            received += 1;
+        }
+    }
+    bytes
+}
+
+fn measure(name: &str, client: &mut WebSocketClient, read: fn(&mut WebSocketClient, usize) -> usize) {
+    read(client, WARMUP_MESSAGES);
+
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let bytes = read(client, MEASURED_MESSAGES);
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+
+    let (_, batch_messages) = traffic_batch();
+    println!("{}: {} messages ({} per batch), {} payload bytes", name, MEASURED_MESSAGES, batch_messages, bytes);
+    println!("  Time per message:   {:.1}ns", elapsed.as_nanos() as f64 / MEASURED_MESSAGES as f64);
+    println!("  Throughput:         {:.1} MB/s", bytes as f64 / elapsed.as_secs_f64() / 1_000_000.0);
+    println!("  Allocations:        {}", allocations);
+
+    assert_eq!(allocations, 0, "{} allocated in steady state", name);
+}
+
+fn main() {
+    let port = spawn_server();
+
+    let config = WebSocketConfig {
+        connect_timeout: Duration::from_secs(5),
+        read_timeout: Some(Duration::from_secs(5)),
+        // Keep the keepalive ping (and its send path) out of the measurement
+        ping_interval: Duration::from_secs(3600),
+        ..Default::default()
+    };
+    let url = format!("ws://127.0.0.1:{}/ws", port);
+    let mut client = WebSocketClient::connect_with_config(&url, config).expect("connect");
+
+    measure("blocking", &mut client, read_messages);
+
+    client.set_nonblocking(true).expect("switch to non-blocking");
+    measure("busy-poll", &mut client, spin_messages);
+}
@@ -0,0 +1,403 @@
+//! Local stand-in for the OKX private WebSocket endpoint.
+//!
+//! Accepts plain `ws://` connections, verifies `login` requests the way OKX
+//! does (API key, passphrase, timestamp within 30 seconds and the
+//! HMAC-SHA256 signature) and, once logged in, acknowledges subscriptions and
+//! pushes a sample order lifecycle, position and account update every second.
+//! Trade requests (`order`, `batch-orders`, `amend-order`, `cancel-order`)
+//! are acked against an in-memory order list, after `MOCK_ACK_DELAY_MS`
+//! milliseconds (default 0).
+//!
+//! ```bash
+//! cargo run --example okx_private_mock -- 127.0.0.1:8765
+//! OKX_API_KEY=test-key OKX_SECRET_KEY=test-secret OKX_PASSPHRASE=test-passphrase \
+//!     cargo run -- private --ws-url ws://127.0.0.1:8765/ws/v5/private
+//! ```
+//!
+//! The expected credentials come from the same environment variables, with
+//! the values above as defaults.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::prelude::*;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+#[path = "../src/okx_private.rs"]
+mod okx_private;
+
+use okx_private::{AmendOrder, CancelOrder, Credentials, NewOrder, OrderOp, CHANNELS, LOGIN_METHOD, LOGIN_PATH};
+
+const MAX_TIMESTAMP_SKEW_SECS: u64 = 30;
+const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<String>,
+    op: String,
+    #[serde(default)]
+    args: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginArgs {
+    api_key: String,
+    passphrase: String,
+    timestamp: String,
+    sign: String,
+}
+
+// Shared between a connection's reader and pusher threads
+#[derive(Default)]
+struct Session {
+    logged_in: AtomicBool,
+    closed: AtomicBool,
+    subscribed: Mutex<Vec<String>>,
+    /// Resting orders, clOrdId to ordId
+    orders: Mutex<HashMap<String, String>>,
+}
+
+fn main() {
+    let ack_delay = std::env::var("MOCK_ACK_DELAY_MS").ok()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or_default();
+    let addr = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8765".to_string());
+    let credentials = Arc::new(Credentials::from_env().unwrap_or_else(|| Credentials {
+        api_key: "test-key".to_string(),
+        secret_key: "test-secret".to_string(),
+        passphrase: "test-passphrase".to_string(),
+    }));
+
+    let listener = TcpListener::bind(&addr).expect("bind mock server");
+    println!("Mock OKX private endpoint on ws://{}/ws/v5/private", listener.local_addr().unwrap());
+    println!("Expecting API key {:?}", credentials.api_key);
+
+    let connections = AtomicU64::new(0);
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let (credentials, id) = (credentials.clone(), connections.fetch_add(1, Ordering::Relaxed));
+        thread::spawn(move || {
+            if let Err(e) = serve(stream, &credentials, id, ack_delay) {
+                println!("[conn {}] ended: {}", id, e);
+            }
+        });
+    }
+}
+
+fn serve(mut stream: TcpStream, credentials: &Credentials, id: u64, ack_delay: Duration) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    accept_handshake(&mut stream)?;
+    println!("[conn {}] connected", id);
+
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let session = Arc::new(Session::default());
+    let pusher = {
+        let (writer, session) = (writer.clone(), session.clone());
+        thread::spawn(move || push_updates(&writer, &session))
+    };
+
+    let result = read_requests(&mut stream, &writer, &session, credentials, id, ack_delay);
+    session.closed.store(true, Ordering::Relaxed);
+    let _ = pusher.join();
+    result
+}
+
+fn read_requests(
+    stream: &mut TcpStream,
+    writer: &Mutex<TcpStream>,
+    session: &Session,
+    credentials: &Credentials,
+    id: u64,
+    ack_delay: Duration,
+) -> io::Result<()> {
+    let send = |text: &str| send_frame(&mut writer.lock().unwrap(), 0x1, text.as_bytes());
+    let conn_id = format!("mock{:04}", id);
+
+    loop {
+        let (opcode, payload) = read_frame(stream)?;
+        match opcode {
+            0x8 => {
+                send_frame(&mut writer.lock().unwrap(), 0x8, &payload)?;
+                return Ok(());
+            }
+            0x9 => send_frame(&mut writer.lock().unwrap(), 0xa, &payload)?,
+            0x1 => {}
+            _ => continue,
+        }
+
+        let text = String::from_utf8_lossy(&payload);
+        if text == "ping" {
+            send("pong")?;
+            continue;
+        }
+        let request: Request = match serde_json::from_str(&text) {
+            Ok(request) => request,
+            Err(e) => {
+                send(&error("60012", &format!("Invalid request: {}", e), &conn_id))?;
+                continue;
+            }
+        };
+
+        match request.op.as_str() {
+            "login" => {
+                let verdict = request.args.first()
+                    .and_then(|args| serde_json::from_value::<LoginArgs>(args.clone()).ok())
+                    .ok_or(("60009", "Login failed.".to_string()))
+                    .and_then(|args| verify_login(&args, credentials));
+                match verdict {
+                    Ok(()) => {
+                        println!("[conn {}] login ok, signature verified", id);
+                        session.logged_in.store(true, Ordering::Relaxed);
+                        send(&serde_json::json!({ "event": "login", "code": "0", "msg": "", "connId": conn_id }).to_string())?;
+                    }
+                    Err((code, msg)) => {
+                        println!("[conn {}] login rejected: {} {}", id, code, msg);
+                        send(&error(code, &msg, &conn_id))?;
+                    }
+                }
+            }
+            _ if !session.logged_in.load(Ordering::Relaxed) => {
+                send(&error("60011", "Please log in", &conn_id))?;
+            }
+            "subscribe" => {
+                for arg in &request.args {
+                    let channel = arg["channel"].as_str().unwrap_or_default();
+                    if !CHANNELS.contains(&channel) {
+                        send(&error("60018", &format!("Wrong URL or channel:{} doesn't exist.", channel), &conn_id))?;
+                        continue;
+                    }
+                    session.subscribed.lock().unwrap().push(channel.to_string());
+                    send(&serde_json::json!({ "event": "subscribe", "arg": arg, "connId": conn_id }).to_string())?;
+                }
+            }
+            op => match OrderOp::from_name(op) {
+                Some(op) => {
+                    let in_time = unix_timestamp_us();
+                    thread::sleep(ack_delay);
+                    let results = trade(op, &request.args, &mut session.orders.lock().unwrap());
+                    send(&trade_ack(request.id.unwrap_or_default(), op, results, in_time))?;
+                }
+                None => send(&error("60012", &format!("Invalid request: unknown op {}", op), &conn_id))?,
+            },
+        }
+    }
+}
+
+/// Checks a login the way OKX does, returning its error code on failure.
+fn verify_login(args: &LoginArgs, credentials: &Credentials) -> Result<(), (&'static str, String)> {
+    if args.api_key != credentials.api_key {
+        return Err(("60005", "Invalid OK-ACCESS-KEY".to_string()));
+    }
+    if args.passphrase != credentials.passphrase {
+        return Err(("60024", "Wrong passphrase".to_string()));
+    }
+    let timestamp: u64 = args.timestamp.parse()
+        .map_err(|_| ("60004", format!("Invalid timestamp {}", args.timestamp)))?;
+    if timestamp.abs_diff(okx_private::unix_timestamp_secs()) > MAX_TIMESTAMP_SKEW_SECS {
+        return Err(("60004", format!("Timestamp {} is more than {}s off", timestamp, MAX_TIMESTAMP_SKEW_SECS)));
+    }
+    let expected = okx_private::sign(&credentials.secret_key, &args.timestamp, LOGIN_METHOD, LOGIN_PATH);
+    if args.sign != expected {
+        return Err(("60007", "Invalid sign".to_string()));
+    }
+    Ok(())
+}
+
+/// Applies a trade request to the order list, returning each order's
+/// `(clOrdId, ordId, sCode, sMsg)`.
+fn trade(op: OrderOp, args: &[serde_json::Value], orders: &mut HashMap<String, String>) -> Vec<(String, String, &'static str, &'static str)> {
+    let next_ord_id = || (unix_timestamp_us() % 1_000_000_000_000).to_string();
+    args.iter()
+        .map(|arg| match op {
+            OrderOp::Order | OrderOp::BatchOrders => match serde_json::from_value::<NewOrder>(arg.clone()) {
+                Ok(order) if orders.contains_key(&order.cl_ord_id) => (order.cl_ord_id, String::new(), "51016", "Duplicated clOrdId"),
+                Ok(order) => {
+                    let ord_id = next_ord_id();
+                    orders.insert(order.cl_ord_id.clone(), ord_id.clone());
+                    (order.cl_ord_id, ord_id, "0", "Order placed")
+                }
+                Err(_) => (String::new(), String::new(), "51000", "Parameter error"),
+            },
+            OrderOp::AmendOrder => match serde_json::from_value::<AmendOrder>(arg.clone()) {
+                Ok(amend) if amend.new_px.is_none() && amend.new_sz.is_none() => (amend.cl_ord_id, String::new(), "51000", "Parameter error"),
+                Ok(amend) => match orders.get(&amend.cl_ord_id) {
+                    Some(ord_id) => (amend.cl_ord_id.clone(), ord_id.clone(), "0", ""),
+                    None => (amend.cl_ord_id, String::new(), "51603", "Order does not exist"),
+                },
+                Err(_) => (String::new(), String::new(), "51000", "Parameter error"),
+            },
+            OrderOp::CancelOrder => match serde_json::from_value::<CancelOrder>(arg.clone()) {
+                Ok(cancel) => match orders.remove(&cancel.cl_ord_id) {
+                    Some(ord_id) => (cancel.cl_ord_id, ord_id, "0", ""),
+                    None => (cancel.cl_ord_id, String::new(), "51400", "Order cancellation failed as the order has been filled, canceled or does not exist"),
+                },
+                Err(_) => (String::new(), String::new(), "51000", "Parameter error"),
+            },
+        })
+        .collect()
+}
+
+fn trade_ack(id: String, op: OrderOp, results: Vec<(String, String, &str, &str)>, in_time: u64) -> String {
+    let failed = results.iter().filter(|(_, _, code, _)| *code != "0").count();
+    let code = match failed {
+        0 => "0",
+        n if n == results.len() => "1",
+        _ => "2",
+    };
+    let data: Vec<serde_json::Value> = results.into_iter()
+        .map(|(cl_ord_id, ord_id, s_code, s_msg)| serde_json::json!({
+            "clOrdId": cl_ord_id, "ordId": ord_id, "tag": "", "ts": (unix_timestamp_us() / 1000).to_string(),
+            "sCode": s_code, "sMsg": s_msg,
+        }))
+        .collect();
+    serde_json::json!({
+        "id": id, "op": op.name(), "code": code, "msg": "", "data": data,
+        "inTime": in_time.to_string(), "outTime": unix_timestamp_us().to_string(),
+    })
+    .to_string()
+}
+
+fn unix_timestamp_us() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_micros() as u64).unwrap_or_default()
+}
+
+fn error(code: &str, msg: &str, conn_id: &str) -> String {
+    serde_json::json!({ "event": "error", "code": code, "msg": msg, "connId": conn_id }).to_string()
+}
+
+/// Pushes an order that goes live, partially fills and fills, with the
+/// matching position and account updates, once per interval.
+fn push_updates(writer: &Mutex<TcpStream>, session: &Session) {
+    const STATES: [(&str, &str); 3] = [("live", "0"), ("partially_filled", "0.4"), ("filled", "1")];
+    let mut tick = 0u64;
+
+    while !session.closed.load(Ordering::Relaxed) {
+        thread::sleep(PUSH_INTERVAL);
+        let subscribed = session.subscribed.lock().unwrap().clone();
+        let now_ms = (okx_private::unix_timestamp_secs() * 1000).to_string();
+        let (state, filled) = STATES[(tick % 3) as usize];
+        let order_id = 1_000 + tick / 3;
+
+        for channel in &subscribed {
+            let data = match channel.as_str() {
+                "orders" => serde_json::json!([{
+                    "instType": "SPOT", "instId": "BTC-USDT", "ordId": order_id.to_string(),
+                    "clOrdId": format!("mock{}", order_id), "px": "65000", "sz": "1", "side": "buy",
+                    "ordType": "limit", "state": state, "fillPx": if filled == "0" { "" } else { "65000" },
+                    "fillSz": filled, "accFillSz": filled, "avgPx": if filled == "0" { "" } else { "65000" },
+                    "uTime": now_ms, "cTime": now_ms,
+                }]),
+                "positions" => serde_json::json!([{
+                    "instType": "SWAP", "instId": "BTC-USDT-SWAP", "posId": "1", "posSide": "net",
+                    "pos": (tick + 1).to_string(), "avgPx": "65000", "upl": "12.5", "lever": "3",
+                    "liqPx": "43000", "mgnMode": "cross", "uTime": now_ms,
+                }]),
+                "account" => serde_json::json!([{
+                    "uTime": now_ms, "totalEq": "100000",
+                    "details": [{ "ccy": "USDT", "eq": "100000", "cashBal": "100000", "availBal": "35000", "frozenBal": "65000" }],
+                }]),
+                _ => continue,
+            };
+            let push = serde_json::json!({ "arg": { "channel": channel, "uid": "1" }, "data": data }).to_string();
+            if send_frame(&mut writer.lock().unwrap(), 0x1, push.as_bytes()).is_err() {
+                return;
+            }
+        }
+        if !subscribed.is_empty() {
+            tick += 1;
+        }
+    }
+}
+
+fn accept_handshake(stream: &mut TcpStream) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let key = request
+        .lines()
+        .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Sec-WebSocket-Key"))?;
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key.trim()).as_bytes());
+    let accept = BASE64_STANDARD.encode(hasher.finalize());
+
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\
+         \r\n",
+        accept
+    );
+    stream.write_all(response.as_bytes())
+}
+
+/// Reads one client frame, unmasking its payload. Fragments are returned as
+/// they arrive; the connector never fragments its requests.
+fn read_frame(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0f;
+    let masked = header[1] & 0x80 != 0;
+
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as usize
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len) as usize
+        }
+        len => len as usize,
+    };
+
+    let mut mask = [0u8; 4];
+    if masked {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    Ok((opcode, payload))
+}
+
+fn send_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() < 65536 {
+        frame.push(126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
@@ -0,0 +1,175 @@
+use serde::Deserialize;
+
+use crate::refdata;
+use crate::trades::{Side, Trade};
+use crate::venue::{number, Adapter, MarketEvent, RefData, Venue};
+use crate::websocket::Heartbeat;
+
+pub const PUBLIC_WS_URL: &str = "wss://stream.binance.com:9443/ws";
+
+// Minimal structs for Binance spot raw stream messages. Book tickers carry
+// no event type or timestamp, so they are told apart by shape.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BinanceMessage {
+    Trade(BinanceTrade),
+    Event(BinanceEvent),
+    BookTicker(BinanceBookTicker),
+    Response(BinanceResponse),
+}
+
+/// Any stream event with an event time, e.g. `trade`.
+#[derive(Debug, Deserialize)]
+pub struct BinanceEvent {
+    /// Event time, ms
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s", default)]
+    pub symbol: String,
+}
+
+/// `<symbol>@trade` push.
+#[derive(Debug, Deserialize)]
+pub struct BinanceTrade {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "t")]
+    pub trade_id: u64,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "q")]
+    pub quantity: String,
+    /// Trade time, ms
+    #[serde(rename = "T")]
+    pub trade_time: u64,
+    /// True when the buyer was the maker, i.e. the taker sold
+    #[serde(rename = "m")]
+    pub buyer_is_maker: bool,
+}
+
+/// `<symbol>@bookTicker` push.
+#[derive(Debug, Deserialize)]
+pub struct BinanceBookTicker {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "b")]
+    pub bid_price: String,
+    #[serde(rename = "a")]
+    pub ask_price: String,
+}
+
+/// Reply to a `SUBSCRIBE` request.
+#[derive(Debug, Deserialize)]
+pub struct BinanceResponse {
+    pub id: Option<u64>,
+    #[serde(default)]
+    pub error: Option<BinanceError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BinanceError {
+    pub code: i64,
+    pub msg: String,
+}
+
+/// Binance spot symbol for a `BASE-QUOTE` instrument, e.g. `BTCUSDT`.
+pub fn symbol(instrument: &str) -> String {
+    instrument.replace('-', "").to_ascii_uppercase()
+}
+
+/// `SUBSCRIBE` request for `streams` (e.g. `bookTicker`) of every symbol.
+pub fn subscribe_request(streams: &[String], instruments: &[String]) -> String {
+    request("SUBSCRIBE", streams, instruments)
+}
+
+pub fn unsubscribe_request(streams: &[String], instruments: &[String]) -> String {
+    request("UNSUBSCRIBE", streams, instruments)
+}
+
+fn request(method: &str, streams: &[String], instruments: &[String]) -> String {
+    let params: Vec<String> = instruments.iter()
+        .flat_map(|instrument| {
+            let symbol = symbol(instrument).to_ascii_lowercase();
+            streams.iter().map(move |stream| format!("{}@{}", symbol, stream))
+        })
+        .collect();
+    serde_json::json!({ "method": method, "params": params, "id": 1 }).to_string()
+}
+
+pub struct Binance;
+
+impl Venue for Binance {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::Ping
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        symbol(instrument)
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "trade"
+    }
+
+    // Book tickers carry no timestamp, so latency comes from the trades
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["bookTicker", "trade"]
+    }
+
+    fn sequential_trade_ids(&self) -> bool {
+        true
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(BinanceAdapter { refdata })
+    }
+}
+
+/// Turns trades into trades, book tickers into BBOs and other stream events
+/// into heartbeats.
+pub struct BinanceAdapter {
+    refdata: RefData,
+}
+
+impl Adapter for BinanceAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![subscribe_request(channels, symbols)]
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![unsubscribe_request(channels, symbols)]
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        match serde_json::from_str(text)? {
+            BinanceMessage::Trade(trade) => events.push(MarketEvent::Trade(Trade {
+                price: number("p", &trade.price)?,
+                size: number("q", &trade.quantity)? * refdata::contract_multiplier(&self.refdata, &trade.symbol),
+                side: if trade.buyer_is_maker { Side::Sell } else { Side::Buy },
+                count: 1,
+                exchange_ns: trade.trade_time * 1_000_000,
+                symbol: trade.symbol,
+                trade_id: trade.trade_id.to_string(),
+            })),
+            BinanceMessage::Event(event) => events.push(MarketEvent::Heartbeat {
+                symbol: event.symbol,
+                exchange_ns: event.event_time * 1_000_000,
+            }),
+            BinanceMessage::BookTicker(ticker) => events.push(MarketEvent::Bbo {
+                bid: Some(number("b", &ticker.bid_price)?),
+                ask: Some(number("a", &ticker.ask_price)?),
+                exchange_ns: None,
+                symbol: ticker.symbol,
+            }),
+            BinanceMessage::Response(response) => match response.error {
+                Some(error) => tracing::error!("binance error {}: {}", error.code, error.msg),
+                None => tracing::info!("binance: request {:?} acknowledged", response.id),
+            },
+        }
+        Ok(())
+    }
+}
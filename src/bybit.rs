@@ -0,0 +1,204 @@
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::refdata;
+use crate::trades::{Side, Trade};
+use crate::venue::{invalid, number, text_heartbeat, Adapter, BookUpdate, MarketEvent, RefData, Venue};
+use crate::websocket::Heartbeat;
+
+pub const PUBLIC_WS_URL: &str = "wss://stream.bybit.com/v5/public/spot";
+
+/// Keepalive request; Bybit asks for one every 20 s.
+pub const PING: &str = r#"{"op":"ping"}"#;
+
+// Minimal structs for Bybit v5 public messages. `D` is the topic's data
+// type, book levels by default.
+#[derive(Debug, Deserialize)]
+#[serde(bound(deserialize = "D: Deserialize<'de>"))]
+pub struct BybitMessage<D = BybitBookData> {
+    /// `<topic>.<symbol>`, e.g. `orderbook.1.BTCUSDT`
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// `snapshot` or `delta`
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+    /// Time the message was generated, ms
+    #[serde(default)]
+    pub ts: Option<u64>,
+    #[serde(default)]
+    pub data: Option<D>,
+    /// Set on request replies
+    #[serde(default)]
+    pub op: Option<String>,
+    #[serde(default)]
+    pub success: Option<bool>,
+    #[serde(default)]
+    pub ret_msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BybitBookData {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// `[price, size]`; a size of `0` deletes the level
+    #[serde(rename = "b", default)]
+    pub bids: Vec<[String; 2]>,
+    #[serde(rename = "a", default)]
+    pub asks: Vec<[String; 2]>,
+}
+
+/// `publicTrade.<symbol>` entry.
+#[derive(Debug, Deserialize)]
+pub struct BybitTrade {
+    /// Trade time, ms
+    #[serde(rename = "T")]
+    pub time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// Taker side, `Buy` or `Sell`
+    #[serde(rename = "S")]
+    pub side: String,
+    #[serde(rename = "v")]
+    pub size: String,
+    #[serde(rename = "p")]
+    pub price: String,
+    /// Not sequential: numeric on spot, a UUID on derivatives
+    #[serde(rename = "i")]
+    pub trade_id: String,
+}
+
+impl<D> BybitMessage<D> {
+    /// Logs request replies, pongs included. Returns false for topic data.
+    pub fn handle_reply(&self) -> bool {
+        let Some(op) = &self.op else {
+            return false;
+        };
+        if self.success == Some(false) {
+            tracing::error!("bybit {} failed: {}", op, self.ret_msg.as_deref().unwrap_or_default());
+        } else if op == "ping" {
+            tracing::debug!("bybit: pong");
+        } else {
+            tracing::info!("bybit: {} acknowledged", op);
+        }
+        true
+    }
+}
+
+/// Bybit symbol for a `BASE-QUOTE` instrument, e.g. `BTCUSDT`.
+pub fn symbol(instrument: &str) -> String {
+    instrument.replace('-', "").to_ascii_uppercase()
+}
+
+/// `subscribe` request for `topic.<symbol>` of every symbol, e.g. topic
+/// `orderbook.1`.
+pub fn subscribe_request(topics: &[String], instruments: &[String]) -> String {
+    request("subscribe", topics, instruments)
+}
+
+pub fn unsubscribe_request(topics: &[String], instruments: &[String]) -> String {
+    request("unsubscribe", topics, instruments)
+}
+
+fn request(op: &str, topics: &[String], instruments: &[String]) -> String {
+    let args: Vec<String> = instruments.iter()
+        .flat_map(|instrument| {
+            let symbol = symbol(instrument);
+            topics.iter().map(move |topic| format!("{}.{}", topic, symbol))
+        })
+        .collect();
+    serde_json::json!({ "op": op, "args": args }).to_string()
+}
+
+pub struct Bybit;
+
+impl Venue for Bybit {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        text_heartbeat(PING, None)
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        symbol(instrument)
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "publicTrade"
+    }
+
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["orderbook.1"]
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(BybitAdapter { refdata })
+    }
+}
+
+/// Turns `orderbook.<depth>` pushes into book events and `publicTrade`
+/// pushes into trades.
+pub struct BybitAdapter {
+    refdata: RefData,
+}
+
+impl Adapter for BybitAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![subscribe_request(channels, symbols)]
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![unsubscribe_request(channels, symbols)]
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        let msg: BybitMessage<Box<RawValue>> = serde_json::from_str(text)?;
+        if msg.handle_reply() {
+            return Ok(());
+        }
+        let (Some(topic), Some(data)) = (msg.topic.as_deref(), &msg.data) else {
+            return Ok(());
+        };
+
+        match topic.split('.').collect::<Vec<_>>().as_slice() {
+            ["orderbook", depth, _] => {
+                let data: BybitBookData = serde_json::from_str(data.get())?;
+                let levels = |levels: Vec<[String; 2]>| levels.into_iter().map(|[price, size]| (price, size)).collect();
+                let update = BookUpdate {
+                    symbol: data.symbol,
+                    exchange_ns: msg.ts.map(|ms| ms * 1_000_000),
+                    bids: levels(data.bids),
+                    asks: levels(data.asks),
+                    depth: depth.parse().ok(),
+                    ..BookUpdate::default()
+                };
+                events.push(match msg.kind.as_deref() {
+                    Some("snapshot") => MarketEvent::BookSnapshot(update),
+                    _ => MarketEvent::BookDelta(update),
+                });
+            }
+            ["publicTrade", _] => {
+                let trades: Vec<BybitTrade> = serde_json::from_str(data.get())?;
+                for trade in trades {
+                    let side = match trade.side.as_str() {
+                        "Buy" => Side::Buy,
+                        "Sell" => Side::Sell,
+                        other => return Err(invalid("S", other)),
+                    };
+                    events.push(MarketEvent::Trade(Trade {
+                        price: number("p", &trade.price)?,
+                        size: number("v", &trade.size)? * refdata::contract_multiplier(&self.refdata, &trade.symbol),
+                        count: 1,
+                        exchange_ns: trade.time * 1_000_000,
+                        symbol: trade.symbol,
+                        trade_id: trade.trade_id,
+                        side,
+                    }));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
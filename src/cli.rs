@@ -0,0 +1,1096 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+use crate::config::{Config, ConfigError, SinkConfig, WebSocketSettings};
+use crate::okx_private::{Credentials, NewOrder};
+use crate::subscriber::SubscriptionMeta;
+use crate::timestamping::KernelTimestamping;
+use crate::venue::RefData;
+use crate::websocket::{Heartbeat, WebSocketConfig};
+
+const DEFAULT_MAX_SYMBOLS_PER_SUB: usize = 200;
+
+/// Measures WebSocket market data latency to cryptocurrency exchanges.
+///
+/// Runs `measure` when no subcommand is given.
+#[derive(Debug, Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub measure: MeasureArgs,
+
+    /// TOML file with sources, sinks and WebSocket settings; flags given on
+    /// the command line take precedence over it
+    #[arg(long, global = true, env = "CEX_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Source from the config file to use (required if it has several)
+    #[arg(long, global = true)]
+    pub source: Option<String>,
+}
+
+impl Cli {
+    pub fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Measure(self.measure))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Measure end-to-end latency of live updates
+    Measure(MeasureArgs),
+    /// Record raw messages with their receive timestamps for later replay
+    Record(RecordArgs),
+    /// Replay a recording through the latency pipeline
+    Replay(ReplayArgs),
+    /// Maintain local order books and print the top of book
+    Book(BookArgs),
+    /// Normalize public trades, check trade ids for gaps and build OHLCV bars
+    Trades(TradesArgs),
+    /// Follow perpetual swap funding, mark price, open interest, liquidations and price limits
+    Perp(PerpArgs),
+    /// Build option chains with greeks, implied volatility, mark prices and top of book
+    Options(OptionsArgs),
+    /// Compare latency and BBO timing of one instrument across venues
+    Compare(CompareArgs),
+    /// Log in to the OKX private endpoint and print order, position and account updates
+    Private(PrivateArgs),
+    /// Place, amend and cancel orders over the OKX private WebSocket, timing each ack
+    OrderLatency(OrderLatencyArgs),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Exchange {
+    Okx,
+    Binance,
+    Bybit,
+    Coinbase,
+    Kraken,
+    Deribit,
+    Hyperliquid,
+    Kucoin,
+}
+
+impl Exchange {
+    pub fn name(self) -> &'static str {
+        match self {
+            Exchange::Okx => "okx",
+            Exchange::Binance => "binance",
+            Exchange::Bybit => "bybit",
+            Exchange::Coinbase => "coinbase",
+            Exchange::Kraken => "kraken",
+            Exchange::Deribit => "deribit",
+            Exchange::Hyperliquid => "hyperliquid",
+            Exchange::Kucoin => "kucoin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable tables
+    #[default]
+    Text,
+    /// One JSON object per report
+    Json,
+}
+
+/// Exchange, channels and symbols to subscribe to.
+#[derive(Debug, Clone, Args)]
+pub struct FeedArgs {
+    #[arg(long, value_enum, default_value_t = Exchange::Okx)]
+    pub exchange: Exchange,
+
+    /// Channels to subscribe to, comma-separated
+    #[arg(long = "channel", value_delimiter = ',', default_value = "books5")]
+    pub channels: Vec<String>,
+
+    /// Symbols to subscribe to, comma-separated
+    #[arg(long = "symbols", value_delimiter = ',', default_value = "BTC-USDT")]
+    pub symbols: Vec<String>,
+
+    /// WebSocket URL, instead of the exchange's public endpoint
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    /// REST API base URL, instead of the exchange's; KuCoin issues its
+    /// connect tokens there
+    #[arg(long)]
+    pub rest_url: Option<String>,
+
+    /// Most symbols to put in a single subscribe request
+    #[arg(long, default_value_t = DEFAULT_MAX_SYMBOLS_PER_SUB)]
+    pub max_symbols_per_sub: usize,
+
+    /// Instruments the venue's adapter scales contract sizes with, loaded
+    /// by commands that take `--refdata`
+    #[arg(skip)]
+    pub refdata: RefData,
+}
+
+impl FeedArgs {
+    pub fn ws_url(&self) -> &str {
+        self.ws_url.as_deref().unwrap_or(self.exchange.venue().public_ws_url())
+    }
+
+    pub fn rest_url(&self) -> &str {
+        self.rest_url.as_deref().unwrap_or(crate::kucoin::PUBLIC_REST_URL)
+    }
+
+    fn apply_source(&mut self, source: &SubscriptionMeta, matches: &ArgMatches) {
+        if !from_cli(matches, "exchange") {
+            self.exchange = Exchange::from_str(&source.exchange, true)
+                .expect("config validation only accepts known exchanges");
+        }
+        if !from_cli(matches, "channels") {
+            self.channels = source.channels.clone();
+        }
+        if !from_cli(matches, "symbols") {
+            self.symbols = source.symbols.clone();
+        }
+        if !from_cli(matches, "ws_url") {
+            self.ws_url = Some(source.ws_url.clone());
+        }
+        if !source.rest_url.is_empty() && !from_cli(matches, "rest_url") {
+            self.rest_url = Some(source.rest_url.clone());
+        }
+        if !from_cli(matches, "max_symbols_per_sub") {
+            self.max_symbols_per_sub = source.max_symbols_per_sub;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ConnectionArgs {
+    #[arg(long, value_parser = parse_duration, default_value = "10s")]
+    pub connect_timeout: Duration,
+
+    /// Socket read timeout; `0` waits forever
+    #[arg(long, value_parser = parse_duration, default_value = "30s")]
+    pub read_timeout: Duration,
+
+    /// Socket write timeout; `0` waits forever
+    #[arg(long, value_parser = parse_duration, default_value = "10s")]
+    pub write_timeout: Duration,
+
+    /// Interval between keepalive pings, sent whether or not data arrives
+    #[arg(long, value_parser = parse_duration, default_value = "20s")]
+    pub ping_interval: Duration,
+
+    /// Keepalive instead of the venue's own: `ping` (WebSocket pings),
+    /// `text:<message>` (e.g. `text:{"op":"ping"}`) or `respond` (only answer
+    /// the server's pings)
+    #[arg(long, value_parser = parse_heartbeat)]
+    pub heartbeat: Option<Heartbeat>,
+
+    /// Reconnect after receiving nothing, not even a pong, for this long;
+    /// `0` never does
+    #[arg(long, value_parser = parse_duration, default_value = "60s")]
+    pub stale_timeout: Duration,
+
+    /// Kernel receive timestamps (Linux): `off`, `ns` (`SO_TIMESTAMPNS`) or
+    /// `timestamping` (`SO_TIMESTAMPING`, with NIC timestamps where enabled)
+    #[arg(long, value_parser = parse_kernel_timestamps, default_value = "timestamping")]
+    pub kernel_timestamps: KernelTimestamping,
+}
+
+impl ConnectionArgs {
+    fn apply_settings(&mut self, settings: &WebSocketSettings, matches: &ArgMatches) {
+        let fields = [
+            ("connect_timeout", &mut self.connect_timeout, settings.connect_timeout),
+            ("read_timeout", &mut self.read_timeout, settings.read_timeout),
+            ("write_timeout", &mut self.write_timeout, settings.write_timeout),
+            ("ping_interval", &mut self.ping_interval, settings.ping_interval),
+            ("stale_timeout", &mut self.stale_timeout, settings.stale_timeout),
+        ];
+        for (id, field, configured) in fields {
+            if let Some(value) = configured.filter(|_| !from_cli(matches, id)) {
+                *field = value;
+            }
+        }
+        if let Some(heartbeat) = settings.heartbeat.as_ref().filter(|_| !from_cli(matches, "heartbeat")) {
+            self.heartbeat = Some(heartbeat.clone());
+        }
+        if let Some(mode) = settings.kernel_timestamps.filter(|_| !from_cli(matches, "kernel_timestamps")) {
+            self.kernel_timestamps = mode;
+        }
+    }
+
+    /// Client settings for a connection to `exchange`.
+    pub fn websocket_config(&self, exchange: Exchange) -> WebSocketConfig {
+        let non_zero = |timeout: Duration| (!timeout.is_zero()).then_some(timeout);
+        WebSocketConfig {
+            connect_timeout: self.connect_timeout,
+            read_timeout: non_zero(self.read_timeout),
+            write_timeout: non_zero(self.write_timeout),
+            ping_interval: self.ping_interval,
+            heartbeat: self.heartbeat.clone().unwrap_or_else(|| exchange.venue().heartbeat()),
+            stale_timeout: non_zero(self.stale_timeout),
+            kernel_timestamps: self.kernel_timestamps,
+            read_clock: Some(|| {
+                let now = crate::latency::current_timestamp_hires();
+                (now.ns, now.core)
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// How latency statistics are reported.
+#[derive(Debug, Clone, Args)]
+pub struct ReportArgs {
+    /// Interval between periodic statistics
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    pub stats_interval: Duration,
+
+    /// Print individual updates slower than this, in milliseconds
+    #[arg(long, default_value_t = 100.0)]
+    pub print_threshold_ms: f64,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Stream every latency sample to this .csv or .parquet file
+    #[arg(long, env = "LATENCY_SAMPLES_PATH")]
+    pub samples: Option<PathBuf>,
+}
+
+impl ReportArgs {
+    fn apply_sinks(&mut self, sinks: &SinkConfig, matches: &ArgMatches) {
+        if let Some(samples) = sinks.samples.as_ref().filter(|_| !from_cli(matches, "samples")) {
+            self.samples = Some(samples.clone());
+        }
+        if let Some(format) = sinks.format.filter(|_| !from_cli(matches, "format")) {
+            self.format = format;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct MeasureArgs {
+    #[command(flatten)]
+    pub feed: FeedArgs,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// Address of the Prometheus `/metrics` endpoint; empty to disable
+    #[arg(long, default_value = "127.0.0.1:9898")]
+    pub metrics_addr: String,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct RecordArgs {
+    #[command(flatten)]
+    pub feed: FeedArgs,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    /// File to write the recording to (JSON lines)
+    #[arg(long, short)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ReplayArgs {
+    /// Recording written by `record`
+    #[arg(long, short)]
+    pub input: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = Exchange::Okx)]
+    pub exchange: Exchange,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BookArgs {
+    #[arg(long, value_enum, default_value_t = Exchange::Okx)]
+    pub exchange: Exchange,
+
+    /// Incremental order book channel, instead of the venue's default (OKX
+    /// `books`, Kraken `book`, which is 10 deep, `book.25` for 25 levels;
+    /// Deribit `book`, at 100ms, `book.raw` for every change; Hyperliquid
+    /// `l2Book`)
+    #[arg(long)]
+    pub channel: Option<String>,
+
+    /// Symbols to build books for, comma-separated
+    #[arg(long = "symbols", value_delimiter = ',', default_value = "BTC-USDT")]
+    pub symbols: Vec<String>,
+
+    /// Price levels per side to print
+    #[arg(long, default_value_t = 5)]
+    pub depth: usize,
+
+    /// WebSocket URL, instead of the exchange's public endpoint
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// Address of the Prometheus `/metrics` endpoint; empty to disable
+    #[arg(long, default_value = "127.0.0.1:9898")]
+    pub metrics_addr: String,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct TradesArgs {
+    #[arg(long, value_enum, default_value_t = Exchange::Okx)]
+    pub exchange: Exchange,
+
+    /// Trade channel, instead of the venue's default (OKX `trades`, Binance
+    /// `trade`, Bybit `publicTrade`, Coinbase `matches`, Kraken `trade`,
+    /// Deribit and Hyperliquid `trades`); OKX `trades-all` is unaggregated
+    #[arg(long)]
+    pub channel: Option<String>,
+
+    /// Symbols to subscribe to, comma-separated
+    #[arg(long = "symbols", value_delimiter = ',', default_value = "BTC-USDT")]
+    pub symbols: Vec<String>,
+
+    /// WebSocket URL, instead of the exchange's public endpoint
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    /// REST API base URL, instead of the exchange's (KuCoin connect tokens)
+    #[arg(long)]
+    pub rest_url: Option<String>,
+
+    /// Reference data (JSON array of instruments with `uid` and
+    /// `contract_multiplier`) for converting contract sizes to base units
+    #[arg(long)]
+    pub refdata: Option<PathBuf>,
+
+    /// Build OHLCV bars of this length from the trades
+    #[arg(long, value_parser = parse_duration)]
+    pub bar_interval: Option<Duration>,
+
+    /// Print every trade
+    #[arg(long)]
+    pub print_trades: bool,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// Address of the Prometheus `/metrics` endpoint; empty to disable
+    #[arg(long, default_value = "127.0.0.1:9898")]
+    pub metrics_addr: String,
+}
+
+impl TradesArgs {
+    pub fn channel(&self) -> &str {
+        self.channel.as_deref().unwrap_or(self.exchange.venue().trade_channel())
+    }
+
+    pub fn feed(&self) -> FeedArgs {
+        // OKX serves unaggregated trades on its business endpoint
+        let business = self.exchange == Exchange::Okx && self.channel() == "trades-all";
+        FeedArgs {
+            exchange: self.exchange,
+            channels: vec![self.channel().to_string()],
+            symbols: self.symbols.clone(),
+            ws_url: self.ws_url.clone().or_else(|| business.then(|| crate::okx::BUSINESS_WS_URL.to_string())),
+            rest_url: self.rest_url.clone(),
+            max_symbols_per_sub: DEFAULT_MAX_SYMBOLS_PER_SUB,
+            refdata: RefData::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PerpArgs {
+    #[arg(long, value_enum, default_value_t = Exchange::Okx)]
+    pub exchange: Exchange,
+
+    /// Derivatives channels to subscribe to, comma-separated
+    #[arg(
+        long = "channel",
+        value_delimiter = ',',
+        default_value = "funding-rate,mark-price,open-interest,liquidation-orders,price-limit"
+    )]
+    pub channels: Vec<String>,
+
+    /// Swap or futures instruments, comma-separated
+    #[arg(long = "symbols", value_delimiter = ',', default_value = "BTC-USDT-SWAP")]
+    pub symbols: Vec<String>,
+
+    /// WebSocket URL, instead of the exchange's public endpoint
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    /// Reference data for converting contract sizes to base units (see `trades`)
+    #[arg(long)]
+    pub refdata: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// Address of the Prometheus `/metrics` endpoint; empty to disable
+    #[arg(long, default_value = "127.0.0.1:9898")]
+    pub metrics_addr: String,
+}
+
+impl PerpArgs {
+    pub fn feed(&self) -> FeedArgs {
+        FeedArgs {
+            exchange: self.exchange,
+            channels: self.channels.clone(),
+            symbols: self.symbols.clone(),
+            ws_url: self.ws_url.clone(),
+            rest_url: None,
+            max_symbols_per_sub: DEFAULT_MAX_SYMBOLS_PER_SUB,
+            refdata: RefData::default(),
+        }
+    }
+}
+
+/// Options are discovered from reference data, which must list their
+/// family, strike, expiry and put/call.
+#[derive(Debug, Clone, Args)]
+pub struct OptionsArgs {
+    #[arg(long, value_enum, default_value_t = Exchange::Okx)]
+    pub exchange: Exchange,
+
+    /// Instrument families whose options to follow, comma-separated
+    #[arg(long = "inst-family", value_delimiter = ',', default_value = "BTC-USD")]
+    pub inst_families: Vec<String>,
+
+    /// Only options expiring on these dates (`YYMMDD`), comma-separated
+    #[arg(long = "expiries", value_delimiter = ',')]
+    pub expiries: Vec<String>,
+
+    /// Channels to subscribe to, comma-separated
+    #[arg(long = "channel", value_delimiter = ',', default_value = "opt-summary,mark-price,books5")]
+    pub channels: Vec<String>,
+
+    /// WebSocket URL, instead of the exchange's public endpoint
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    /// Reference data: our instrument list or a saved OKX
+    /// `/api/v5/public/instruments?instType=OPTION` response
+    #[arg(long)]
+    pub refdata: Option<PathBuf>,
+
+    /// Most options to put in a single subscribe request
+    #[arg(long, default_value_t = DEFAULT_MAX_SYMBOLS_PER_SUB)]
+    pub max_symbols_per_sub: usize,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    /// `--stats-interval` is also the interval between chain snapshots
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// Address of the Prometheus `/metrics` endpoint; empty to disable
+    #[arg(long, default_value = "127.0.0.1:9898")]
+    pub metrics_addr: String,
+}
+
+impl OptionsArgs {
+    pub fn feed(&self, inst_ids: Vec<String>) -> FeedArgs {
+        FeedArgs {
+            exchange: self.exchange,
+            channels: self.channels.clone(),
+            symbols: inst_ids,
+            ws_url: self.ws_url.clone(),
+            rest_url: None,
+            max_symbols_per_sub: self.max_symbols_per_sub,
+            refdata: RefData::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CompareArgs {
+    /// Venues to connect to, comma-separated
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "okx,binance,bybit")]
+    pub venues: Vec<Exchange>,
+
+    /// Instrument as BASE-QUOTE, translated to each venue's symbol
+    #[arg(long, default_value = "BTC-USDT")]
+    pub symbol: String,
+
+    /// BBO moves on different venues this close together count as the same move
+    #[arg(long, value_parser = parse_duration, default_value = "100ms")]
+    pub lead_window: Duration,
+
+    /// Interval between comparison tables
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    pub stats_interval: Duration,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    /// Address of the Prometheus `/metrics` endpoint; empty to disable
+    #[arg(long, default_value = "127.0.0.1:9898")]
+    pub metrics_addr: String,
+
+    /// WebSocket URLs of configured sources, by venue
+    #[arg(skip)]
+    pub ws_urls: Vec<(Exchange, String)>,
+
+    /// REST URLs of configured sources that set one, by venue
+    #[arg(skip)]
+    pub rest_urls: Vec<(Exchange, String)>,
+}
+
+impl CompareArgs {
+    /// Feed for one venue, subscribed to the channels the comparison uses.
+    pub fn feed(&self, exchange: Exchange) -> FeedArgs {
+        FeedArgs {
+            exchange,
+            channels: exchange.venue().compare_channels().iter().map(|channel| channel.to_string()).collect(),
+            symbols: vec![self.symbol.clone()],
+            ws_url: self.ws_urls.iter()
+                .find(|(venue, _)| *venue == exchange)
+                .map(|(_, url)| url.clone()),
+            rest_url: self.rest_urls.iter()
+                .find(|(venue, _)| *venue == exchange)
+                .map(|(_, url)| url.clone()),
+            max_symbols_per_sub: DEFAULT_MAX_SYMBOLS_PER_SUB,
+            refdata: RefData::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PrivateArgs {
+    /// Private channels to subscribe to, comma-separated
+    #[arg(long = "channel", value_delimiter = ',', default_value = "orders,positions,account")]
+    pub channels: Vec<String>,
+
+    /// WebSocket URL, instead of the OKX private endpoint
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+}
+
+impl PrivateArgs {
+    pub fn ws_url(&self) -> &str {
+        self.ws_url.as_deref().unwrap_or(crate::okx_private::PRIVATE_WS_URL)
+    }
+}
+
+/// OKX API credentials and login settings.
+#[derive(Debug, Clone, Args)]
+pub struct CredentialArgs {
+    #[arg(long, env = "OKX_API_KEY")]
+    pub api_key: Option<String>,
+
+    #[arg(long, env = "OKX_SECRET_KEY", hide_env_values = true)]
+    pub secret_key: Option<String>,
+
+    #[arg(long, env = "OKX_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+
+    /// How long to wait for the login reply
+    #[arg(long, value_parser = parse_duration, default_value = "10s")]
+    pub login_timeout: Duration,
+}
+
+impl CredentialArgs {
+    /// Credentials, if the key, secret and passphrase are all set.
+    pub fn credentials(&self) -> Option<Credentials> {
+        Some(Credentials {
+            api_key: self.api_key.clone()?,
+            secret_key: self.secret_key.clone()?,
+            passphrase: self.passphrase.clone()?,
+        })
+    }
+
+    fn apply_credentials(&mut self, configured: Option<&Credentials>, matches: &ArgMatches) {
+        let Some(configured) = configured else {
+            return;
+        };
+        let fields = [
+            ("api_key", &mut self.api_key, &configured.api_key),
+            ("secret_key", &mut self.secret_key, &configured.secret_key),
+            ("passphrase", &mut self.passphrase, &configured.passphrase),
+        ];
+        for (id, field, value) in fields {
+            if !from_cli(matches, id) {
+                *field = Some(value.clone());
+            }
+        }
+    }
+}
+
+/// Places orders that should rest on the book (post-only by default), then
+/// optionally amends and always cancels them, for `--count` cycles.
+#[derive(Debug, Clone, Args)]
+pub struct OrderLatencyArgs {
+    #[arg(long, default_value = "BTC-USDT")]
+    pub inst_id: String,
+
+    /// `cash` for spot, `cross` or `isolated` for margin and derivatives
+    #[arg(long, default_value = "cash")]
+    pub td_mode: String,
+
+    #[arg(long, default_value = "buy")]
+    pub side: String,
+
+    #[arg(long, default_value = "post_only")]
+    pub ord_type: String,
+
+    /// Limit price; keep it away from the market so orders rest
+    #[arg(long)]
+    pub px: String,
+
+    #[arg(long)]
+    pub sz: String,
+
+    /// Price to amend each order to; amending is skipped when unset
+    #[arg(long)]
+    pub amend_px: Option<String>,
+
+    /// Orders per cycle; more than one are placed with `batch-orders`
+    #[arg(long, default_value_t = 1)]
+    pub batch_size: usize,
+
+    /// Place/amend/cancel cycles to run
+    #[arg(long, default_value_t = 10)]
+    pub count: u64,
+
+    /// Pause between cycles
+    #[arg(long, value_parser = parse_duration, default_value = "1s")]
+    pub interval: Duration,
+
+    /// How long to wait for each ack
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    pub ack_timeout: Duration,
+
+    /// Use the OKX demo trading endpoint
+    #[arg(long, conflicts_with = "ws_url")]
+    pub demo: bool,
+
+    /// WebSocket URL, instead of the OKX private endpoint
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    /// Address of the Prometheus `/metrics` endpoint; empty to disable
+    #[arg(long, default_value = "127.0.0.1:9898")]
+    pub metrics_addr: String,
+}
+
+impl OrderLatencyArgs {
+    pub fn ws_url(&self) -> &str {
+        match (&self.ws_url, self.demo) {
+            (Some(url), _) => url,
+            (None, true) => crate::okx_private::DEMO_PRIVATE_WS_URL,
+            (None, false) => crate::okx_private::PRIVATE_WS_URL,
+        }
+    }
+
+    pub fn new_order(&self, cl_ord_id: String) -> NewOrder {
+        NewOrder {
+            inst_id: self.inst_id.clone(),
+            td_mode: self.td_mode.clone(),
+            side: self.side.clone(),
+            ord_type: self.ord_type.clone(),
+            px: (self.ord_type != "market").then(|| self.px.clone()),
+            sz: self.sz.clone(),
+            cl_ord_id,
+        }
+    }
+}
+
+impl BookArgs {
+    pub fn channel(&self) -> &str {
+        match &self.channel {
+            Some(channel) => channel,
+            None => self.exchange.venue().book_channel().unwrap_or_default(),
+        }
+    }
+
+    pub fn feed(&self) -> FeedArgs {
+        FeedArgs {
+            exchange: self.exchange,
+            channels: vec![self.channel().to_string()],
+            symbols: self.symbols.clone(),
+            ws_url: self.ws_url.clone(),
+            rest_url: None,
+            max_symbols_per_sub: DEFAULT_MAX_SYMBOLS_PER_SUB,
+            refdata: RefData::default(),
+        }
+    }
+}
+
+impl Command {
+    /// Fills in settings from a config file source, keeping any given on the
+    /// command line or through their environment variables. `matches` are
+    /// the matches of this command's arguments.
+    pub fn apply_config(&mut self, config: &Config, source_name: Option<&str>, matches: &ArgMatches) -> Result<(), ConfigError> {
+        let source = || config.source(source_name).map(|(_, meta)| meta);
+        let apply_metrics_addr = |metrics_addr: &mut String| {
+            if let Some(addr) = config.sinks.metrics_addr.as_ref().filter(|_| !from_cli(matches, "metrics_addr")) {
+                metrics_addr.clone_from(addr);
+            }
+        };
+
+        match self {
+            Command::Measure(args) => {
+                args.feed.apply_source(source()?, matches);
+                args.connection.apply_settings(&config.websocket, matches);
+                args.report.apply_sinks(&config.sinks, matches);
+                apply_metrics_addr(&mut args.metrics_addr);
+            }
+            Command::Record(args) => {
+                args.feed.apply_source(source()?, matches);
+                args.connection.apply_settings(&config.websocket, matches);
+            }
+            Command::Replay(args) => {
+                if !from_cli(matches, "exchange") {
+                    args.exchange = Exchange::from_str(&source()?.exchange, true)
+                        .expect("config validation only accepts known exchanges");
+                }
+                args.report.apply_sinks(&config.sinks, matches);
+            }
+            Command::Book(args) => {
+                // The book channel is specific to the command, so only the
+                // venue and symbols come from the source
+                let mut feed = args.feed();
+                feed.apply_source(source()?, matches);
+                args.exchange = feed.exchange;
+                args.symbols = feed.symbols;
+                args.ws_url = feed.ws_url;
+                args.connection.apply_settings(&config.websocket, matches);
+                args.report.apply_sinks(&config.sinks, matches);
+                apply_metrics_addr(&mut args.metrics_addr);
+            }
+            Command::Trades(args) => {
+                // Like `book`, the channel stays the command's own
+                let source = source()?;
+                let mut feed = args.feed();
+                feed.apply_source(source, matches);
+                args.exchange = feed.exchange;
+                args.symbols = feed.symbols;
+                args.ws_url = feed.ws_url;
+                args.rest_url = feed.rest_url;
+                if !source.refdata_path.is_empty() && !from_cli(matches, "refdata") {
+                    args.refdata = Some(PathBuf::from(&source.refdata_path));
+                }
+                args.connection.apply_settings(&config.websocket, matches);
+                args.report.apply_sinks(&config.sinks, matches);
+                apply_metrics_addr(&mut args.metrics_addr);
+            }
+            Command::Perp(args) => {
+                // The channels are the command's own; the source only
+                // supplies the venue, symbols and reference data
+                let source = source()?;
+                let mut feed = args.feed();
+                feed.apply_source(source, matches);
+                args.exchange = feed.exchange;
+                args.symbols = feed.symbols;
+                args.ws_url = feed.ws_url;
+                if !source.refdata_path.is_empty() && !from_cli(matches, "refdata") {
+                    args.refdata = Some(PathBuf::from(&source.refdata_path));
+                }
+                args.connection.apply_settings(&config.websocket, matches);
+                args.report.apply_sinks(&config.sinks, matches);
+                apply_metrics_addr(&mut args.metrics_addr);
+            }
+            Command::Options(args) => {
+                // The options come from the reference data, so the source's
+                // symbols are not used
+                let source = source()?;
+                let mut feed = args.feed(Vec::new());
+                feed.apply_source(source, matches);
+                args.exchange = feed.exchange;
+                args.ws_url = feed.ws_url;
+                args.max_symbols_per_sub = feed.max_symbols_per_sub;
+                if !source.refdata_path.is_empty() && !from_cli(matches, "refdata") {
+                    args.refdata = Some(PathBuf::from(&source.refdata_path));
+                }
+                args.connection.apply_settings(&config.websocket, matches);
+                args.report.apply_sinks(&config.sinks, matches);
+                apply_metrics_addr(&mut args.metrics_addr);
+            }
+            Command::Private(args) => {
+                let (name, _) = config.source(source_name)?;
+                args.credentials.apply_credentials(config.credentials.get(name), matches);
+                if let Some(format) = config.sinks.format.filter(|_| !from_cli(matches, "format")) {
+                    args.format = format;
+                }
+                args.connection.apply_settings(&config.websocket, matches);
+            }
+            Command::OrderLatency(args) => {
+                let (name, _) = config.source(source_name)?;
+                args.credentials.apply_credentials(config.credentials.get(name), matches);
+                if let Some(format) = config.sinks.format.filter(|_| !from_cli(matches, "format")) {
+                    args.format = format;
+                }
+                args.connection.apply_settings(&config.websocket, matches);
+                apply_metrics_addr(&mut args.metrics_addr);
+            }
+            Command::Compare(args) => {
+                // Every configured source is a venue to compare, unless
+                // --venues picks them
+                let sources = config.sources.values().filter_map(|meta| {
+                    let exchange = Exchange::from_str(&meta.exchange, true).ok()?;
+                    Some((exchange, meta))
+                });
+                for (exchange, meta) in sources {
+                    if !args.ws_urls.iter().any(|(venue, _)| *venue == exchange) {
+                        args.ws_urls.push((exchange, meta.ws_url.clone()));
+                        if !meta.rest_url.is_empty() {
+                            args.rest_urls.push((exchange, meta.rest_url.clone()));
+                        }
+                    }
+                }
+                if !from_cli(matches, "venues") {
+                    args.venues = args.ws_urls.iter().map(|(venue, _)| *venue).collect();
+                }
+                if let Some(format) = config.sinks.format.filter(|_| !from_cli(matches, "format")) {
+                    args.format = format;
+                }
+                args.connection.apply_settings(&config.websocket, matches);
+                apply_metrics_addr(&mut args.metrics_addr);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// True if an argument was given on the command line or via its environment
+/// variable rather than left at its default.
+fn from_cli(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.try_get_raw(id).ok().flatten().and(matches.value_source(id)),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// Parses a `--heartbeat` policy: `ping`, `respond` or `text:<message>`.
+pub fn parse_heartbeat(value: &str) -> Result<Heartbeat, String> {
+    match value {
+        "ping" => Ok(Heartbeat::Ping),
+        "respond" => Ok(Heartbeat::Respond),
+        _ => match value.strip_prefix("text:") {
+            Some(ping) if !ping.is_empty() => Ok(Heartbeat::Text { ping: ping.to_string(), pong: None }),
+            _ => Err(format!("invalid heartbeat '{}' (use ping, respond or text:<message>)", value)),
+        },
+    }
+}
+
+/// Parses a `--kernel-timestamps` mode: `off`, `ns` or `timestamping`.
+pub fn parse_kernel_timestamps(value: &str) -> Result<KernelTimestamping, String> {
+    match value {
+        "off" => Ok(KernelTimestamping::Off),
+        "ns" => Ok(KernelTimestamping::Nanoseconds),
+        "timestamping" => Ok(KernelTimestamping::Timestamping),
+        _ => Err(format!("invalid kernel timestamps '{}' (use off, ns or timestamping)", value)),
+    }
+}
+
+/// Parses `250ms`, `10s`, `2m` or a bare number of seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("invalid duration '{}'", value))?;
+
+    let seconds = match unit {
+        "us" => number / 1_000_000.0,
+        "ms" => number / 1_000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("invalid duration unit '{}' (use us, ms, s, m or h)", unit)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration '{}': {}", value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use clap::{CommandFactory, FromArgMatches};
+
+    use crate::config::SymbolFilter;
+
+    // The command and its own matches, as `main` passes them to `apply_config`
+    fn parse(args: &[&str]) -> (Command, ArgMatches) {
+        let matches = Cli::command()
+            .try_get_matches_from(std::iter::once("cex_connector").chain(args.iter().copied()))
+            .unwrap();
+        let command = Cli::from_arg_matches(&matches).unwrap().into_command();
+        let command_matches = matches.subcommand().map_or(&matches, |(_, matches)| matches).clone();
+        (command, command_matches)
+    }
+
+    fn measure(args: &[&str], config: &Config) -> MeasureArgs {
+        let (mut command, matches) = parse(args);
+        command.apply_config(config, None, &matches).unwrap();
+        match command {
+            Command::Measure(args) => args,
+            other => panic!("expected measure, got {:?}", other),
+        }
+    }
+
+    fn config() -> Config {
+        let source = SubscriptionMeta {
+            exchange: "okx".to_string(),
+            channels: vec!["tickers".to_string()],
+            ws_url: "wss://config.example/ws".to_string(),
+            rest_url: String::new(),
+            max_symbols_per_sub: 50,
+            refdata_path: String::new(),
+            symbols: vec!["ETH-USDT-SWAP".to_string()],
+            symbol_filter: SymbolFilter::default(),
+        };
+        Config {
+            sources: BTreeMap::from([("okx-swap".to_string(), source)]),
+            credentials: BTreeMap::new(),
+            sinks: SinkConfig {
+                metrics_addr: Some("0.0.0.0:9000".to_string()),
+                samples: None,
+                format: Some(OutputFormat::Json),
+            },
+            websocket: WebSocketSettings {
+                read_timeout: Some(Duration::from_secs(5)),
+                stale_timeout: Some(Duration::from_secs(90)),
+                heartbeat: Some(Heartbeat::Respond),
+                kernel_timestamps: Some(KernelTimestamping::Off),
+                ..WebSocketSettings::default()
+            },
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration(" 2m "), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("500us"), Ok(Duration::from_micros(500)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+
+        assert_eq!(parse_duration("10d"), Err("invalid duration unit 'd' (use us, ms, s, m or h)".to_string()));
+        assert_eq!(parse_duration("ms"), Err("invalid duration 'ms'".to_string()));
+        assert_eq!(parse_duration("1.2.3s"), Err("invalid duration '1.2.3s'".to_string()));
+    }
+
+    #[test]
+    fn parses_heartbeats_and_timestamp_modes() {
+        assert_eq!(parse_heartbeat("ping"), Ok(Heartbeat::Ping));
+        assert_eq!(parse_heartbeat("respond"), Ok(Heartbeat::Respond));
+        assert_eq!(
+            parse_heartbeat(r#"text:{"op":"ping"}"#),
+            Ok(Heartbeat::Text { ping: r#"{"op":"ping"}"#.to_string(), pong: None })
+        );
+        assert!(parse_heartbeat("text:").is_err());
+        assert!(parse_heartbeat("pong").is_err());
+
+        assert_eq!(parse_kernel_timestamps("off"), Ok(KernelTimestamping::Off));
+        assert_eq!(parse_kernel_timestamps("ns"), Ok(KernelTimestamping::Nanoseconds));
+        assert_eq!(parse_kernel_timestamps("timestamping"), Ok(KernelTimestamping::Timestamping));
+        assert!(parse_kernel_timestamps("hardware").is_err());
+    }
+
+    #[test]
+    fn websocket_config_follows_the_flags() {
+        let (Command::Measure(args), _) = parse(&["measure", "--read-timeout", "0"]) else {
+            panic!("expected measure");
+        };
+        let config = args.connection.websocket_config(Exchange::Okx);
+        assert_eq!(config.read_timeout, None);
+        assert_eq!(config.kernel_timestamps, KernelTimestamping::Timestamping);
+
+        let (Command::Measure(args), _) = parse(&["measure", "--kernel-timestamps", "ns"]) else {
+            panic!("expected measure");
+        };
+        assert_eq!(args.connection.websocket_config(Exchange::Okx).kernel_timestamps, KernelTimestamping::Nanoseconds);
+    }
+
+    #[test]
+    fn config_fills_in_what_the_command_line_leaves_out() {
+        let args = measure(&["measure"], &config());
+        assert_eq!(args.feed.channels, ["tickers"]);
+        assert_eq!(args.feed.symbols, ["ETH-USDT-SWAP"]);
+        assert_eq!(args.feed.ws_url(), "wss://config.example/ws");
+        assert_eq!(args.feed.max_symbols_per_sub, 50);
+        assert_eq!(args.connection.read_timeout, Duration::from_secs(5));
+        assert_eq!(args.connection.stale_timeout, Duration::from_secs(90));
+        assert_eq!(args.connection.heartbeat, Some(Heartbeat::Respond));
+        assert_eq!(args.connection.kernel_timestamps, KernelTimestamping::Off);
+        // Left at their defaults where the file is silent
+        assert_eq!(args.connection.connect_timeout, Duration::from_secs(10));
+        assert_eq!(args.report.format, OutputFormat::Json);
+        assert_eq!(args.metrics_addr, "0.0.0.0:9000");
+    }
+
+    #[test]
+    fn command_line_wins_over_config() {
+        let args = measure(
+            &[
+                "measure",
+                "--symbols", "BTC-USDT",
+                "--read-timeout", "1s",
+                "--heartbeat", "ping",
+                "--kernel-timestamps", "ns",
+                "--format", "text",
+                "--metrics-addr", "",
+            ],
+            &config(),
+        );
+        assert_eq!(args.feed.symbols, ["BTC-USDT"]);
+        assert_eq!(args.feed.channels, ["tickers"]);
+        assert_eq!(args.connection.read_timeout, Duration::from_secs(1));
+        assert_eq!(args.connection.heartbeat, Some(Heartbeat::Ping));
+        assert_eq!(args.connection.kernel_timestamps, KernelTimestamping::Nanoseconds);
+        assert_eq!(args.report.format, OutputFormat::Text);
+        assert_eq!(args.metrics_addr, "");
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::cli::Exchange;
+use crate::latency::parse_utc_time_ns;
+use crate::metrics;
+use crate::refdata;
+use crate::trades::{Side, Trade};
+use crate::venue::{invalid, number, Adapter, BookUpdate, MarketEvent, RefData, Venue};
+use crate::websocket::Heartbeat;
+
+/// Coinbase Exchange public market data feed.
+pub const PUBLIC_WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+
+// Minimal structs for Coinbase Exchange feed messages. Times are ISO 8601
+// UTC strings with microseconds, e.g. `2024-05-01T12:00:00.123456Z`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoinbaseMessage {
+    Subscriptions {
+        channels: Vec<CoinbaseChannel>,
+    },
+    /// Full `level2_batch` book, sent on subscription
+    Snapshot {
+        product_id: String,
+        bids: Vec<[String; 2]>,
+        asks: Vec<[String; 2]>,
+    },
+    /// `[side, price, size]` changes; a size of `0` removes the level
+    L2update {
+        product_id: String,
+        time: String,
+        changes: Vec<[String; 3]>,
+    },
+    Match(CoinbaseMatch),
+    /// The most recent match, sent once on subscribing to `matches`
+    LastMatch(CoinbaseMatch),
+    Ticker {
+        product_id: String,
+        sequence: u64,
+        #[serde(default)]
+        time: Option<String>,
+    },
+    Heartbeat {
+        product_id: String,
+        sequence: u64,
+        last_trade_id: u64,
+        time: String,
+    },
+    Error {
+        message: String,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseChannel {
+    pub name: String,
+    #[serde(default)]
+    pub product_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseMatch {
+    pub product_id: String,
+    pub trade_id: u64,
+    pub sequence: u64,
+    pub time: String,
+    pub price: String,
+    pub size: String,
+    /// Side of the maker order; the taker traded the other way
+    pub side: String,
+}
+
+impl CoinbaseMessage {
+    pub fn product_id(&self) -> Option<&str> {
+        match self {
+            CoinbaseMessage::Snapshot { product_id, .. }
+            | CoinbaseMessage::L2update { product_id, .. }
+            | CoinbaseMessage::Ticker { product_id, .. }
+            | CoinbaseMessage::Heartbeat { product_id, .. } => Some(product_id),
+            CoinbaseMessage::Match(trade) | CoinbaseMessage::LastMatch(trade) => Some(&trade.product_id),
+            _ => None,
+        }
+    }
+
+    /// Exchange time of the message, ns since the epoch.
+    pub fn exchange_ns(&self) -> Option<u64> {
+        match self {
+            CoinbaseMessage::L2update { time, .. } | CoinbaseMessage::Heartbeat { time, .. } => parse_utc_time_ns(time),
+            CoinbaseMessage::Match(trade) | CoinbaseMessage::LastMatch(trade) => parse_utc_time_ns(&trade.time),
+            CoinbaseMessage::Ticker { time, .. } => time.as_deref().and_then(parse_utc_time_ns),
+            _ => None,
+        }
+    }
+}
+
+/// Coinbase product id for a `BASE-QUOTE` instrument, e.g. `BTC-USD`.
+pub fn symbol(instrument: &str) -> String {
+    instrument.to_ascii_uppercase()
+}
+
+/// `subscribe` request for `channels` (e.g. `level2_batch`, `matches`) of
+/// every product.
+pub fn subscribe_request(channels: &[String], products: &[String]) -> String {
+    request("subscribe", channels, products)
+}
+
+pub fn unsubscribe_request(channels: &[String], products: &[String]) -> String {
+    request("unsubscribe", channels, products)
+}
+
+fn request(kind: &str, channels: &[String], products: &[String]) -> String {
+    let products: Vec<String> = products.iter().map(|product| symbol(product)).collect();
+    serde_json::json!({ "type": kind, "product_ids": products, "channels": channels }).to_string()
+}
+
+pub struct Coinbase;
+
+impl Venue for Coinbase {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::Ping
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        symbol(instrument)
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "matches"
+    }
+
+    // The book is built from `level2_batch`; `matches`, `ticker` and
+    // `heartbeat` carry the sequence numbers the feed is checked against
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["level2_batch", "matches", "ticker", "heartbeat"]
+    }
+
+    fn sequential_trade_ids(&self) -> bool {
+        true
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(CoinbaseAdapter::new(refdata))
+    }
+}
+
+/// Why a product's feed fell out of sync.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncError {
+    /// A message's sequence was lower than the last one seen
+    StaleSequence { last: u64, received: u64 },
+    /// Trade ids skipped, in matches or against a heartbeat's `last_trade_id`
+    TradeGap { expected: u64, received: u64 },
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::StaleSequence { last, received } => {
+                write!(f, "Sequence {} is behind {}", received, last)
+            }
+            SyncError::TradeGap { expected, received } => {
+                write!(f, "Trade id gap: expected {}, received {}", expected, received)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+// Sequence state of one product
+#[derive(Default)]
+struct Product {
+    sequence: Option<u64>,
+    trade_id: Option<u64>,
+    /// Resubscribed after a gap; book updates are dropped until the new
+    /// snapshot
+    resyncing: bool,
+}
+
+/// Coinbase messages as events, with per-product sequence checks.
+///
+/// Every product's `sequence` (on tickers, matches and heartbeats) must
+/// never go back, trade ids must be consecutive and each heartbeat's
+/// `last_trade_id` must match the last trade seen. A product that fails any
+/// of these is resubscribed on every channel for a fresh snapshot, and an
+/// `OutOfSync` event tells consumers to drop its book meanwhile.
+pub struct CoinbaseAdapter {
+    refdata: RefData,
+    /// Channels subscribed, resubscribed together on a resync
+    channels: Vec<String>,
+    products: HashMap<String, Product>,
+    requests: Vec<String>,
+}
+
+impl CoinbaseAdapter {
+    pub fn new(refdata: RefData) -> Self {
+        Self { refdata, channels: Vec::new(), products: HashMap::new(), requests: Vec::new() }
+    }
+
+    /// Checks a message against its product's sequence. Returns false for
+    /// messages that make no event: book updates while resyncing, and the
+    /// last match replayed on subscribing, which only starts the trade id
+    /// count.
+    pub fn check(&mut self, msg: &CoinbaseMessage) -> Result<bool, SyncError> {
+        let Some(product_id) = msg.product_id() else {
+            return Ok(true);
+        };
+        let product = self.products.entry(product_id.to_string()).or_default();
+
+        match msg {
+            CoinbaseMessage::Snapshot { .. } => product.resyncing = false,
+            CoinbaseMessage::L2update { .. } => return Ok(!product.resyncing),
+            CoinbaseMessage::Match(trade) | CoinbaseMessage::LastMatch(trade) => {
+                check_sequence(&mut product.sequence, trade.sequence)?;
+                if let (CoinbaseMessage::Match(_), Some(last)) = (msg, product.trade_id) {
+                    if trade.trade_id != last + 1 {
+                        return Err(SyncError::TradeGap { expected: last + 1, received: trade.trade_id });
+                    }
+                }
+                product.trade_id = Some(trade.trade_id);
+                return Ok(matches!(msg, CoinbaseMessage::Match(_)));
+            }
+            CoinbaseMessage::Ticker { sequence, .. } => check_sequence(&mut product.sequence, *sequence)?,
+            CoinbaseMessage::Heartbeat { sequence, last_trade_id, .. } => {
+                check_sequence(&mut product.sequence, *sequence)?;
+                if let Some(last) = product.trade_id.filter(|last| last != last_trade_id) {
+                    return Err(SyncError::TradeGap { expected: last, received: *last_trade_id });
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /// Forgets a product's state and queues resubscribing to all its
+    /// channels. Its book updates are dropped until the new snapshot.
+    pub fn start_resync(&mut self, product_id: &str) {
+        self.products.insert(product_id.to_string(), Product { resyncing: true, ..Product::default() });
+        let products = [product_id.to_string()];
+        self.requests.push(unsubscribe_request(&self.channels, &products));
+        self.requests.push(subscribe_request(&self.channels, &products));
+    }
+
+    fn event(&self, msg: CoinbaseMessage) -> Result<Option<MarketEvent>, serde_json::Error> {
+        let exchange_ns = msg.exchange_ns();
+        let levels = |levels: Vec<[String; 2]>| levels.into_iter().map(|[price, size]| (price, size)).collect();
+        let event = match msg {
+            CoinbaseMessage::Snapshot { product_id, bids, asks } => MarketEvent::BookSnapshot(BookUpdate {
+                symbol: product_id,
+                bids: levels(bids),
+                asks: levels(asks),
+                ..BookUpdate::default()
+            }),
+            CoinbaseMessage::L2update { product_id, changes, .. } => {
+                let (mut bids, mut asks) = (Vec::new(), Vec::new());
+                for [side, price, size] in changes {
+                    match side.as_str() {
+                        "buy" => bids.push((price, size)),
+                        "sell" => asks.push((price, size)),
+                        other => return Err(invalid("side", other)),
+                    }
+                }
+                MarketEvent::BookDelta(BookUpdate { symbol: product_id, exchange_ns, bids, asks, ..BookUpdate::default() })
+            }
+            CoinbaseMessage::Match(trade) => MarketEvent::Trade(Trade {
+                price: number("price", &trade.price)?,
+                size: number("size", &trade.size)? * refdata::contract_multiplier(&self.refdata, &trade.product_id),
+                // `side` is the maker's
+                side: match trade.side.as_str() {
+                    "buy" => Side::Sell,
+                    "sell" => Side::Buy,
+                    other => return Err(invalid("side", other)),
+                },
+                count: 1,
+                exchange_ns: exchange_ns.ok_or_else(|| invalid("time", &trade.time))?,
+                symbol: trade.product_id,
+                trade_id: trade.trade_id.to_string(),
+            }),
+            // Tickers and heartbeats are only latency samples here
+            CoinbaseMessage::Ticker { product_id, .. } | CoinbaseMessage::Heartbeat { product_id, .. } => {
+                let Some(exchange_ns) = exchange_ns else {
+                    return Ok(None);
+                };
+                MarketEvent::Heartbeat { symbol: product_id, exchange_ns }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+}
+
+impl Adapter for CoinbaseAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        for channel in channels {
+            if !self.channels.contains(channel) {
+                self.channels.push(channel.clone());
+            }
+        }
+        vec![subscribe_request(channels, symbols)]
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![unsubscribe_request(channels, symbols)]
+    }
+
+    // A product's channels share its sequence, so all of them restart
+    fn resync_requests(&mut self, _channel: &str, symbol: &str) -> Vec<String> {
+        self.start_resync(symbol);
+        self.take_requests()
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        let msg: CoinbaseMessage = serde_json::from_str(text)?;
+        match &msg {
+            CoinbaseMessage::Subscriptions { channels } => {
+                for channel in channels {
+                    tracing::info!("coinbase: subscribed to {} {}", channel.name, channel.product_ids.join(","));
+                }
+                return Ok(());
+            }
+            CoinbaseMessage::Error { message, reason } => {
+                tracing::error!("coinbase error: {} {}", message, reason.as_deref().unwrap_or_default());
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        match self.check(&msg) {
+            Ok(true) => events.extend(self.event(msg)?),
+            Ok(false) => {}
+            Err(e) => {
+                let product = msg.product_id().unwrap_or_default().to_string();
+                tracing::warn!("coinbase {} out of sync, resubscribing: {}", product, e);
+                metrics::metrics().sequence_gaps(Exchange::Coinbase.name(), &product).inc();
+                self.start_resync(&product);
+                events.push(MarketEvent::OutOfSync { symbol: product });
+            }
+        }
+        Ok(())
+    }
+
+    fn take_requests(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.requests)
+    }
+}
+
+// A ticker repeats the sequence of the match it follows, and heartbeats
+// repeat it while the product is quiet, so only going back is an error
+fn check_sequence(last: &mut Option<u64>, received: u64) -> Result<(), SyncError> {
+    if let Some(last) = last.filter(|&last| received < last) {
+        return Err(SyncError::StaleSequence { last, received });
+    }
+    *last = Some(received);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME: &str = "2024-05-01T12:00:00.123456Z";
+
+    fn adapter() -> CoinbaseAdapter {
+        let mut adapter = CoinbaseAdapter::new(RefData::default());
+        let channels = ["level2_batch", "matches", "ticker", "heartbeat"].map(String::from);
+        adapter.subscribe_requests(&channels, &["BTC-USD".to_string()]);
+        adapter
+    }
+
+    fn parse(adapter: &mut CoinbaseAdapter, text: &str) -> Vec<MarketEvent> {
+        let mut events = Vec::new();
+        adapter.parse(text, &mut events).unwrap();
+        events
+    }
+
+    fn matched(kind: &str, trade_id: u64, sequence: u64) -> String {
+        format!(
+            r#"{{"type":"{kind}","product_id":"BTC-USD","trade_id":{trade_id},"sequence":{sequence},"time":"{TIME}","price":"60000.5","size":"0.25","side":"sell"}}"#
+        )
+    }
+
+    fn ticker(sequence: u64) -> String {
+        format!(r#"{{"type":"ticker","product_id":"BTC-USD","sequence":{sequence},"time":"{TIME}"}}"#)
+    }
+
+    fn heartbeat(sequence: u64, last_trade_id: u64) -> String {
+        format!(
+            r#"{{"type":"heartbeat","product_id":"BTC-USD","sequence":{sequence},"last_trade_id":{last_trade_id},"time":"{TIME}"}}"#
+        )
+    }
+
+    fn out_of_sync(events: &[MarketEvent]) -> bool {
+        matches!(events, [MarketEvent::OutOfSync { symbol }] if symbol == "BTC-USD")
+    }
+
+    #[test]
+    fn ordered_messages_become_events() {
+        let mut adapter = adapter();
+        // The replayed last match only starts the trade id count
+        assert!(parse(&mut adapter, &matched("last_match", 10, 100)).is_empty());
+
+        let events = parse(&mut adapter, &matched("match", 11, 101));
+        let [MarketEvent::Trade(trade)] = events.as_slice() else {
+            panic!("expected a trade, got {:?}", events);
+        };
+        assert_eq!(trade.trade_id, "11");
+        assert_eq!(trade.side, Side::Buy);
+        assert_eq!(trade.price, 60000.5);
+        assert_eq!(trade.exchange_ns, parse_utc_time_ns(TIME).unwrap());
+
+        // Tickers and heartbeats repeat the last sequence
+        for text in [ticker(101), heartbeat(101, 11)] {
+            let events = parse(&mut adapter, &text);
+            assert!(matches!(events.as_slice(), [MarketEvent::Heartbeat { symbol, .. }] if symbol == "BTC-USD"));
+        }
+        assert!(adapter.take_requests().is_empty());
+    }
+
+    #[test]
+    fn regressed_sequence_resubscribes() {
+        let mut adapter = adapter();
+        parse(&mut adapter, &ticker(100));
+
+        let msg: CoinbaseMessage = serde_json::from_str(&ticker(99)).unwrap();
+        assert_eq!(adapter.check(&msg), Err(SyncError::StaleSequence { last: 100, received: 99 }));
+
+        assert!(out_of_sync(&parse(&mut adapter, &ticker(99))));
+        let channels = r#""channels":["level2_batch","matches","ticker","heartbeat"]"#;
+        let requests = adapter.take_requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains(r#""type":"unsubscribe""#) && requests[0].contains(channels));
+        assert!(requests[1].contains(r#""type":"subscribe""#) && requests[1].contains(channels));
+
+        // The product starts over, so an older sequence is accepted again
+        assert!(!parse(&mut adapter, &ticker(50)).is_empty());
+    }
+
+    #[test]
+    fn trade_id_gap_resubscribes() {
+        let mut adapter = adapter();
+        parse(&mut adapter, &matched("match", 11, 101));
+
+        let msg: CoinbaseMessage = serde_json::from_str(&matched("match", 13, 102)).unwrap();
+        assert_eq!(adapter.check(&msg), Err(SyncError::TradeGap { expected: 12, received: 13 }));
+
+        assert!(out_of_sync(&parse(&mut adapter, &matched("match", 13, 102))));
+        assert_eq!(adapter.take_requests().len(), 2);
+    }
+
+    #[test]
+    fn heartbeat_behind_the_last_trade_resubscribes() {
+        let mut adapter = adapter();
+        parse(&mut adapter, &matched("match", 11, 101));
+
+        assert!(out_of_sync(&parse(&mut adapter, &heartbeat(102, 12))));
+        assert_eq!(adapter.take_requests().len(), 2);
+    }
+
+    #[test]
+    fn drops_book_updates_until_the_resync_snapshot() {
+        let mut adapter = adapter();
+        let update = format!(
+            r#"{{"type":"l2update","product_id":"BTC-USD","time":"{TIME}","changes":[["buy","60000.0","1.5"],["sell","60001.0","0"]]}}"#
+        );
+        let snapshot = r#"{"type":"snapshot","product_id":"BTC-USD","bids":[["60000.0","1.0"]],"asks":[["60001.0","2.0"]]}"#;
+
+        adapter.start_resync("BTC-USD");
+        assert!(parse(&mut adapter, &update).is_empty());
+        assert!(matches!(parse(&mut adapter, snapshot).as_slice(), [MarketEvent::BookSnapshot(_)]));
+
+        let events = parse(&mut adapter, &update);
+        let [MarketEvent::BookDelta(delta)] = events.as_slice() else {
+            panic!("expected a delta, got {:?}", events);
+        };
+        assert_eq!(delta.bids, vec![("60000.0".to_string(), "1.5".to_string())]);
+        assert_eq!(delta.asks, vec![("60001.0".to_string(), "0".to_string())]);
+    }
+
+    #[test]
+    fn only_a_lower_sequence_is_stale() {
+        let mut last = None;
+        check_sequence(&mut last, 5).unwrap();
+        check_sequence(&mut last, 5).unwrap();
+        check_sequence(&mut last, 7).unwrap();
+        assert_eq!(check_sequence(&mut last, 6), Err(SyncError::StaleSequence { last: 7, received: 6 }));
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::cli::{BookArgs, OutputFormat};
+use crate::latency::{Stage, StageTimestamps};
+use crate::measurement::Measurement;
+use crate::orderbook::{self, BookError, OrderBook};
+use crate::venue::MarketEvent;
+use crate::{metrics, shutdown};
+
+use super::{connect, next_message, reconnect, serve_metrics, shut_down, stamp_receive, NextMessage};
+
+pub fn run(args: &BookArgs) -> anyhow::Result<()> {
+    serve_metrics(&args.metrics_addr);
+    let feed = args.feed();
+    let venue = args.exchange.venue();
+    let exchange = args.exchange.name();
+    let channel = args.channel().to_string();
+    let mut measurement = Measurement::new(exchange, &args.report)?;
+    let connected = metrics::metrics().connected(exchange);
+
+    // Keyed by the venue's symbol, as pushes name them
+    let symbols: Vec<String> = args.symbols.iter().map(|symbol| venue.symbol(symbol)).collect();
+    let mut books: HashMap<String, (OrderBook, Arc<metrics::Counter>)> = symbols.iter()
+        .map(|symbol| {
+            let failures = metrics::metrics().book_checksum_failures(exchange, symbol);
+            (symbol.clone(), (OrderBook::default(), failures))
+        })
+        .collect();
+
+    let (mut client, mut adapter) = connect(&feed, &args.connection)?;
+    connected.set(1);
+
+    println!("Building {} order books for {}. Press Ctrl+C to stop.\n", exchange, symbols.join(","));
+
+    let mut events = Vec::new();
+    let mut last_book_print = Instant::now();
+    while !shutdown::requested() {
+        match next_message(&mut client) {
+            NextMessage::Text(text) => {
+                let mut stages = StageTimestamps::default();
+                stages.mark(Stage::FrameDecoded);
+                let message_bytes = text.len() as u64;
+                measurement.message_received(message_bytes);
+
+                events.clear();
+                if let Err(e) = adapter.parse(text, &mut events) {
+                    measurement.parse_error();
+                    tracing::warn!("Failed to parse message: {}", e);
+                    continue;
+                }
+                stages.mark(Stage::JsonParsed);
+                stamp_receive(&client, &mut stages);
+                for request in adapter.take_requests() {
+                    client.send_text(&request)?;
+                }
+
+                for event in &events {
+                    let Some((book, checksum_failures)) = books.get_mut(event.symbol()) else {
+                        continue;
+                    };
+                    let (update, snapshot) = match event {
+                        MarketEvent::BookSnapshot(update) => (update, true),
+                        MarketEvent::BookDelta(update) => (update, false),
+                        MarketEvent::OutOfSync { .. } => {
+                            book.clear();
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    match update.apply(book, snapshot, venue.book_checksum()) {
+                        Ok(()) => {
+                            stages.mark(Stage::BookApplied);
+                            if let Some(exchange_ns) = update.exchange_ns {
+                                stages.set(Stage::Exchange, exchange_ns);
+                                stages.mark(Stage::EventPublished);
+                                measurement.record(&channel, &update.symbol, &stages, message_bytes);
+                            }
+                        }
+                        Err(e) => {
+                            if matches!(e, BookError::ChecksumMismatch { .. }) {
+                                checksum_failures.inc();
+                            }
+                            tracing::warn!("{} book out of sync: {}, resyncing", update.symbol, e);
+                            book.clear();
+
+                            for request in adapter.resync_requests(&channel, &update.symbol) {
+                                client.send_text(&request)?;
+                            }
+                        }
+                    }
+                }
+            }
+            NextMessage::Skip => {}
+            NextMessage::Stale => match reconnect(&feed, &args.connection) {
+                Some((new_client, new_adapter)) => {
+                    (client, adapter) = (new_client, new_adapter);
+                    // The new subscriptions start from fresh snapshots
+                    for (book, _) in books.values_mut() {
+                        book.clear();
+                    }
+                }
+                None => break,
+            },
+            NextMessage::Closed => break,
+        }
+
+        if last_book_print.elapsed() >= args.report.stats_interval {
+            for symbol in &symbols {
+                if let Some((book, _)) = books.get(symbol) {
+                    print_book(symbol, book, args.depth, args.report.format);
+                }
+            }
+            last_book_print = Instant::now();
+        }
+        measurement.maybe_print_stats();
+    }
+
+    shut_down(&mut client);
+    connected.set(0);
+    measurement.finish()?;
+
+    println!("Done.");
+    Ok(())
+}
+
+fn print_book(symbol: &str, book: &OrderBook, depth: usize, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => {
+            println!("\n=== {} order book (seq {}) ===", symbol, book.seq_id().unwrap_or_default());
+            println!("  {:>14} {:>14} | {:<14} {:<14}", "bid size", "bid", "ask", "ask size");
+            let mut bids = book.bids().take(depth);
+            let mut asks = book.asks().take(depth);
+            for _ in 0..depth {
+                let (bid, ask) = (bids.next(), asks.next());
+                if bid.is_none() && ask.is_none() {
+                    break;
+                }
+                println!(
+                    "  {:>14} {:>14} | {:<14} {:<14}",
+                    bid.map_or("", |level| &level.size),
+                    bid.map_or("", |level| &level.price),
+                    ask.map_or("", |level| &level.price),
+                    ask.map_or("", |level| &level.size),
+                );
+            }
+        }
+        OutputFormat::Json => {
+            let side = |levels: &mut dyn Iterator<Item = &orderbook::Level>| -> Vec<[String; 2]> {
+                levels.take(depth).map(|level| [level.price.clone(), level.size.clone()]).collect()
+            };
+            println!(
+                "{}",
+                serde_json::json!({
+                    "type": "book",
+                    "symbol": symbol,
+                    "seq_id": book.seq_id(),
+                    "bids": side(&mut book.bids()),
+                    "asks": side(&mut book.asks()),
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Instant;
+
+use crate::cli::{CompareArgs, ConnectionArgs, Exchange, FeedArgs};
+use crate::compare::{Comparison, FeedEvent};
+use crate::latency::{Stage, StageTimestamps};
+use crate::{metrics, shutdown};
+
+use super::{close_gracefully, connect, next_message, reconnect, serve_metrics, stamp_receive, NextMessage, EVENT_POLL_INTERVAL};
+
+pub fn run(args: &CompareArgs) -> anyhow::Result<()> {
+    anyhow::ensure!(!args.venues.is_empty(), "compare needs at least one venue");
+    serve_metrics(&args.metrics_addr);
+    let venues: Vec<(Exchange, String)> = args.venues.iter()
+        .map(|&exchange| (exchange, exchange.venue().symbol(&args.symbol)))
+        .collect();
+    let mut comparison = Comparison::new(&venues, args.lead_window, args.format);
+
+    // One blocking connection per venue, all stamping receive times with the
+    // shared high-resolution timer
+    let (events, received) = mpsc::channel();
+    let workers = args.venues.iter()
+        .enumerate()
+        .map(|(venue, &exchange)| {
+            let (feed, connection, events) = (args.feed(exchange), args.connection.clone(), events.clone());
+            thread::Builder::new()
+                .name(format!("{}-feed", exchange.name()))
+                .spawn(move || run_venue(venue, &feed, &connection, &events))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    drop(events);
+
+    println!(
+        "Comparing {} on {}. Press Ctrl+C to stop.",
+        args.symbol,
+        venues.iter().map(|(exchange, _)| exchange.name()).collect::<Vec<_>>().join(", ")
+    );
+
+    let mut last_print = Instant::now();
+    while !shutdown::requested() && comparison.any_connected() {
+        match received.recv_timeout(EVENT_POLL_INTERVAL) {
+            Ok(event) => comparison.handle(event),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if last_print.elapsed() >= args.stats_interval {
+            comparison.print();
+            last_print = Instant::now();
+        }
+    }
+
+    if shutdown::requested() {
+        println!("\nShutting down...");
+    }
+    for worker in workers {
+        if worker.join().is_err() {
+            tracing::error!("Venue connection thread panicked");
+        }
+    }
+    for event in received.try_iter() {
+        comparison.handle(event);
+    }
+    comparison.finish();
+
+    println!("Done.");
+    Ok(())
+}
+
+/// Reads one venue until shutdown or disconnect, sending its updates to the
+/// comparison.
+pub fn run_venue(venue: usize, feed: &FeedArgs, connection: &ConnectionArgs, events: &Sender<FeedEvent>) {
+    let exchange = feed.exchange.name();
+    let connected = metrics::metrics().connected(exchange);
+    let parse_errors = metrics::metrics().parse_errors(exchange);
+
+    let (mut client, mut adapter) = match connect(feed, connection) {
+        Ok(connection) => connection,
+        Err(e) => {
+            tracing::error!("Failed to connect to {}: {}", exchange, e);
+            let _ = events.send(FeedEvent::Closed { venue });
+            return;
+        }
+    };
+    connected.set(1);
+
+    while !shutdown::requested() {
+        let requests = adapter.take_requests();
+        if let Err(e) = requests.iter().try_for_each(|request| client.send_text(request)) {
+            tracing::error!("Failed to send request to {}: {}", exchange, e);
+            break;
+        }
+        let event = match next_message(&mut client) {
+            NextMessage::Text(text) => {
+                let mut stages = StageTimestamps::default();
+                stages.mark(Stage::FrameDecoded);
+                let mut events = Vec::new();
+                match adapter.parse(text, &mut events) {
+                    Ok(()) if events.is_empty() => continue,
+                    Ok(()) => {
+                        stages.mark(Stage::JsonParsed);
+                        stamp_receive(&client, &mut stages);
+                        stages.mark(Stage::EventPublished);
+                        FeedEvent::Update { venue, stages, events }
+                    }
+                    Err(e) => {
+                        parse_errors.inc();
+                        tracing::warn!("Failed to parse {} message: {}", exchange, e);
+                        FeedEvent::ParseError { venue }
+                    }
+                }
+            }
+            NextMessage::Skip => continue,
+            NextMessage::Stale => match reconnect(feed, connection) {
+                Some((new_client, new_adapter)) => {
+                    (client, adapter) = (new_client, new_adapter);
+                    continue;
+                }
+                None => break,
+            },
+            NextMessage::Closed => break,
+        };
+        if events.send(event).is_err() {
+            break;
+        }
+    }
+
+    if shutdown::requested() {
+        close_gracefully(&mut client);
+    }
+    connected.set(0);
+    let _ = events.send(FeedEvent::Closed { venue });
+}
@@ -0,0 +1,86 @@
+use crate::cli::MeasureArgs;
+use crate::latency::{Stage, StageTimestamps};
+use crate::measurement::Measurement;
+use crate::metrics;
+use crate::okx::OkxMessage;
+use crate::shutdown;
+
+use super::{connect, next_message, reconnect, serve_metrics, shut_down, stamp_receive, NextMessage};
+
+pub fn run(args: &MeasureArgs) -> anyhow::Result<()> {
+    serve_metrics(&args.metrics_addr);
+    let exchange = args.feed.exchange.name();
+    let mut measurement = Measurement::new(exchange, &args.report)?;
+    let connected = metrics::metrics().connected(exchange);
+
+    let (mut client, _) = connect(&args.feed, &args.connection)?;
+    connected.set(1);
+
+    println!(
+        "Measuring {} latency on {} for {}. Press Ctrl+C to stop.\n",
+        args.feed.channels.join(","), exchange, args.feed.symbols.join(",")
+    );
+
+    while !shutdown::requested() {
+        match next_message(&mut client) {
+            NextMessage::Text(text) => {
+                let mut stages = StageTimestamps::default();
+                stages.mark(Stage::FrameDecoded);
+                let message_bytes = text.len() as u64;
+                measurement.message_received(message_bytes);
+
+                let msg: OkxMessage = match serde_json::from_str(text) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        measurement.parse_error();
+                        tracing::warn!("Failed to parse message: {}", e);
+                        continue;
+                    }
+                };
+                stages.mark(Stage::JsonParsed);
+                stamp_receive(&client, &mut stages);
+
+                if !msg.handle_event() {
+                    stages.mark(Stage::EventPublished);
+                    measure_update(&mut measurement, &msg, &mut stages, message_bytes);
+                }
+            }
+            NextMessage::Skip => {}
+            NextMessage::Stale => match reconnect(&args.feed, &args.connection) {
+                Some((new_client, _)) => client = new_client,
+                None => break,
+            },
+            NextMessage::Closed => break,
+        }
+
+        measurement.maybe_print_stats();
+    }
+
+    shut_down(&mut client);
+    connected.set(0);
+    measurement.finish()?;
+
+    println!("Done.");
+    Ok(())
+}
+
+/// Records the latency of a data message from its exchange timestamp.
+pub fn measure_update(measurement: &mut Measurement, msg: &OkxMessage, stages: &mut StageTimestamps, message_bytes: u64) {
+    let (Some(channel), Some(data)) = (msg.channel(), &msg.data) else {
+        return;
+    };
+    let Some(entry) = data.first() else {
+        return;
+    };
+
+    match entry.ts.parse::<u64>() {
+        Ok(exchange_timestamp_ms) => {
+            stages.set(Stage::Exchange, exchange_timestamp_ms * 1_000_000);
+            measurement.record(channel, msg.inst_id().unwrap_or_default(), stages, message_bytes);
+        }
+        Err(e) => {
+            measurement.parse_error();
+            tracing::warn!("Failed to parse timestamp '{}': {}", entry.ts, e);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::cli::{ConnectionArgs, FeedArgs};
+use crate::latency::{Stage, StageTimestamps};
+use crate::venue::{Adapter, RefData, Venue};
+use crate::websocket::{WebSocketClient, WebSocketError, WebSocketMessageRef, CLOSE_NORMAL};
+use crate::{metrics, refdata, shutdown};
+
+pub mod book;
+pub mod compare;
+pub mod measure;
+pub mod options;
+pub mod order_latency;
+pub mod perp;
+pub mod private;
+pub mod record;
+pub mod trades;
+
+// How long to wait for the server to echo our close frame on shutdown
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How often `compare` checks for shutdown while no venue has sent anything
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// First and longest wait between attempts to replace a stale connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+pub enum NextMessage<'a> {
+    Text(&'a str),
+    Skip,
+    /// Nothing arrived for the stale timeout; the connection is closed
+    Stale,
+    Closed,
+}
+
+/// Connects to the exchange and subscribes to every channel/symbol pair,
+/// returning the connection with the adapter for its messages.
+pub fn connect(feed: &FeedArgs, connection: &ConnectionArgs) -> anyhow::Result<(WebSocketClient, Box<dyn Adapter>)> {
+    let venue = feed.exchange.venue();
+    let mut config = connection.websocket_config(feed.exchange);
+    let ws_url = venue.endpoint(feed, &mut config)?;
+    let read_timeout = config.read_timeout;
+    let mut client = WebSocketClient::connect_with_config(&ws_url, config)?;
+    if venue.awaits_welcome() {
+        await_welcome(&mut client, venue, connection.connect_timeout)?;
+        client.set_read_timeout(read_timeout)?;
+    }
+    let mut adapter = venue.adapter(feed.refdata.clone());
+    for symbols in feed.symbols.chunks(feed.max_symbols_per_sub.max(1)) {
+        for request in adapter.subscribe_requests(&feed.channels, symbols) {
+            client.send_text(&request)?;
+        }
+    }
+    for request in adapter.session_requests() {
+        client.send_text(&request)?;
+    }
+    Ok((client, adapter))
+}
+
+/// Replaces a stale connection with a new, resubscribed one, retrying with
+/// backoff until it succeeds. Returns `None` if shutdown is requested first.
+pub fn reconnect(feed: &FeedArgs, connection: &ConnectionArgs) -> Option<(WebSocketClient, Box<dyn Adapter>)> {
+    reconnect_with(feed.exchange.name(), || connect(feed, connection))
+}
+
+/// Like `reconnect`, setting the new connection up with `connect`.
+pub fn reconnect_with<T>(exchange: &str, connect: impl Fn() -> anyhow::Result<T>) -> Option<T> {
+    let connected = metrics::metrics().connected(exchange);
+    connected.set(0);
+
+    let mut delay = RECONNECT_DELAY;
+    while !shutdown::requested() {
+        match connect() {
+            Ok(client) => {
+                tracing::info!("Reconnected to {}", exchange);
+                metrics::metrics().reconnects(exchange).inc();
+                connected.set(1);
+                return Some(client);
+            }
+            Err(e) => tracing::warn!("Failed to reconnect to {}: {:#}, retrying in {:?}", exchange, e, delay),
+        }
+        let retry_at = Instant::now() + delay;
+        while Instant::now() < retry_at && !shutdown::requested() {
+            thread::sleep(EVENT_POLL_INTERVAL);
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+    None
+}
+
+/// Waits up to `timeout` for the venue's welcome, which it sends before
+/// accepting subscriptions.
+pub fn await_welcome(client: &mut WebSocketClient, venue: &dyn Venue, timeout: Duration) -> anyhow::Result<()> {
+    client.set_read_timeout(Some(timeout))?;
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline && !shutdown::requested() {
+        let NextMessage::Text(text) = next_message(client) else {
+            if client.is_closed() {
+                anyhow::bail!("Connection closed before the welcome");
+            }
+            continue;
+        };
+        if venue.is_welcome(text)? {
+            return Ok(());
+        }
+    }
+    anyhow::bail!("No welcome within {:?}", timeout)
+}
+
+/// Loads `--refdata`, if given.
+pub fn load_refdata(path: Option<&std::path::Path>) -> anyhow::Result<RefData> {
+    let Some(path) = path else {
+        return Ok(RefData::default());
+    };
+    let refdata = refdata::load(path).with_context(|| format!("Failed to load reference data from {}", path.display()))?;
+    Ok(Arc::new(refdata))
+}
+
+pub fn serve_metrics(addr: &str) {
+    if addr.is_empty() {
+        return;
+    }
+    if let Err(e) = metrics::serve(addr) {
+        tracing::warn!("Failed to start metrics endpoint on {}: {}", addr, e);
+    }
+}
+
+/// Reads the next message, logging control frames and ending the read loop
+/// on close or error.
+pub fn next_message(client: &mut WebSocketClient) -> NextMessage<'_> {
+    match client.read_message_ref() {
+        Ok(WebSocketMessageRef::Text(text)) => NextMessage::Text(text),
+        Ok(WebSocketMessageRef::Ping(_)) => {
+            tracing::debug!("Received ping from server");
+            NextMessage::Skip
+        }
+        Ok(WebSocketMessageRef::Pong(_)) => {
+            tracing::debug!("Received pong from server");
+            NextMessage::Skip
+        }
+        Ok(WebSocketMessageRef::Close { code, reason }) => {
+            tracing::info!("Connection closed by server - code: {:?}, reason: {}", code, reason);
+            NextMessage::Closed
+        }
+        Ok(WebSocketMessageRef::Binary(_)) => {
+            tracing::debug!("Received unexpected binary message");
+            NextMessage::Skip
+        }
+        // Signal delivered during a blocking read; the read loop checks
+        // whether it was a shutdown request
+        Err(WebSocketError::Io(e)) if e.kind() == std::io::ErrorKind::Interrupted => NextMessage::Skip,
+        // A quiet connection stays open until it goes stale
+        Err(WebSocketError::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+            tracing::debug!("No message within the read timeout");
+            NextMessage::Skip
+        }
+        Err(e @ WebSocketError::Stale(_)) => {
+            tracing::warn!("Connection went stale: {}", e);
+            NextMessage::Stale
+        }
+        Err(e) => {
+            tracing::error!("Error reading message: {}", e);
+            NextMessage::Closed
+        }
+    }
+}
+
+pub fn stamp_receive(client: &WebSocketClient, stages: &mut StageTimestamps) {
+    if let Some(kernel_rx) = client.last_rx_timestamp() {
+        stages.set(Stage::KernelReceived, kernel_rx.kernel_ns);
+    }
+    if let Some(read_ns) = client.last_read_ns() {
+        stages.set_on_core(Stage::FrameReceived, read_ns, client.last_read_core());
+    }
+}
+
+pub fn shut_down(client: &mut WebSocketClient) {
+    if shutdown::requested() {
+        println!("\nShutting down...");
+        close_gracefully(client);
+    }
+}
+
+/// Sends a close frame and waits briefly for the server's close reply.
+pub fn close_gracefully(client: &mut WebSocketClient) {
+    if let Err(e) = client.close_with_code(CLOSE_NORMAL, "client shutdown") {
+        tracing::warn!("Failed to send close frame: {}", e);
+        return;
+    }
+
+    if let Err(e) = client.set_read_timeout(Some(CLOSE_TIMEOUT)) {
+        tracing::warn!("Failed to set close timeout: {}", e);
+        return;
+    }
+    let deadline = Instant::now() + CLOSE_TIMEOUT;
+    while Instant::now() < deadline {
+        match client.read_message_ref() {
+            Ok(WebSocketMessageRef::Close { code, .. }) => {
+                tracing::info!("Server acknowledged close - code: {:?}", code);
+                return;
+            }
+            Ok(_) => {}
+            Err(WebSocketError::Io(e)) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                tracing::debug!("Connection ended during close: {}", e);
+                return;
+            }
+        }
+    }
+    tracing::debug!("No close reply from server within {:?}", CLOSE_TIMEOUT);
+}
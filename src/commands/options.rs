@@ -0,0 +1,96 @@
+use std::time::Instant;
+
+use anyhow::Context;
+
+use crate::cli::OptionsArgs;
+use crate::latency::{Stage, StageTimestamps};
+use crate::measurement::Measurement;
+use crate::options::{self, OptionChain};
+use crate::{metrics, refdata, shutdown};
+
+use super::{connect, next_message, reconnect, serve_metrics, shut_down, stamp_receive, NextMessage};
+
+pub fn run(args: &OptionsArgs) -> anyhow::Result<()> {
+    for channel in &args.channels {
+        anyhow::ensure!(
+            options::CHANNELS.contains(&channel.as_str()),
+            "unknown options channel '{}' (expected one of {})",
+            channel, options::CHANNELS.join(", ")
+        );
+    }
+    let path = args.refdata.as_ref()
+        .context("options are discovered from reference data; pass --refdata or set refdata_path")?;
+    let refdata = refdata::load(path).with_context(|| format!("Failed to load reference data from {}", path.display()))?;
+    let mut chain = OptionChain::new(&refdata, &args.inst_families, &args.expiries);
+    anyhow::ensure!(
+        !chain.is_empty(),
+        "{} lists no options of {}",
+        path.display(), args.inst_families.join(", ")
+    );
+
+    serve_metrics(&args.metrics_addr);
+    let exchange = args.exchange.name();
+    let inst_ids = chain.inst_ids();
+    let mut measurement = Measurement::new(exchange, &args.report)?;
+    let connected = metrics::metrics().connected(exchange);
+
+    let feed = args.feed(inst_ids.clone());
+    let (mut client, _) = connect(&feed, &args.connection)?;
+    connected.set(1);
+
+    println!(
+        "Following {} options of {} on {}. Press Ctrl+C to stop.\n",
+        inst_ids.len(), args.inst_families.join(","), exchange
+    );
+
+    let mut last_chain_print = Instant::now();
+    while !shutdown::requested() {
+        match next_message(&mut client) {
+            NextMessage::Text(text) => {
+                let mut stages = StageTimestamps::default();
+                stages.mark(Stage::FrameDecoded);
+                let message_bytes = text.len() as u64;
+                measurement.message_received(message_bytes);
+
+                let update = match chain.apply(text) {
+                    Ok(update) => update,
+                    Err(e) => {
+                        measurement.parse_error();
+                        tracing::warn!("Failed to parse options message: {}", e);
+                        continue;
+                    }
+                };
+                stages.mark(Stage::JsonParsed);
+                stamp_receive(&client, &mut stages);
+
+                // Labelled by family rather than option, to keep the number
+                // of metric series bounded
+                if let Some(update) = update {
+                    stages.set(Stage::Exchange, update.exchange_ns);
+                    stages.mark(Stage::EventPublished);
+                    measurement.record(&update.channel, &update.family, &stages, message_bytes);
+                }
+            }
+            NextMessage::Skip => {}
+            NextMessage::Stale => match reconnect(&feed, &args.connection) {
+                Some((new_client, _)) => client = new_client,
+                None => break,
+            },
+            NextMessage::Closed => break,
+        }
+
+        if last_chain_print.elapsed() >= args.report.stats_interval {
+            chain.print(args.report.format);
+            last_chain_print = Instant::now();
+        }
+        measurement.maybe_print_stats();
+    }
+
+    shut_down(&mut client);
+    connected.set(0);
+    chain.print(args.report.format);
+    measurement.finish()?;
+
+    println!("Done.");
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::thread;
+use std::time::Instant;
+
+use crate::cli::{Exchange, OrderLatencyArgs, OutputFormat};
+use crate::latency::current_timestamp_ns_hires;
+use crate::okx_private::{AmendOrder, CancelOrder, OrderOp, PrivateEvent};
+use crate::order_entry::{AckOutcome, OrderEntry};
+use crate::shutdown;
+use crate::websocket::WebSocketClient;
+
+use super::private::connect_private;
+use super::{next_message, reconnect_with, serve_metrics, shut_down, NextMessage, EVENT_POLL_INTERVAL};
+
+pub fn run(args: &OrderLatencyArgs) -> anyhow::Result<()> {
+    anyhow::ensure!(args.batch_size > 0, "--batch-size must be at least 1");
+    serve_metrics(&args.metrics_addr);
+    let mut client = connect_private(args.ws_url(), &args.credentials, &args.connection)?;
+    let mut entry = OrderEntry::new(Exchange::Okx.name());
+
+    println!(
+        "Placing {} {} {} {} @ {} on {} for {} cycles. Press Ctrl+C to stop.\n",
+        args.batch_size, args.side, args.ord_type, args.inst_id, args.px, args.ws_url(), args.count
+    );
+
+    let mut cycle = 0;
+    while cycle < args.count && !shutdown::requested() {
+        cycle += 1;
+        let orders: Vec<_> = (0..args.batch_size)
+            .map(|_| args.new_order(entry.next_cl_ord_id()))
+            .collect();
+        let op = if orders.len() == 1 { OrderOp::Order } else { OrderOp::BatchOrders };
+        entry.send(&mut client, op, &orders)?;
+        let acks = await_acks(&mut client, &mut entry, args)?;
+        // Orders whose acks were lost with the connection may be resting
+        let placed = if acks.reconnected {
+            orders.into_iter().map(|order| order.cl_ord_id).collect()
+        } else {
+            acks.accepted
+        };
+        if shutdown::requested() && acks.reconnected {
+            break;
+        }
+
+        if let Some(new_px) = &args.amend_px {
+            for cl_ord_id in &placed {
+                let amend = AmendOrder {
+                    inst_id: args.inst_id.clone(),
+                    cl_ord_id: cl_ord_id.clone(),
+                    new_sz: None,
+                    new_px: Some(new_px.clone()),
+                };
+                entry.send(&mut client, OrderOp::AmendOrder, &[amend])?;
+            }
+            await_acks(&mut client, &mut entry, args)?;
+        }
+
+        // Cancel even if the amend failed, so nothing is left resting
+        for cl_ord_id in placed {
+            let cancel = CancelOrder { inst_id: args.inst_id.clone(), cl_ord_id };
+            entry.send(&mut client, OrderOp::CancelOrder, &[cancel])?;
+        }
+        await_acks(&mut client, &mut entry, args)?;
+
+        let pause_until = Instant::now() + args.interval;
+        while cycle < args.count && Instant::now() < pause_until && !shutdown::requested() {
+            thread::sleep(EVENT_POLL_INTERVAL.min(pause_until.saturating_duration_since(Instant::now())));
+        }
+    }
+
+    entry.print_summary(args.format);
+    shut_down(&mut client);
+    println!("Done.");
+    Ok(())
+}
+
+/// Acks collected by `await_acks`.
+pub struct Acks {
+    /// Client order ids the exchange accepted
+    pub accepted: Vec<String>,
+    /// The connection was lost and replaced by a new one; requests still
+    /// pending then were failed
+    pub reconnected: bool,
+}
+
+/// Reads until every outstanding request is acked or has timed out. If the
+/// connection goes stale or closes, the outstanding requests are failed and
+/// a new connection is logged in to.
+pub fn await_acks(client: &mut WebSocketClient, entry: &mut OrderEntry, args: &OrderLatencyArgs) -> anyhow::Result<Acks> {
+    let mut accepted = Vec::new();
+    client.set_read_timeout(Some(args.ack_timeout))?;
+
+    while entry.has_pending() {
+        for (id, op) in entry.expire(args.ack_timeout) {
+            tracing::warn!("No ack for {} request {} within {:?}", op.name(), id, args.ack_timeout);
+        }
+
+        let event = match next_message(client) {
+            NextMessage::Text(text) => PrivateEvent::parse(text),
+            // A read timeout leaves the connection open; the next pass
+            // expires the requests that caused it
+            NextMessage::Skip => continue,
+            NextMessage::Stale | NextMessage::Closed => {
+                for (id, op) in entry.fail_pending() {
+                    tracing::warn!("Connection lost before the ack for {} request {}", op.name(), id);
+                }
+                let connect = || connect_private(args.ws_url(), &args.credentials, &args.connection);
+                if let Some(new_client) = reconnect_with(Exchange::Okx.name(), connect) {
+                    *client = new_client;
+                }
+                return Ok(Acks { accepted, reconnected: true });
+            }
+        };
+        let receive_ns = client.last_read_ns().unwrap_or_else(current_timestamp_ns_hires);
+
+        match event {
+            Ok(PrivateEvent::OrderAck(ack)) => match entry.acknowledge(&ack, receive_ns) {
+                Some(outcome) => {
+                    print_ack(&outcome, args.format);
+                    accepted.extend(outcome.accepted);
+                }
+                None => tracing::debug!("Ack for unknown request {}", ack.id),
+            },
+            Ok(PrivateEvent::Error { code, msg }) => tracing::error!("Exchange error {}: {}", code, msg),
+            Ok(event) => tracing::debug!("Ignoring {:?} while waiting for acks", event),
+            Err(e) => tracing::warn!("Failed to parse private message: {}", e),
+        }
+    }
+    Ok(Acks { accepted, reconnected: false })
+}
+
+pub fn print_ack(outcome: &AckOutcome, format: OutputFormat) {
+    let round_trip_ms = outcome.round_trip_ns as f64 / 1_000_000.0;
+    let gateway_ms = outcome.gateway_ns.map(|ns| ns as f64 / 1_000_000.0);
+    match format {
+        OutputFormat::Text => {
+            let gateway = gateway_ms.map(|ms| format!(" (gateway {:.3}ms)", ms)).unwrap_or_default();
+            println!(
+                "{:<13} {:>9.3}ms{}  accepted {}, rejected {}",
+                outcome.op.name(), round_trip_ms, gateway, outcome.accepted.len(), outcome.rejected.len()
+            );
+            for (cl_ord_id, code, msg) in &outcome.rejected {
+                println!("  rejected {}: {} {}", cl_ord_id, code, msg);
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "type": "ack",
+                "op": outcome.op.name(),
+                "round_trip_ms": round_trip_ms,
+                "gateway_ms": gateway_ms,
+                "accepted": outcome.accepted,
+                "rejected": outcome.rejected,
+            })
+        ),
+    }
+}
@@ -0,0 +1,93 @@
+use crate::cli::PerpArgs;
+use crate::derivatives::{self, DerivativeEvent};
+use crate::latency::{Stage, StageTimestamps};
+use crate::measurement::Measurement;
+use crate::venue::MarketEvent;
+use crate::{metrics, shutdown};
+
+use super::{connect, load_refdata, next_message, reconnect, serve_metrics, shut_down, stamp_receive, NextMessage};
+
+pub fn run(args: &PerpArgs) -> anyhow::Result<()> {
+    for channel in &args.channels {
+        anyhow::ensure!(
+            derivatives::CHANNELS.contains(&channel.as_str()),
+            "unknown derivatives channel '{}' (expected one of {})",
+            channel, derivatives::CHANNELS.join(", ")
+        );
+    }
+    let mut feed = args.feed();
+    feed.refdata = load_refdata(args.refdata.as_deref())?;
+    serve_metrics(&args.metrics_addr);
+    let exchange = args.exchange.name();
+    let mut measurement = Measurement::new(exchange, &args.report)?;
+    let connected = metrics::metrics().connected(exchange);
+
+    let (mut client, mut adapter) = connect(&feed, &args.connection)?;
+    connected.set(1);
+
+    println!(
+        "Following {} on {} for {}. Press Ctrl+C to stop.\n",
+        args.channels.join(","), exchange, args.symbols.join(",")
+    );
+
+    let mut events = Vec::new();
+    while !shutdown::requested() {
+        match next_message(&mut client) {
+            NextMessage::Text(text) => {
+                let mut stages = StageTimestamps::default();
+                stages.mark(Stage::FrameDecoded);
+                let message_bytes = text.len() as u64;
+                measurement.message_received(message_bytes);
+
+                events.clear();
+                if let Err(e) = adapter.parse(text, &mut events) {
+                    measurement.parse_error();
+                    tracing::warn!("Failed to parse derivatives message: {}", e);
+                    continue;
+                }
+                stages.mark(Stage::JsonParsed);
+                stamp_receive(&client, &mut stages);
+
+                // Liquidations are pushed for every instrument of a type;
+                // only the requested symbols' are kept
+                let derivatives: Vec<&DerivativeEvent> = events.iter()
+                    .filter_map(|event| match event {
+                        MarketEvent::Derivative(event) => Some(event),
+                        _ => None,
+                    })
+                    .filter(|event| match event {
+                        DerivativeEvent::Liquidation { symbol, .. } => args.symbols.contains(symbol),
+                        _ => true,
+                    })
+                    .collect();
+                for &event in &derivatives {
+                    if let DerivativeEvent::Liquidation { symbol, side, .. } = event {
+                        // Rare enough to look the counter up each time
+                        metrics::metrics().liquidations(exchange, symbol, side.name()).inc();
+                    }
+                    derivatives::print_event(event, args.report.format);
+                }
+                if let Some(first) = derivatives.first() {
+                    stages.set(Stage::Exchange, first.exchange_ns());
+                    stages.mark(Stage::EventPublished);
+                    measurement.record(first.channel(), first.symbol(), &stages, message_bytes);
+                }
+            }
+            NextMessage::Skip => {}
+            NextMessage::Stale => match reconnect(&feed, &args.connection) {
+                Some((new_client, new_adapter)) => (client, adapter) = (new_client, new_adapter),
+                None => break,
+            },
+            NextMessage::Closed => break,
+        }
+
+        measurement.maybe_print_stats();
+    }
+
+    shut_down(&mut client);
+    connected.set(0);
+    measurement.finish()?;
+
+    println!("Done.");
+    Ok(())
+}
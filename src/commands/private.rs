@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::cli::{ConnectionArgs, CredentialArgs, Exchange, OutputFormat, PrivateArgs};
+use crate::okx_private::{self, PrivateEvent};
+use crate::websocket::WebSocketClient;
+use crate::{metrics, shutdown};
+
+use super::{next_message, reconnect_with, shut_down, NextMessage};
+
+pub fn run(args: &PrivateArgs) -> anyhow::Result<()> {
+    let parse_errors = metrics::metrics().parse_errors(Exchange::Okx.name());
+    // Logs in and subscribes, on every (re)connection
+    let connect = || -> anyhow::Result<WebSocketClient> {
+        let mut client = connect_private(args.ws_url(), &args.credentials, &args.connection)?;
+        client.send_text(&okx_private::subscribe_request(&args.channels))?;
+        Ok(client)
+    };
+    let mut client = connect()?;
+
+    println!("Logged in to {}. Press Ctrl+C to stop.\n", args.ws_url());
+
+    while !shutdown::requested() {
+        match next_message(&mut client) {
+            NextMessage::Text(text) => match PrivateEvent::parse(text) {
+                Ok(event) => print_private_event(&event, args.format),
+                Err(e) => {
+                    parse_errors.inc();
+                    tracing::warn!("Failed to parse private message: {}", e);
+                }
+            },
+            NextMessage::Skip => {}
+            NextMessage::Stale => match reconnect_with(Exchange::Okx.name(), connect) {
+                Some(new_client) => client = new_client,
+                None => break,
+            },
+            NextMessage::Closed => break,
+        }
+    }
+
+    shut_down(&mut client);
+    println!("Done.");
+    Ok(())
+}
+
+/// Connects to an OKX private endpoint and logs in.
+pub fn connect_private(url: &str, credentials: &CredentialArgs, connection: &ConnectionArgs) -> anyhow::Result<WebSocketClient> {
+    let login_credentials = credentials.credentials().context(
+        "OKX credentials missing: set OKX_API_KEY, OKX_SECRET_KEY and OKX_PASSPHRASE, \
+         or a credentials table on the config source"
+    )?;
+
+    let config = connection.websocket_config(Exchange::Okx);
+    let read_timeout = config.read_timeout;
+    let mut client = WebSocketClient::connect_with_config(url, config)?;
+    login(&mut client, &login_credentials, credentials.login_timeout)?;
+    client.set_read_timeout(read_timeout)?;
+    Ok(client)
+}
+
+/// Sends a signed login request and waits up to `timeout` for its reply.
+pub fn login(client: &mut WebSocketClient, credentials: &okx_private::Credentials, timeout: Duration) -> anyhow::Result<()> {
+    client.send_text(&credentials.login_request(okx_private::unix_timestamp_secs()))?;
+    client.set_read_timeout(Some(timeout))?;
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline && !shutdown::requested() {
+        let NextMessage::Text(text) = next_message(client) else {
+            if client.is_closed() {
+                anyhow::bail!("Connection closed during login");
+            }
+            continue;
+        };
+        match PrivateEvent::parse(text) {
+            Ok(PrivateEvent::Login { conn_id }) => {
+                tracing::info!("Logged in (connection {})", conn_id.as_deref().unwrap_or("unknown"));
+                return Ok(());
+            }
+            Ok(PrivateEvent::Error { code, msg }) => anyhow::bail!("Login rejected ({}): {}", code, msg),
+            Ok(event) => tracing::debug!("Ignoring {:?} before login reply", event),
+            Err(e) => tracing::warn!("Failed to parse private message: {}", e),
+        }
+    }
+    anyhow::bail!("No login reply within {:?}", timeout)
+}
+
+pub fn print_private_event(event: &PrivateEvent, format: OutputFormat) {
+    let json = |kind: &str, data: serde_json::Value| {
+        println!("{}", serde_json::json!({ "type": kind, "data": data }));
+    };
+
+    match (event, format) {
+        (PrivateEvent::Login { .. }, _) => {}
+        (PrivateEvent::Subscribed { channel }, _) => tracing::info!("Subscribed to channel: {}", channel),
+        (PrivateEvent::Error { code, msg }, _) => tracing::error!("Exchange error {}: {}", code, msg),
+        (PrivateEvent::OrderAck(ack), _) => tracing::debug!("Ack for request {} ({})", ack.id, ack.op),
+        (PrivateEvent::Other { event, channel }, _) => {
+            tracing::debug!("Received {} on {}", event.as_deref().unwrap_or("push"), channel.as_deref().unwrap_or("-"));
+        }
+        (PrivateEvent::Orders(orders), OutputFormat::Text) => {
+            for order in orders {
+                println!(
+                    "order    {} {} {} {} @ {} {} (filled {}, ordId {}, clOrdId {})",
+                    order.inst_id, order.side, order.ord_type, order.sz, order.px, order.state,
+                    order.acc_fill_sz, order.ord_id, order.cl_ord_id
+                );
+            }
+        }
+        (PrivateEvent::Positions(positions), OutputFormat::Text) => {
+            for position in positions {
+                println!(
+                    "position {} {} {} @ {} upl {} ({} {}x)",
+                    position.inst_id, position.pos_side, position.pos, position.avg_px, position.upl,
+                    position.mgn_mode, position.lever
+                );
+            }
+        }
+        (PrivateEvent::Account(accounts), OutputFormat::Text) => {
+            for account in accounts {
+                let balances: Vec<String> = account.details.iter()
+                    .map(|balance| format!("{} {} (avail {})", balance.ccy, balance.eq, balance.avail_bal))
+                    .collect();
+                println!("account  equity {} USD: {}", account.total_eq, balances.join(", "));
+            }
+        }
+        (PrivateEvent::Orders(orders), OutputFormat::Json) => json("orders", serde_json::json!(orders)),
+        (PrivateEvent::Positions(positions), OutputFormat::Json) => json("positions", serde_json::json!(positions)),
+        (PrivateEvent::Account(accounts), OutputFormat::Json) => json("account", serde_json::json!(accounts)),
+    }
+}
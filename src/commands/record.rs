@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{RecordArgs, ReplayArgs};
+use crate::latency::{current_timestamp_ns_hires, Stage, StageTimestamps};
+use crate::measurement::Measurement;
+use crate::okx::OkxMessage;
+use crate::shutdown;
+
+use super::measure::measure_update;
+use super::{connect, next_message, reconnect, shut_down, NextMessage};
+
+/// One line of a `record` file.
+#[derive(Serialize, Deserialize)]
+struct RecordedMessage<'a> {
+    /// Local time the socket read that completed the message returned
+    receive_ns: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kernel_ns: Option<u64>,
+    #[serde(borrow)]
+    text: Cow<'a, str>,
+}
+
+pub fn record(args: &RecordArgs) -> anyhow::Result<()> {
+    let file = File::create(&args.output)
+        .with_context(|| format!("Failed to create {}", args.output.display()))?;
+    let mut output = BufWriter::new(file);
+    let (mut client, mut adapter) = connect(&args.feed, &args.connection)?;
+
+    println!("Recording to {}. Press Ctrl+C to stop.\n", args.output.display());
+
+    let mut text = String::new();
+    let mut events = Vec::new();
+    let mut recorded = 0u64;
+    while !shutdown::requested() {
+        match next_message(&mut client) {
+            NextMessage::Text(message) => {
+                text.clear();
+                text.push_str(message);
+            }
+            NextMessage::Skip => continue,
+            NextMessage::Stale => match reconnect(&args.feed, &args.connection) {
+                Some((new_client, new_adapter)) => {
+                    (client, adapter) = (new_client, new_adapter);
+                    continue;
+                }
+                None => break,
+            },
+            NextMessage::Closed => break,
+        }
+        // Parsed only for the requests the venue expects, such as answers
+        // to Deribit heartbeats
+        events.clear();
+        if adapter.parse(&text, &mut events).is_ok() {
+            for request in adapter.take_requests() {
+                client.send_text(&request)?;
+            }
+        }
+
+        let record = RecordedMessage {
+            receive_ns: client.last_read_ns().unwrap_or_else(current_timestamp_ns_hires),
+            kernel_ns: client.last_rx_timestamp().map(|rx| rx.kernel_ns),
+            text: Cow::Borrowed(&text),
+        };
+        serde_json::to_writer(&mut output, &record)?;
+        output.write_all(b"\n")?;
+        recorded += 1;
+    }
+
+    shut_down(&mut client);
+    output.flush()?;
+    output.get_ref().sync_all()?;
+
+    println!("Recorded {} messages to {}", recorded, args.output.display());
+    Ok(())
+}
+
+pub fn replay(args: &ReplayArgs) -> anyhow::Result<()> {
+    let file = File::open(&args.input)
+        .with_context(|| format!("Failed to open {}", args.input.display()))?;
+    let mut measurement = Measurement::new(args.exchange.name(), &args.report)?;
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        if shutdown::requested() {
+            break;
+        }
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recorded: RecordedMessage = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{}: invalid recording", args.input.display(), index + 1))?;
+
+        let message_bytes = recorded.text.len() as u64;
+        measurement.message_received(message_bytes);
+        let msg: OkxMessage = match serde_json::from_str(&recorded.text) {
+            Ok(m) => m,
+            Err(e) => {
+                measurement.parse_error();
+                tracing::warn!("Failed to parse message on line {}: {}", index + 1, e);
+                continue;
+            }
+        };
+        if msg.handle_event() {
+            continue;
+        }
+
+        let mut stages = StageTimestamps::default();
+        if let Some(kernel_ns) = recorded.kernel_ns {
+            stages.set(Stage::KernelReceived, kernel_ns);
+        }
+        stages.set(Stage::FrameReceived, recorded.receive_ns);
+        measure_update(&mut measurement, &msg, &mut stages, message_bytes);
+    }
+
+    measurement.finish()?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::time::Instant;
+
+use crate::cli::TradesArgs;
+use crate::latency::{current_timestamp_ns_hires, Stage, StageTimestamps};
+use crate::measurement::Measurement;
+use crate::trades::{self, TradeAggregator};
+use crate::venue::MarketEvent;
+use crate::{metrics, shutdown};
+
+use super::{connect, load_refdata, next_message, reconnect, serve_metrics, shut_down, stamp_receive, NextMessage};
+
+pub fn run(args: &TradesArgs) -> anyhow::Result<()> {
+    let mut feed = args.feed();
+    feed.refdata = load_refdata(args.refdata.as_deref())?;
+    serve_metrics(&args.metrics_addr);
+    let exchange = args.exchange.name();
+    let mut aggregator = TradeAggregator::new(args.exchange, args.bar_interval);
+    let mut measurement = Measurement::new(exchange, &args.report)?;
+    let connected = metrics::metrics().connected(exchange);
+
+    let (mut client, mut adapter) = connect(&feed, &args.connection)?;
+    connected.set(1);
+
+    println!(
+        "Following {} {} trades for {}. Press Ctrl+C to stop.\n",
+        exchange, args.channel(), args.symbols.join(",")
+    );
+
+    let mut events = Vec::new();
+    let mut last_summary_print = Instant::now();
+    while !shutdown::requested() {
+        match next_message(&mut client) {
+            NextMessage::Text(text) => {
+                let mut stages = StageTimestamps::default();
+                stages.mark(Stage::FrameDecoded);
+                let message_bytes = text.len() as u64;
+                measurement.message_received(message_bytes);
+
+                events.clear();
+                if let Err(e) = adapter.parse(text, &mut events) {
+                    measurement.parse_error();
+                    tracing::warn!("Failed to parse trade message: {}", e);
+                    continue;
+                }
+                stages.mark(Stage::JsonParsed);
+                stamp_receive(&client, &mut stages);
+                for request in adapter.take_requests() {
+                    client.send_text(&request)?;
+                }
+
+                let trades: Vec<&trades::Trade> = events.iter()
+                    .filter_map(|event| match event {
+                        MarketEvent::Trade(trade) => Some(trade),
+                        _ => None,
+                    })
+                    .collect();
+                for &trade in &trades {
+                    if args.print_trades {
+                        trades::print_trade(trade, args.report.format);
+                    }
+                    let outcome = aggregator.handle(trade);
+                    if let Some(missed) = outcome.missed {
+                        tracing::warn!("{} missed {} trades before trade {}", trade.symbol, missed, trade.trade_id);
+                    }
+                    if let Some(bar) = outcome.closed_bar {
+                        trades::print_bar(&bar, args.report.format);
+                    }
+                }
+                // One latency sample per message, from its first trade, once
+                // all of them are out
+                if let Some(first) = trades.first() {
+                    stages.set(Stage::Exchange, first.exchange_ns);
+                    stages.mark(Stage::EventPublished);
+                    measurement.record(args.channel(), &first.symbol, &stages, message_bytes);
+                }
+            }
+            NextMessage::Skip => {}
+            NextMessage::Stale => match reconnect(&feed, &args.connection) {
+                Some((new_client, new_adapter)) => (client, adapter) = (new_client, new_adapter),
+                None => break,
+            },
+            NextMessage::Closed => break,
+        }
+
+        for bar in aggregator.close_due(current_timestamp_ns_hires() / 1_000_000) {
+            trades::print_bar(&bar, args.report.format);
+        }
+        if last_summary_print.elapsed() >= args.report.stats_interval {
+            aggregator.print_summary(args.report.format);
+            last_summary_print = Instant::now();
+        }
+        measurement.maybe_print_stats();
+    }
+
+    shut_down(&mut client);
+    connected.set(0);
+    for bar in aggregator.close_all() {
+        trades::print_bar(&bar, args.report.format);
+    }
+    aggregator.print_summary(args.report.format);
+    measurement.finish()?;
+
+    println!("Done.");
+    Ok(())
+}
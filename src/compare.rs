@@ -0,0 +1,471 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cli::{Exchange, OutputFormat};
+use crate::latency::{LatencyHistogram, LatencyStats, Stage, StageTimestamps};
+use crate::metrics::{self, Counter, Histogram};
+use crate::orderbook::{Level, OrderBook};
+use crate::venue::MarketEvent;
+
+/// Best bid and ask prices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bbo {
+    pub bid: f64,
+    pub ask: f64,
+}
+
+impl Bbo {
+    fn mid(self) -> f64 {
+        (self.bid + self.ask) / 2.0
+    }
+}
+
+/// Sent from each venue's connection thread to the comparison.
+#[derive(Debug)]
+pub enum FeedEvent {
+    /// The events of one message
+    Update { venue: usize, stages: StageTimestamps, events: Vec<MarketEvent> },
+    ParseError { venue: usize },
+    Closed { venue: usize },
+}
+
+// A venue's top of book, from BBO events or the book its book events build
+#[derive(Default)]
+struct TopOfBook {
+    book: OrderBook,
+    bid: Option<f64>,
+    ask: Option<f64>,
+}
+
+impl TopOfBook {
+    fn apply(&mut self, event: &MarketEvent, exchange: Exchange) {
+        match event {
+            MarketEvent::BookSnapshot(update) | MarketEvent::BookDelta(update) => {
+                let snapshot = matches!(event, MarketEvent::BookSnapshot(_));
+                if let Err(e) = update.apply(&mut self.book, snapshot, exchange.venue().book_checksum()) {
+                    tracing::warn!("{} {} book out of sync: {}", exchange.name(), update.symbol, e);
+                    self.book.clear();
+                }
+                let best = |level: Option<&Level>| level.and_then(|level| level.price.parse().ok());
+                (self.bid, self.ask) = (best(self.book.bids().next()), best(self.book.asks().next()));
+            }
+            MarketEvent::Bbo { bid, ask, .. } => (self.bid, self.ask) = (*bid, *ask),
+            // Its book starts again from the next snapshot
+            MarketEvent::OutOfSync { .. } => *self = TopOfBook::default(),
+            _ => {}
+        }
+    }
+
+    fn bbo(&self) -> Option<Bbo> {
+        match (self.bid, self.ask) {
+            (Some(bid), Some(ask)) => Some(Bbo { bid, ask }),
+            _ => None,
+        }
+    }
+}
+
+// A mid price move in one direction, and when each venue first showed it
+struct Move {
+    up: bool,
+    arrivals: Vec<Option<u64>>,
+}
+
+impl Move {
+    fn first(&self) -> (usize, u64) {
+        self.arrivals.iter()
+            .enumerate()
+            .filter_map(|(venue, arrival)| arrival.map(|ns| (venue, ns)))
+            .min_by_key(|&(_, ns)| ns)
+            .expect("a move has at least one arrival")
+    }
+}
+
+/// Works out which venue's BBO moved first.
+///
+/// Mid price changes in the same direction on different venues within
+/// `window` of each other are treated as the same move; the venue that
+/// received it first leads. Moves only one venue showed are ignored.
+struct LeadTracker {
+    window_ns: u64,
+    venues: usize,
+    open: Vec<Move>,
+    latest_ns: u64,
+}
+
+impl LeadTracker {
+    fn new(window: Duration, venues: usize) -> Self {
+        Self {
+            window_ns: window.as_nanos() as u64,
+            venues,
+            open: Vec::new(),
+            latest_ns: 0,
+        }
+    }
+
+    /// Records a mid price move and returns the moves that can no longer
+    /// gain arrivals. Venue events arrive in per-venue order but interleaved
+    /// arbitrarily, so moves are matched on receive time, not arrival order.
+    fn record(&mut self, venue: usize, up: bool, receive_ns: u64) -> Vec<Move> {
+        let window_ns = self.window_ns;
+        let matched = self.open.iter_mut().find(|candidate| {
+            candidate.up == up
+                && candidate.arrivals[venue].is_none()
+                && candidate.first().1.abs_diff(receive_ns) <= window_ns
+        });
+        match matched {
+            Some(candidate) => candidate.arrivals[venue] = Some(receive_ns),
+            None => {
+                let mut arrivals = vec![None; self.venues];
+                arrivals[venue] = Some(receive_ns);
+                self.open.push(Move { up, arrivals });
+            }
+        }
+
+        self.latest_ns = self.latest_ns.max(receive_ns);
+        // Allow another window for slower venue threads to deliver theirs
+        let horizon = self.latest_ns.saturating_sub(2 * window_ns);
+        let (closed, open) = std::mem::take(&mut self.open).into_iter()
+            .partition(|candidate| candidate.first().1 < horizon);
+        self.open = open;
+        closed
+    }
+
+    fn close_all(&mut self) -> Vec<Move> {
+        self.open.drain(..).collect()
+    }
+}
+
+// Per-venue statistics, kept on the comparison thread
+struct VenueStats {
+    exchange: Exchange,
+    symbol: String,
+    stats: LatencyStats,
+    histogram: LatencyHistogram,
+    messages: u64,
+    parse_errors: u64,
+    bbo_changes: u64,
+    last_mid: Option<f64>,
+    top: TopOfBook,
+    /// Moves this venue showed first
+    led: u64,
+    /// How far behind the leader this venue showed the moves it followed
+    lag: LatencyHistogram,
+    lag_stats: LatencyStats,
+    connected: bool,
+    latency_metric: Arc<Histogram>,
+    messages_metric: Arc<Counter>,
+    led_metric: Arc<Counter>,
+}
+
+/// Latency and BBO lead statistics for several venues quoting the same
+/// instrument.
+pub struct Comparison {
+    venues: Vec<VenueStats>,
+    tracker: LeadTracker,
+    moves: u64,
+    format: OutputFormat,
+    started: Instant,
+}
+
+impl Comparison {
+    /// `venues` are `(exchange, venue symbol)` pairs, indexed by the `venue`
+    /// in `FeedEvent`s.
+    pub fn new(venues: &[(Exchange, String)], lead_window: Duration, format: OutputFormat) -> Self {
+        let registry = metrics::metrics();
+        Self {
+            venues: venues.iter()
+                .map(|(exchange, symbol)| VenueStats {
+                    exchange: *exchange,
+                    symbol: symbol.clone(),
+                    stats: LatencyStats::default(),
+                    histogram: LatencyHistogram::default(),
+                    messages: 0,
+                    parse_errors: 0,
+                    bbo_changes: 0,
+                    last_mid: None,
+                    top: TopOfBook::default(),
+                    led: 0,
+                    lag: LatencyHistogram::default(),
+                    lag_stats: LatencyStats::default(),
+                    connected: true,
+                    latency_metric: registry.latency(exchange.name(), "compare", symbol),
+                    messages_metric: registry.messages(exchange.name(), "compare", symbol),
+                    led_metric: registry.bbo_led(exchange.name(), symbol),
+                })
+                .collect(),
+            tracker: LeadTracker::new(lead_window, venues.len()),
+            moves: 0,
+            format,
+            started: Instant::now(),
+        }
+    }
+
+    /// True while any venue connection is still up.
+    pub fn any_connected(&self) -> bool {
+        self.venues.iter().any(|venue| venue.connected)
+    }
+
+    pub fn handle(&mut self, event: FeedEvent) {
+        match event {
+            FeedEvent::Update { venue, stages, events } => {
+                // Read time of the frame; the hand-off time when the socket
+                // does not keep it
+                let receive_ns = stages.get(Stage::FrameReceived)
+                    .or_else(|| stages.get(Stage::EventPublished));
+                let stats = &mut self.venues[venue];
+                stats.messages += 1;
+                stats.messages_metric.inc();
+
+                // One latency sample per message, from its first exchange time
+                let exchange_ns = events.iter().find_map(MarketEvent::exchange_ns);
+                if let (Some(receive_ns), Some(exchange_ns)) = (receive_ns, exchange_ns) {
+                    let latency_ns = receive_ns.saturating_sub(exchange_ns);
+                    stats.stats.add_measurement(latency_ns);
+                    stats.histogram.record(latency_ns);
+                    stats.latency_metric.observe(latency_ns);
+                }
+
+                let before = stats.top.bbo();
+                for event in &events {
+                    stats.top.apply(event, stats.exchange);
+                }
+                if let Some(bbo) = stats.top.bbo().filter(|&bbo| Some(bbo) != before) {
+                    self.bbo_changed(venue, bbo, receive_ns);
+                }
+            }
+            FeedEvent::ParseError { venue } => self.venues[venue].parse_errors += 1,
+            FeedEvent::Closed { venue } => self.venues[venue].connected = false,
+        }
+    }
+
+    fn bbo_changed(&mut self, venue: usize, bbo: Bbo, receive_ns: Option<u64>) {
+        let stats = &mut self.venues[venue];
+        stats.bbo_changes += 1;
+
+        // The first BBO has nothing to compare against; later ones only
+        // count when the mid moved
+        let previous = stats.last_mid.replace(bbo.mid());
+        let Some(previous) = previous else {
+            return;
+        };
+        if bbo.mid() == previous {
+            return;
+        }
+        // Without a receive time the move cannot be placed against other venues
+        let Some(receive_ns) = receive_ns else {
+            return;
+        };
+
+        let closed = self.tracker.record(venue, bbo.mid() > previous, receive_ns);
+        self.score(closed);
+    }
+
+    fn score(&mut self, moves: Vec<Move>) {
+        for closed in moves {
+            if closed.arrivals.iter().flatten().count() < 2 {
+                continue;
+            }
+            self.moves += 1;
+
+            let (leader, first_ns) = closed.first();
+            self.venues[leader].led += 1;
+            self.venues[leader].led_metric.inc();
+            for (venue, arrival) in closed.arrivals.iter().enumerate() {
+                if let Some(ns) = arrival.filter(|_| venue != leader) {
+                    self.venues[venue].lag.record(ns - first_ns);
+                    self.venues[venue].lag_stats.add_measurement(ns - first_ns);
+                }
+            }
+        }
+    }
+
+    pub fn print(&self) {
+        match self.format {
+            OutputFormat::Text => self.print_table(),
+            OutputFormat::Json => println!("{}", self.json("stats")),
+        }
+    }
+
+    /// Scores the remaining moves and prints the final comparison.
+    pub fn finish(mut self) {
+        let closed = self.tracker.close_all();
+        self.score(closed);
+        match self.format {
+            OutputFormat::Text => {
+                println!("\n=== Final comparison ===");
+                self.print_table();
+            }
+            OutputFormat::Json => println!("{}", self.json("final")),
+        }
+    }
+
+    fn print_table(&self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        println!(
+            "\n{:<9} {:<10} {:>9} {:>10} {:>10} {:>11} {:>10} {:>7} {:>12}",
+            "venue", "symbol", "msg/s", "p50 ms", "p99 ms", "bbo changes", "bbo first", "first%", "avg lag ms"
+        );
+        for venue in &self.venues {
+            let percentile = |q| match venue.histogram.percentile(q) {
+                Some(ns) => format!("{:.3}", ns as f64 / 1_000_000.0),
+                None => "-".to_string(),
+            };
+            let avg_lag = if venue.lag_stats.count > 0 {
+                format!("{:.3}", venue.lag_stats.average_latency_ms())
+            } else {
+                "-".to_string()
+            };
+            println!(
+                "{:<9} {:<10} {:>9.1} {:>10} {:>10} {:>11} {:>10} {:>6.1}% {:>12}{}",
+                venue.exchange.name(),
+                venue.symbol,
+                venue.messages as f64 / elapsed,
+                percentile(0.50),
+                percentile(0.99),
+                venue.bbo_changes,
+                venue.led,
+                share(venue.led, self.moves),
+                avg_lag,
+                if venue.connected { "" } else { "  (disconnected)" },
+            );
+        }
+        println!("{} BBO moves seen on more than one venue", self.moves);
+    }
+
+    fn json(&self, kind: &str) -> serde_json::Value {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let venues: Vec<serde_json::Value> = self.venues.iter()
+            .map(|venue| serde_json::json!({
+                "exchange": venue.exchange.name(),
+                "symbol": venue.symbol,
+                "connected": venue.connected,
+                "messages": venue.messages,
+                "messages_per_sec": venue.messages as f64 / elapsed,
+                "parse_errors": venue.parse_errors,
+                "latency_count": venue.stats.count,
+                "p50_ms": venue.histogram.percentile(0.50).map(|ns| ns as f64 / 1_000_000.0),
+                "p99_ms": venue.histogram.percentile(0.99).map(|ns| ns as f64 / 1_000_000.0),
+                "bbo_changes": venue.bbo_changes,
+                "bbo_first": venue.led,
+                "bbo_first_share": share(venue.led, self.moves) / 100.0,
+                "lag_p50_ms": venue.lag.percentile(0.50).map(|ns| ns as f64 / 1_000_000.0),
+            }))
+            .collect();
+
+        serde_json::json!({
+            "type": kind,
+            "uptime_secs": elapsed,
+            "bbo_moves": self.moves,
+            "venues": venues,
+        })
+    }
+}
+
+fn share(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparison() -> Comparison {
+        let venues = [(Exchange::Okx, "BTC-USDT".to_string()), (Exchange::Binance, "BTCUSDT".to_string())];
+        Comparison::new(&venues, Duration::from_nanos(1_000), OutputFormat::Text)
+    }
+
+    fn bbo(venue: usize, bid: f64, ask: f64, receive_ns: Option<u64>) -> FeedEvent {
+        let mut stages = StageTimestamps::default();
+        if let Some(ns) = receive_ns {
+            stages.set(Stage::FrameReceived, ns);
+        }
+        let event = MarketEvent::Bbo {
+            symbol: "BTC".to_string(),
+            bid: Some(bid),
+            ask: Some(ask),
+            exchange_ns: Some(100),
+        };
+        FeedEvent::Update { venue, stages, events: vec![event] }
+    }
+
+    #[test]
+    fn matches_same_direction_moves_inside_the_window() {
+        let mut tracker = LeadTracker::new(Duration::from_nanos(1_000), 3);
+        assert!(tracker.record(1, true, 10_000).is_empty());
+        assert!(tracker.record(0, true, 10_800).is_empty());
+        // Opposite direction is a different move
+        assert!(tracker.record(2, false, 10_900).is_empty());
+
+        let moves = tracker.close_all();
+        assert_eq!(moves.len(), 2);
+        assert!(moves[0].up);
+        assert_eq!(moves[0].arrivals, [Some(10_800), Some(10_000), None]);
+        assert_eq!(moves[0].first(), (1, 10_000));
+        assert!(!moves[1].up);
+        assert_eq!(moves[1].arrivals, [None, None, Some(10_900)]);
+    }
+
+    #[test]
+    fn keeps_moves_outside_the_window_apart() {
+        let mut tracker = LeadTracker::new(Duration::from_nanos(1_000), 2);
+        assert!(tracker.record(0, true, 10_000).is_empty());
+        assert!(tracker.record(1, true, 11_001).is_empty());
+        // Joins the move venue 0 has not shown yet, not its own earlier one
+        assert!(tracker.record(0, true, 11_500).is_empty());
+
+        let moves = tracker.close_all();
+        let arrivals: Vec<_> = moves.iter().map(|m| m.arrivals.clone()).collect();
+        assert_eq!(arrivals, [
+            vec![Some(10_000), None],
+            vec![Some(11_500), Some(11_001)],
+        ]);
+    }
+
+    #[test]
+    fn closes_moves_past_the_horizon() {
+        let mut tracker = LeadTracker::new(Duration::from_nanos(1_000), 2);
+        assert!(tracker.record(0, true, 10_000).is_empty());
+        // Horizon is two windows behind the latest receive time
+        assert!(tracker.record(1, false, 12_000).is_empty());
+        let closed = tracker.record(1, false, 12_001);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].arrivals, [Some(10_000), None]);
+        assert_eq!(tracker.close_all().len(), 2);
+    }
+
+    #[test]
+    fn scores_the_venue_that_moved_first() {
+        let mut comparison = comparison();
+        comparison.handle(bbo(0, 100.0, 101.0, Some(1_000)));
+        comparison.handle(bbo(1, 100.0, 101.0, Some(1_000)));
+        comparison.handle(bbo(1, 101.0, 102.0, Some(5_000)));
+        comparison.handle(bbo(0, 101.0, 102.0, Some(5_400)));
+        // Seen on one venue only
+        comparison.handle(bbo(0, 99.0, 100.0, Some(20_000)));
+
+        let closed = comparison.tracker.close_all();
+        comparison.score(closed);
+        assert_eq!(comparison.moves, 1);
+        assert_eq!((comparison.venues[0].led, comparison.venues[1].led), (0, 1));
+        assert_eq!(comparison.venues[0].lag_stats.count, 1);
+        assert_eq!(comparison.venues[0].lag_stats.total_latency_ns, 400);
+        assert_eq!(comparison.venues[1].lag_stats.count, 0);
+        assert_eq!(comparison.venues[0].stats.count, 3);
+    }
+
+    #[test]
+    fn skips_unstamped_messages() {
+        let mut comparison = comparison();
+        comparison.handle(bbo(0, 100.0, 101.0, None));
+        comparison.handle(bbo(0, 101.0, 102.0, None));
+
+        let venue = &comparison.venues[0];
+        assert_eq!((venue.messages, venue.bbo_changes, venue.stats.count), (2, 2, 0));
+        assert_eq!(venue.last_mid, Some(101.5));
+        assert!(comparison.tracker.close_all().is_empty());
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::cli::{parse_duration, parse_heartbeat, parse_kernel_timestamps, Exchange, OutputFormat};
+use crate::okx_private::Credentials;
+use crate::subscriber::SubscriptionMeta;
+use crate::timestamping::KernelTimestamping;
+use crate::websocket::Heartbeat;
+
+// Same default as the original hand-built sources
+const DEFAULT_REFDATA_DIR: &str = "data";
+const DEFAULT_MAX_SYMBOLS_PER_SUB: usize = 200;
+
+/// Error loading or validating a configuration file.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// A setting that parsed but is not usable
+    Invalid { section: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Failed to read config {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::Invalid { section, message } => write!(f, "Invalid config [{}]: {}", section, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Symbol include/exclude patterns; `*` matches any run of characters.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolFilter {
+    /// Keep only symbols matching one of these (all symbols when empty)
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl SymbolFilter {
+    pub fn matches(&self, symbol: &str) -> bool {
+        let included = self.include.is_empty()
+            || self.include.iter().any(|pattern| glob_match(pattern, symbol));
+        included && !self.exclude.iter().any(|pattern| glob_match(pattern, symbol))
+    }
+}
+
+/// Where measurements are reported.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkConfig {
+    /// Prometheus endpoint address; empty disables it
+    pub metrics_addr: Option<String>,
+    /// Latency sample export (.csv or .parquet)
+    pub samples: Option<PathBuf>,
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebSocketSettings {
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub read_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub write_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub ping_interval: Option<Duration>,
+    /// `ping`, `respond` or `text:<message>`, as `--heartbeat`
+    #[serde(default, deserialize_with = "deserialize_heartbeat")]
+    pub heartbeat: Option<Heartbeat>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub stale_timeout: Option<Duration>,
+    /// `off`, `ns` or `timestamping`, as `--kernel-timestamps`
+    #[serde(default, deserialize_with = "deserialize_kernel_timestamps")]
+    pub kernel_timestamps: Option<KernelTimestamping>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    /// Base directory for relative `refdata_path`s
+    refdata_dir: Option<String>,
+    #[serde(default)]
+    sources: BTreeMap<String, RawSource>,
+    #[serde(default)]
+    sinks: SinkConfig,
+    #[serde(default)]
+    websocket: WebSocketSettings,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSource {
+    exchange: Exchange,
+    ws_url: Option<String>,
+    #[serde(default)]
+    rest_url: String,
+    channels: Vec<String>,
+    #[serde(default)]
+    symbols: Vec<String>,
+    #[serde(default)]
+    symbol_filter: SymbolFilter,
+    max_symbols_per_sub: Option<usize>,
+    #[serde(default)]
+    refdata_path: String,
+    /// API credentials for private channels
+    credentials: Option<Credentials>,
+}
+
+/// Validated configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub sources: BTreeMap<String, SubscriptionMeta>,
+    /// Credentials of the sources that have them, by source name
+    pub credentials: BTreeMap<String, Credentials>,
+    pub sinks: SinkConfig,
+    pub websocket: WebSocketSettings,
+}
+
+impl Config {
+    /// Reads, validates and applies environment overrides to a config file.
+    ///
+    /// Overrides: `REF_DATA_PATH` replaces `refdata_dir`, `CEX_METRICS_ADDR`
+    /// the metrics address, and `CEX_<SOURCE>_WS_URL`, `CEX_<SOURCE>_REST_URL`
+    /// and `CEX_<SOURCE>_SYMBOLS` (comma-separated) a source's settings, with
+    /// the source name upper-cased and `-` replaced by `_`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let raw: RawConfig = toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        Self::validate(raw, |name| std::env::var(name).ok())
+    }
+
+    fn validate(raw: RawConfig, env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        if raw.sources.is_empty() {
+            return Err(invalid("sources", "at least one [sources.<name>] table is required"));
+        }
+
+        let refdata_dir = env("REF_DATA_PATH")
+            .or(raw.refdata_dir)
+            .unwrap_or_else(|| DEFAULT_REFDATA_DIR.to_string());
+
+        let mut credentials = BTreeMap::new();
+        let mut sources = BTreeMap::new();
+        for (name, mut source) in raw.sources {
+            if let Some(source_credentials) = source.credentials.take() {
+                if source_credentials.api_key.is_empty() || source_credentials.secret_key.is_empty() {
+                    return Err(invalid(&format!("sources.{}.credentials", name), "api_key and secret_key must not be empty"));
+                }
+                credentials.insert(name.clone(), source_credentials);
+            }
+            let meta = validate_source(&name, source, &refdata_dir, &env)?;
+            sources.insert(name, meta);
+        }
+
+        let mut sinks = raw.sinks;
+        if let Some(addr) = env("CEX_METRICS_ADDR") {
+            sinks.metrics_addr = Some(addr);
+        }
+
+        Ok(Self {
+            sources,
+            credentials,
+            sinks,
+            websocket: raw.websocket,
+        })
+    }
+
+    /// The named source, or the only source when `name` is `None`.
+    pub fn source(&self, name: Option<&str>) -> Result<(&str, &SubscriptionMeta), ConfigError> {
+        match name {
+            Some(name) => self.sources.get_key_value(name)
+                .map(|(name, meta)| (name.as_str(), meta))
+                .ok_or_else(|| invalid("sources", format!(
+                    "no source named '{}' (configured: {})", name, self.source_names()
+                ))),
+            None if self.sources.len() == 1 => {
+                let (name, meta) = self.sources.iter().next().unwrap();
+                Ok((name, meta))
+            }
+            None => Err(invalid("sources", format!(
+                "several sources configured, choose one with --source ({})", self.source_names()
+            ))),
+        }
+    }
+
+    fn source_names(&self) -> String {
+        self.sources.keys().cloned().collect::<Vec<_>>().join(", ")
+    }
+}
+
+fn validate_source(
+    name: &str,
+    source: RawSource,
+    refdata_dir: &str,
+    env: &impl Fn(&str) -> Option<String>,
+) -> Result<SubscriptionMeta, ConfigError> {
+    let section = format!("sources.{}", name);
+    let env_prefix = format!("CEX_{}_", name.to_ascii_uppercase().replace(['-', '.'], "_"));
+    let env_var = |field: &str| env(&format!("{}{}", env_prefix, field));
+
+    let ws_url = env_var("WS_URL")
+        .or(source.ws_url)
+        .unwrap_or_else(|| source.exchange.venue().public_ws_url().to_string());
+    if !(ws_url.starts_with("ws://") || ws_url.starts_with("wss://")) {
+        return Err(invalid(&section, format!("ws_url must start with ws:// or wss://, got '{}'", ws_url)));
+    }
+
+    let rest_url = env_var("REST_URL").unwrap_or(source.rest_url);
+    if !(rest_url.is_empty() || rest_url.starts_with("http://") || rest_url.starts_with("https://")) {
+        return Err(invalid(&section, format!("rest_url must start with http:// or https://, got '{}'", rest_url)));
+    }
+
+    if source.channels.is_empty() || source.channels.iter().any(|channel| channel.trim().is_empty()) {
+        return Err(invalid(&section, "channels must list at least one non-empty channel"));
+    }
+
+    let max_symbols_per_sub = source.max_symbols_per_sub.unwrap_or(DEFAULT_MAX_SYMBOLS_PER_SUB);
+    if max_symbols_per_sub == 0 {
+        return Err(invalid(&section, "max_symbols_per_sub must be at least 1"));
+    }
+
+    let configured = match env_var("SYMBOLS") {
+        Some(symbols) => symbols.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        None => source.symbols,
+    };
+    let symbols: Vec<String> = configured.iter()
+        .filter(|symbol| source.symbol_filter.matches(symbol))
+        .cloned()
+        .collect();
+    if symbols.is_empty() && source.refdata_path.is_empty() {
+        let message = if configured.is_empty() {
+            "symbols must list at least one symbol (or set refdata_path)"
+        } else {
+            "symbol_filter excludes every configured symbol"
+        };
+        return Err(invalid(&section, message));
+    }
+
+    let refdata_path = if source.refdata_path.is_empty() || Path::new(&source.refdata_path).is_absolute() {
+        source.refdata_path
+    } else {
+        format!("{}/{}", refdata_dir, source.refdata_path)
+    };
+
+    Ok(SubscriptionMeta {
+        exchange: source.exchange.name().to_string(),
+        channels: source.channels,
+        ws_url,
+        rest_url,
+        max_symbols_per_sub,
+        refdata_path,
+        symbols,
+        symbol_filter: source.symbol_filter,
+    })
+}
+
+fn invalid(section: &str, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        section: section.to_string(),
+        message: message.into(),
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_heartbeat<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Heartbeat>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_heartbeat(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_kernel_timestamps<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<KernelTimestamping>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_kernel_timestamps(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Matches `symbol` against a pattern where `*` matches any run of characters.
+fn glob_match(pattern: &str, symbol: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = symbol.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`: exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        [sources.okx-spot]
+        exchange = "okx"
+        channels = ["tickers"]
+        symbols = ["BTC-USDT", "ETH-USDT"]
+    "#;
+
+    fn load(text: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let raw: RawConfig = toml::from_str(text).unwrap();
+        Config::validate(raw, |name| {
+            env.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+        })
+    }
+
+    // Section and message of a validation error
+    fn error(text: &str) -> (String, String) {
+        match load(text, &[]) {
+            Err(ConfigError::Invalid { section, message }) => (section, message),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fills_in_source_defaults() {
+        let config = load(&format!("{}refdata_path = \"okx.json\"", SOURCE), &[]).unwrap();
+        let (name, meta) = config.source(None).unwrap();
+        assert_eq!(name, "okx-spot");
+        assert_eq!(meta.ws_url, Exchange::Okx.venue().public_ws_url());
+        assert_eq!(meta.max_symbols_per_sub, DEFAULT_MAX_SYMBOLS_PER_SUB);
+        assert_eq!(meta.refdata_path, "data/okx.json");
+        assert_eq!(meta.symbols, ["BTC-USDT", "ETH-USDT"]);
+    }
+
+    #[test]
+    fn reads_websocket_settings() {
+        let text = format!("[websocket]\nread_timeout = \"250ms\"\nkernel_timestamps = \"ns\"\n{}", SOURCE);
+        let websocket = load(&text, &[]).unwrap().websocket;
+        assert_eq!(websocket.read_timeout, Some(Duration::from_millis(250)));
+        assert_eq!(websocket.kernel_timestamps, Some(KernelTimestamping::Nanoseconds));
+
+        let invalid = format!("[websocket]\nkernel_timestamps = \"hardware\"\n{}", SOURCE);
+        assert!(toml::from_str::<RawConfig>(&invalid).is_err());
+    }
+
+    #[test]
+    fn rejects_unusable_settings() {
+        assert_eq!(error("").0, "sources");
+
+        let cases = [
+            ("ws_url = \"https://example.com\"", "ws_url must start with ws:// or wss://"),
+            ("rest_url = \"ftp://example.com\"", "rest_url must start with http:// or https://"),
+            ("max_symbols_per_sub = 0", "max_symbols_per_sub must be at least 1"),
+            ("symbol_filter = { exclude = [\"*-USDT\"] }", "symbol_filter excludes every configured symbol"),
+        ];
+        for (setting, expected) in cases {
+            let (section, message) = error(&format!("{}{}", SOURCE, setting));
+            assert_eq!(section, "sources.okx-spot");
+            assert!(message.starts_with(expected), "{}: {}", setting, message);
+        }
+
+        let no_channels = SOURCE.replace(r#"["tickers"]"#, r#"[" "]"#);
+        assert!(error(&no_channels).1.starts_with("channels must list"));
+        let no_symbols = SOURCE.replace(r#"["BTC-USDT", "ETH-USDT"]"#, "[]");
+        assert!(error(&no_symbols).1.starts_with("symbols must list at least one symbol"));
+
+        let credentials = format!("{}credentials = {{ api_key = \"key\", secret_key = \"\", passphrase = \"p\" }}", SOURCE);
+        assert_eq!(error(&credentials).0, "sources.okx-spot.credentials");
+    }
+
+    #[test]
+    fn names_the_source_to_choose() {
+        let two = format!("{}{}", SOURCE, SOURCE.replace("okx-spot", "okx-swap"));
+        let config = load(&two, &[]).unwrap();
+        assert!(config.source(None).is_err());
+        assert_eq!(config.source(Some("okx-swap")).unwrap().0, "okx-swap");
+        let Err(ConfigError::Invalid { message, .. }) = config.source(Some("kraken")) else {
+            panic!("unknown source accepted");
+        };
+        assert_eq!(message, "no source named 'kraken' (configured: okx-spot, okx-swap)");
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let text = format!(
+            "refdata_dir = \"/srv/refdata\"\n[sinks]\nmetrics_addr = \"127.0.0.1:1\"\n{}ws_url = \"wss://file.example\"\nrefdata_path = \"okx.json\"",
+            SOURCE
+        );
+        let env = [
+            ("REF_DATA_PATH", "/env/refdata"),
+            ("CEX_METRICS_ADDR", "0.0.0.0:9898"),
+            ("CEX_OKX_SPOT_WS_URL", "wss://env.example"),
+            ("CEX_OKX_SPOT_REST_URL", "https://env.example"),
+            ("CEX_OKX_SPOT_SYMBOLS", "SOL-USDT, ,XRP-USDT"),
+        ];
+        let config = load(&text, &env).unwrap();
+        let meta = &config.sources["okx-spot"];
+        assert_eq!(meta.ws_url, "wss://env.example");
+        assert_eq!(meta.rest_url, "https://env.example");
+        assert_eq!(meta.symbols, ["SOL-USDT", "XRP-USDT"]);
+        assert_eq!(meta.refdata_path, "/env/refdata/okx.json");
+        assert_eq!(config.sinks.metrics_addr.as_deref(), Some("0.0.0.0:9898"));
+
+        // Without the environment, the file wins over the defaults
+        let config = load(&text, &[]).unwrap();
+        assert_eq!(config.sources["okx-spot"].ws_url, "wss://file.example");
+        assert_eq!(config.sources["okx-spot"].refdata_path, "/srv/refdata/okx.json");
+    }
+
+    #[test]
+    fn globs_match_whole_symbols() {
+        assert!(glob_match("*", "BTC-USDT"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("BTC-*", "BTC-USDT"));
+        assert!(!glob_match("BTC-*", "ETH-BTC"));
+        assert!(glob_match("*-USDT", "ETH-USDT"));
+        assert!(!glob_match("*-USDT", "ETH-USDT-SWAP"));
+        assert!(glob_match("*-USDT-*", "ETH-USDT-SWAP"));
+        assert!(glob_match("B*C*T", "BTC-USDT"));
+        // The suffix may not overlap the prefix
+        assert!(!glob_match("BTC*TC", "BTC"));
+        assert!(glob_match("BTC-USDT", "BTC-USDT"));
+        assert!(!glob_match("BTC-USDT", "BTC-USDT-SWAP"));
+        assert!(!glob_match("BTC", "BT"));
+
+        let filter = SymbolFilter { include: vec!["*-USDT".into()], exclude: vec!["DOGE-*".into()] };
+        assert!(filter.matches("BTC-USDT"));
+        assert!(!filter.matches("DOGE-USDT"));
+        assert!(!filter.matches("BTC-USD"));
+    }
+}
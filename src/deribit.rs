@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::refdata;
+use crate::trades::{Side, Trade};
+use crate::venue::{invalid, Adapter, BookUpdate, MarketEvent, RefData, Venue};
+use crate::websocket::Heartbeat;
+
+pub const PUBLIC_WS_URL: &str = "wss://www.deribit.com/ws/api/v2";
+
+/// Seconds between server heartbeats (10 at least). Deribit closes the
+/// connection when a `test_request` goes unanswered.
+pub const HEARTBEAT_INTERVAL_S: u64 = 30;
+
+// Minimal structs for Deribit JSON-RPC 2.0 messages: replies to our
+// requests carry `id` and `result` or `error`; server notifications carry
+// `method` and `params`. Subscription data differs per channel, so it is
+// kept as JSON and converted with `data`.
+#[derive(Debug, Deserialize)]
+pub struct DeribitMessage {
+    #[serde(default)]
+    pub id: Option<u64>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub params: Option<DeribitParams>,
+    #[serde(default)]
+    pub result: Option<serde_json::Value>,
+    #[serde(default)]
+    pub error: Option<DeribitError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeribitParams {
+    /// Set on subscription notifications
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+    /// Heartbeat type: `heartbeat` or `test_request`
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeribitError {
+    pub code: i64,
+    pub message: String,
+}
+
+/// `book.{instrument}.{interval}` data.
+#[derive(Debug, Deserialize)]
+pub struct DeribitBook {
+    /// `snapshot` or `change`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// ms
+    pub timestamp: u64,
+    pub instrument_name: String,
+    pub change_id: i64,
+    /// Set on changes
+    #[serde(default)]
+    pub prev_change_id: Option<i64>,
+    /// `[action, price, amount]`, action being `new`, `change` or `delete`
+    pub bids: Vec<(String, f64, f64)>,
+    pub asks: Vec<(String, f64, f64)>,
+}
+
+/// `trades.{instrument}.{interval}` entry.
+#[derive(Debug, Deserialize)]
+pub struct DeribitTrade {
+    /// Sequential per instrument, unlike `trade_id`
+    pub trade_seq: u64,
+    /// ms
+    pub timestamp: u64,
+    pub instrument_name: String,
+    pub price: f64,
+    /// USD for inverse futures and perpetuals, the base currency otherwise
+    pub amount: f64,
+    /// Taker side, `buy` or `sell`
+    pub direction: String,
+}
+
+/// `quote.{instrument}` data. Prices are null while a side is empty.
+#[derive(Debug, Deserialize)]
+pub struct DeribitQuote {
+    /// ms
+    pub timestamp: u64,
+    pub instrument_name: String,
+    #[serde(default)]
+    pub best_bid_price: Option<f64>,
+    #[serde(default)]
+    pub best_ask_price: Option<f64>,
+}
+
+impl DeribitMessage {
+    /// Channel of a subscription notification.
+    pub fn channel(&self) -> Option<&str> {
+        self.params.as_ref().and_then(|params| params.channel.as_deref())
+    }
+
+    /// Converts the notification's `data` to the channel's type;
+    /// `T::default()` without data.
+    pub fn data<T: DeserializeOwned + Default>(&self) -> Result<T, serde_json::Error> {
+        match self.params.as_ref().and_then(|params| params.data.as_ref()) {
+            Some(data) => T::deserialize(data),
+            None => Ok(T::default()),
+        }
+    }
+}
+
+/// What `handle_rpc` made of a message.
+#[derive(Debug, PartialEq)]
+pub enum RpcOutcome {
+    /// Subscription data, for the caller to parse
+    Data,
+    /// A reply or heartbeat that needs nothing further
+    Handled,
+    /// A request to send back, answering a `test_request`
+    Reply(String),
+}
+
+/// JSON-RPC state of one connection: the last request id, and the method
+/// of every request not yet replied to, which replies are logged by.
+#[derive(Debug, Default)]
+pub struct DeribitSession {
+    last_id: u64,
+    pending: BTreeMap<u64, &'static str>,
+}
+
+impl DeribitSession {
+    /// Handles JSON-RPC traffic other than subscription data: logs replies
+    /// to our requests by method and answers `test_request` heartbeats.
+    /// Subscription notifications are recognized without parsing them.
+    pub fn handle_rpc(&mut self, text: &str) -> Result<RpcOutcome, serde_json::Error> {
+        if text.contains(r#""method":"subscription""#) {
+            return Ok(RpcOutcome::Data);
+        }
+        let msg: DeribitMessage = serde_json::from_str(text)?;
+
+        if let Some(id) = msg.id {
+            let method = self.pending.remove(&id).unwrap_or("unknown request");
+            match (&msg.error, &msg.result) {
+                (Some(error), _) => tracing::error!("deribit {} (id {}) failed: {} {}", method, id, error.code, error.message),
+                (None, _) if method == "public/test" => tracing::debug!("deribit: heartbeat answered"),
+                (None, result) => tracing::info!(
+                    "deribit: {} (id {}) {}",
+                    method, id, result.as_ref().map(|result| result.to_string()).unwrap_or_default()
+                ),
+            }
+            return Ok(RpcOutcome::Handled);
+        }
+
+        let kind = msg.params.as_ref().and_then(|params| params.kind.as_deref());
+        match (msg.method.as_deref(), kind) {
+            (Some("heartbeat"), Some("test_request")) => {
+                Ok(RpcOutcome::Reply(self.request("public/test", serde_json::json!({}))))
+            }
+            (Some("heartbeat"), _) => Ok(RpcOutcome::Handled),
+            _ => Ok(RpcOutcome::Data),
+        }
+    }
+
+    /// A JSON-RPC request with a fresh id, remembered until its reply.
+    pub fn request(&mut self, method: &'static str, params: serde_json::Value) -> String {
+        self.last_id += 1;
+        self.pending.insert(self.last_id, method);
+        serde_json::json!({ "jsonrpc": "2.0", "id": self.last_id, "method": method, "params": params }).to_string()
+    }
+
+    /// `public/subscribe` request for every channel/instrument pair.
+    pub fn subscribe_request(&mut self, channels: &[String], instruments: &[String]) -> String {
+        self.request("public/subscribe", serde_json::json!({ "channels": channel_names(channels, instruments) }))
+    }
+
+    pub fn unsubscribe_request(&mut self, channels: &[String], instruments: &[String]) -> String {
+        self.request("public/unsubscribe", serde_json::json!({ "channels": channel_names(channels, instruments) }))
+    }
+
+    pub fn set_heartbeat_request(&mut self) -> String {
+        self.request("public/set_heartbeat", serde_json::json!({ "interval": HEARTBEAT_INTERVAL_S }))
+    }
+}
+
+/// Deribit channel names. A channel is given as `<name>` or
+/// `<name>.<interval>`: `book` becomes `book.BTC-PERPETUAL.100ms` and
+/// `book.raw` becomes `book.BTC-PERPETUAL.raw`. `quote` takes no interval.
+pub fn channel_names(channels: &[String], instruments: &[String]) -> Vec<String> {
+    instruments.iter()
+        .flat_map(|instrument| {
+            let symbol = symbol(instrument);
+            channels.iter().map(move |channel| match channel.split_once('.') {
+                Some((name, interval)) => format!("{}.{}.{}", name, symbol, interval),
+                None if channel == "quote" => format!("quote.{}", symbol),
+                None => format!("{}.{}.100ms", channel, symbol),
+            })
+        })
+        .collect()
+}
+
+/// Name (e.g. `book`) of a notification's channel.
+pub fn channel_kind(channel: &str) -> &str {
+    channel.split('.').next().unwrap_or_default()
+}
+
+/// Deribit instrument name. `BASE-QUOTE` spot pairs become `BASE_QUOTE`;
+/// futures and options (`BTC-PERPETUAL`, `BTC-27DEC24`,
+/// `BTC-27DEC24-60000-C`) are used as given.
+pub fn symbol(instrument: &str) -> String {
+    let instrument = instrument.to_ascii_uppercase();
+    match instrument.split_once('-') {
+        Some((base, quote)) if quote != "PERPETUAL" && quote.bytes().all(|b| b.is_ascii_alphabetic()) => {
+            format!("{}_{}", base, quote)
+        }
+        _ => instrument,
+    }
+}
+
+pub struct Deribit;
+
+impl Venue for Deribit {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    // Deribit sends `test_request`s, answered by the read loops
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::Respond
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        symbol(instrument)
+    }
+
+    fn book_channel(&self) -> Option<&'static str> {
+        Some("book")
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "trades"
+    }
+
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["quote"]
+    }
+
+    fn sequential_trade_ids(&self) -> bool {
+        true
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(DeribitAdapter { refdata, session: DeribitSession::default(), requests: Vec::new() })
+    }
+}
+
+/// Answers heartbeats and turns book, trade and quote notifications into
+/// events.
+pub struct DeribitAdapter {
+    refdata: RefData,
+    session: DeribitSession,
+    /// Answers to `test_request`s
+    requests: Vec<String>,
+}
+
+impl Adapter for DeribitAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![self.session.subscribe_request(channels, symbols)]
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![self.session.unsubscribe_request(channels, symbols)]
+    }
+
+    fn session_requests(&mut self) -> Vec<String> {
+        vec![self.session.set_heartbeat_request()]
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        match self.session.handle_rpc(text)? {
+            RpcOutcome::Data => {}
+            RpcOutcome::Handled => return Ok(()),
+            RpcOutcome::Reply(reply) => {
+                self.requests.push(reply);
+                return Ok(());
+            }
+        }
+
+        let msg: DeribitMessage = serde_json::from_str(text)?;
+        match msg.channel().map(channel_kind) {
+            Some("book") => events.extend(msg.data::<Option<DeribitBook>>()?.map(book_event)),
+            // Trades are identified by `trade_seq`, which is sequential per
+            // instrument; Deribit's `trade_id` is not
+            Some("trades") => {
+                for trade in msg.data::<Vec<DeribitTrade>>()? {
+                    let side = match trade.direction.as_str() {
+                        "buy" => Side::Buy,
+                        "sell" => Side::Sell,
+                        other => return Err(invalid("direction", other)),
+                    };
+                    events.push(MarketEvent::Trade(Trade {
+                        price: trade.price,
+                        size: trade.amount * refdata::contract_multiplier(&self.refdata, &trade.instrument_name),
+                        count: 1,
+                        exchange_ns: trade.timestamp * 1_000_000,
+                        symbol: trade.instrument_name,
+                        trade_id: trade.trade_seq.to_string(),
+                        side,
+                    }));
+                }
+            }
+            Some("quote") => {
+                if let Some(quote) = msg.data::<Option<DeribitQuote>>()? {
+                    events.push(MarketEvent::Bbo {
+                        bid: quote.best_bid_price,
+                        ask: quote.best_ask_price,
+                        exchange_ns: Some(quote.timestamp * 1_000_000),
+                        symbol: quote.instrument_name,
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn take_requests(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.requests)
+    }
+}
+
+/// Book event of a book notification. Changes must continue from the last
+/// `change_id` applied.
+pub fn book_event(data: DeribitBook) -> MarketEvent {
+    let levels = |levels: Vec<(String, f64, f64)>| -> Vec<(String, String)> {
+        levels.into_iter()
+            .map(|(action, price, amount)| {
+                let amount = if action == "delete" { "0".to_string() } else { amount.to_string() };
+                (price.to_string(), amount)
+            })
+            .collect()
+    };
+    let update = BookUpdate {
+        exchange_ns: Some(data.timestamp * 1_000_000),
+        bids: levels(data.bids),
+        asks: levels(data.asks),
+        prev_sequence: data.prev_change_id,
+        sequence: Some(data.change_id),
+        symbol: data.instrument_name,
+        ..BookUpdate::default()
+    };
+    match data.kind.as_str() {
+        "change" => MarketEvent::BookDelta(update),
+        _ => MarketEvent::BookSnapshot(update),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_heartbeats_and_matches_replies() {
+        let mut session = DeribitSession::default();
+
+        let test_request = r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"test_request"}}"#;
+        let RpcOutcome::Reply(reply) = session.handle_rpc(test_request).unwrap() else {
+            panic!("test_request left unanswered");
+        };
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["method"], "public/test");
+        assert_eq!(session.pending.get(&1), Some(&"public/test"));
+
+        let subscribe = session.subscribe_request(&["book".to_string()], &["BTC-PERPETUAL".to_string()]);
+        assert!(subscribe.contains(r#""id":2"#));
+        assert_eq!(session.pending.get(&2), Some(&"public/subscribe"));
+
+        // Each reply settles the request with its id
+        let subscribed = r#"{"jsonrpc":"2.0","id":2,"result":["book.BTC-PERPETUAL.100ms"]}"#;
+        assert_eq!(session.handle_rpc(subscribed).unwrap(), RpcOutcome::Handled);
+        assert_eq!(session.pending.keys().collect::<Vec<_>>(), [&1]);
+        let tested = r#"{"jsonrpc":"2.0","id":1,"result":{"version":"1.2.26"}}"#;
+        assert_eq!(session.handle_rpc(tested).unwrap(), RpcOutcome::Handled);
+        assert!(session.pending.is_empty());
+
+        let heartbeat = r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"heartbeat"}}"#;
+        assert_eq!(session.handle_rpc(heartbeat).unwrap(), RpcOutcome::Handled);
+
+        let data = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"quote.BTC-PERPETUAL","data":{}}}"#;
+        assert_eq!(session.handle_rpc(data).unwrap(), RpcOutcome::Data);
+    }
+
+    #[test]
+    fn sessions_number_requests_independently() {
+        let (mut first, mut second) = (DeribitSession::default(), DeribitSession::default());
+        first.set_heartbeat_request();
+        assert!(second.set_heartbeat_request().contains(r#""id":1"#));
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+use crate::trades::Side;
+
+/// OKX channels carrying perpetual swap and futures data.
+pub const CHANNELS: &[&str] = &["funding-rate", "mark-price", "open-interest", "liquidation-orders", "price-limit"];
+
+/// A derivatives market data update in venue-independent form. Sizes are in
+/// base units (contracts times the contract multiplier).
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DerivativeEvent {
+    Funding {
+        symbol: String,
+        rate: f64,
+        /// Forecast for the next period, when the venue publishes one
+        next_rate: Option<f64>,
+        /// When `rate` is settled, ms since the epoch
+        funding_time_ms: u64,
+        next_funding_time_ms: Option<u64>,
+        exchange_ns: u64,
+    },
+    MarkPrice {
+        symbol: String,
+        price: f64,
+        exchange_ns: u64,
+    },
+    OpenInterest {
+        symbol: String,
+        contracts: f64,
+        size: f64,
+        usd: Option<f64>,
+        exchange_ns: u64,
+    },
+    Liquidation {
+        symbol: String,
+        /// Side of the liquidation order; a buy closes a short position
+        side: Side,
+        pos_side: String,
+        /// Bankruptcy price
+        price: f64,
+        size: f64,
+        exchange_ns: u64,
+    },
+    PriceLimit {
+        symbol: String,
+        /// Highest price buy orders may use; `None` while limits are off
+        buy_limit: Option<f64>,
+        sell_limit: Option<f64>,
+        exchange_ns: u64,
+    },
+}
+
+impl DerivativeEvent {
+    /// Channel the event came from.
+    pub fn channel(&self) -> &'static str {
+        match self {
+            DerivativeEvent::Funding { .. } => "funding-rate",
+            DerivativeEvent::MarkPrice { .. } => "mark-price",
+            DerivativeEvent::OpenInterest { .. } => "open-interest",
+            DerivativeEvent::Liquidation { .. } => "liquidation-orders",
+            DerivativeEvent::PriceLimit { .. } => "price-limit",
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            DerivativeEvent::Funding { symbol, .. }
+            | DerivativeEvent::MarkPrice { symbol, .. }
+            | DerivativeEvent::OpenInterest { symbol, .. }
+            | DerivativeEvent::Liquidation { symbol, .. }
+            | DerivativeEvent::PriceLimit { symbol, .. } => symbol,
+        }
+    }
+
+    pub fn exchange_ns(&self) -> u64 {
+        match self {
+            DerivativeEvent::Funding { exchange_ns, .. }
+            | DerivativeEvent::MarkPrice { exchange_ns, .. }
+            | DerivativeEvent::OpenInterest { exchange_ns, .. }
+            | DerivativeEvent::Liquidation { exchange_ns, .. }
+            | DerivativeEvent::PriceLimit { exchange_ns, .. } => *exchange_ns,
+        }
+    }
+}
+
+pub fn print_event(event: &DerivativeEvent, format: OutputFormat) {
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::json!(event));
+        return;
+    }
+
+    let optional = |value: Option<f64>| value.map_or("-".to_string(), |value| value.to_string());
+    match event {
+        DerivativeEvent::Funding { symbol, rate, next_rate, funding_time_ms, .. } => println!(
+            "funding       {:<22} {:+.6}% (next {}) settles at {}",
+            symbol, rate * 100.0, next_rate.map_or("-".to_string(), |rate| format!("{:+.6}%", rate * 100.0)), funding_time_ms
+        ),
+        DerivativeEvent::MarkPrice { symbol, price, .. } => println!("mark          {:<22} {}", symbol, price),
+        DerivativeEvent::OpenInterest { symbol, contracts, size, usd, .. } => println!(
+            "open interest {:<22} {} contracts ({} base, {} USD)",
+            symbol, contracts, size, optional(*usd)
+        ),
+        DerivativeEvent::Liquidation { symbol, side, pos_side, price, size, .. } => println!(
+            "liquidation   {:<22} {} {} @ {} ({} position)",
+            symbol, side.name(), size, price, pos_side
+        ),
+        DerivativeEvent::PriceLimit { symbol, buy_limit, sell_limit, .. } => println!(
+            "price limit   {:<22} buy <= {}, sell >= {}",
+            symbol, optional(*buy_limit), optional(*sell_limit)
+        ),
+    }
+}
@@ -0,0 +1,380 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+// Samples queued between the read loop and the writer thread
+const CHANNEL_CAPACITY: usize = 64 * 1024;
+const CSV_BUFFER_SIZE: usize = 256 * 1024;
+#[cfg(feature = "parquet")]
+const PARQUET_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+const CSV_HEADER: &str = "receive_ns,exchange_ns,latency_ns,channel,symbol,message_bytes";
+
+/// One latency measurement as streamed to the export file.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// Local receive time (ns since the Unix epoch)
+    pub receive_ns: u64,
+    /// Exchange timestamp of the update (ns since the Unix epoch)
+    pub exchange_ns: u64,
+    pub latency_ns: u64,
+    pub channel: Arc<str>,
+    pub symbol: Arc<str>,
+    pub message_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl ExportFormat {
+    /// Picks the format from the file extension: `.parquet` (with the
+    /// `parquet` feature), anything else is written as CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            #[cfg(feature = "parquet")]
+            Some("parquet") => ExportFormat::Parquet,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExportSummary {
+    pub written: u64,
+    /// Samples dropped because the writer thread fell behind
+    pub dropped: u64,
+}
+
+/// Streams samples to a file from a background thread.
+///
+/// `record` never blocks or allocates: samples go through a bounded channel
+/// and are dropped (and counted) if the writer falls behind. Call `finish`
+/// on shutdown to flush buffered samples and close the file.
+pub struct SampleExporter {
+    sender: Option<SyncSender<Sample>>,
+    writer: Option<JoinHandle<std::io::Result<u64>>>,
+    dropped: u64,
+}
+
+impl SampleExporter {
+    pub fn create(path: &Path, format: ExportFormat) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+        let sink: Box<dyn SampleSink + Send> = match format {
+            ExportFormat::Csv => Box::new(CsvSink::new(file)?),
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => Box::new(parquet_sink::ParquetSink::new(file)?),
+        };
+
+        let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+        let writer = std::thread::Builder::new()
+            .name("sample-export".to_string())
+            .spawn(move || write_samples(receiver, sink))?;
+
+        tracing::info!("Exporting latency samples to {} ({:?})", path.display(), format);
+        Ok(Self {
+            sender: Some(sender),
+            writer: Some(writer),
+            dropped: 0,
+        })
+    }
+
+    pub fn record(&mut self, sample: Sample) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        match sender.try_send(sample) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => self.dropped += 1,
+            Err(TrySendError::Disconnected(_)) => {
+                // Writer thread failed; its error is reported by `finish`
+                self.sender = None;
+            }
+        }
+    }
+
+    /// Flushes all queued samples and closes the file.
+    pub fn finish(mut self) -> std::io::Result<ExportSummary> {
+        // Closing the channel ends the writer loop
+        self.sender = None;
+        let written = match self.writer.take() {
+            Some(writer) => writer.join()
+                .map_err(|_| std::io::Error::other("sample export thread panicked"))??,
+            None => 0,
+        };
+
+        Ok(ExportSummary {
+            written,
+            dropped: self.dropped,
+        })
+    }
+}
+
+fn write_samples(receiver: Receiver<Sample>, mut sink: Box<dyn SampleSink + Send>) -> std::io::Result<u64> {
+    let mut written = 0;
+    for sample in receiver {
+        sink.write(&sample)?;
+        written += 1;
+    }
+    sink.finish()?;
+    Ok(written)
+}
+
+trait SampleSink {
+    fn write(&mut self, sample: &Sample) -> std::io::Result<()>;
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+}
+
+struct CsvSink {
+    writer: BufWriter<File>,
+}
+
+impl CsvSink {
+    fn new(file: File) -> std::io::Result<Self> {
+        let mut writer = BufWriter::with_capacity(CSV_BUFFER_SIZE, file);
+        writeln!(writer, "{}", CSV_HEADER)?;
+        Ok(Self { writer })
+    }
+}
+
+impl SampleSink for CsvSink {
+    fn write(&mut self, sample: &Sample) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{}",
+            sample.receive_ns,
+            sample.exchange_ns,
+            sample.latency_ns,
+            CsvField(&sample.channel),
+            CsvField(&sample.symbol),
+            sample.message_bytes,
+        )
+    }
+
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()
+    }
+}
+
+/// Writes a string field, quoting it only when it contains CSV metacharacters.
+struct CsvField<'a>(&'a str);
+
+impl std::fmt::Display for CsvField<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.contains([',', '"', '\n', '\r']) {
+            write!(f, "\"{}\"", self.0.replace('"', "\"\""))
+        } else {
+            f.write_str(self.0)
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_sink {
+    use std::fs::File;
+    use std::sync::Arc;
+
+    use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use super::{Sample, SampleSink, PARQUET_ROW_GROUP_SIZE};
+
+    const SCHEMA: &str = "
+        message latency_sample {
+            REQUIRED INT64 receive_ns (INTEGER(64, false));
+            REQUIRED INT64 exchange_ns (INTEGER(64, false));
+            REQUIRED INT64 latency_ns (INTEGER(64, false));
+            REQUIRED BYTE_ARRAY channel (UTF8);
+            REQUIRED BYTE_ARRAY symbol (UTF8);
+            REQUIRED INT64 message_bytes (INTEGER(64, false));
+        }
+    ";
+
+    /// Buffers samples column-wise and writes one row group per
+    /// `PARQUET_ROW_GROUP_SIZE` samples.
+    pub(super) struct ParquetSink {
+        writer: SerializedFileWriter<File>,
+        receive_ns: Vec<i64>,
+        exchange_ns: Vec<i64>,
+        latency_ns: Vec<i64>,
+        channel: Vec<ByteArray>,
+        symbol: Vec<ByteArray>,
+        message_bytes: Vec<i64>,
+    }
+
+    impl ParquetSink {
+        pub(super) fn new(file: File) -> std::io::Result<Self> {
+            let schema = Arc::new(parse_message_type(SCHEMA).map_err(std::io::Error::other)?);
+            let properties = Arc::new(WriterProperties::builder().build());
+            let writer = SerializedFileWriter::new(file, schema, properties).map_err(std::io::Error::other)?;
+
+            Ok(Self {
+                writer,
+                receive_ns: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE),
+                exchange_ns: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE),
+                latency_ns: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE),
+                channel: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE),
+                symbol: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE),
+                message_bytes: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE),
+            })
+        }
+
+        fn write_row_group(&mut self) -> parquet::errors::Result<()> {
+            if self.receive_ns.is_empty() {
+                return Ok(());
+            }
+
+            let mut row_group = self.writer.next_row_group()?;
+            let mut index = 0;
+            while let Some(mut column) = row_group.next_column()? {
+                match index {
+                    0 => column.typed::<Int64Type>().write_batch(&self.receive_ns, None, None)?,
+                    1 => column.typed::<Int64Type>().write_batch(&self.exchange_ns, None, None)?,
+                    2 => column.typed::<Int64Type>().write_batch(&self.latency_ns, None, None)?,
+                    3 => column.typed::<ByteArrayType>().write_batch(&self.channel, None, None)?,
+                    4 => column.typed::<ByteArrayType>().write_batch(&self.symbol, None, None)?,
+                    _ => column.typed::<Int64Type>().write_batch(&self.message_bytes, None, None)?,
+                };
+                column.close()?;
+                index += 1;
+            }
+            row_group.close()?;
+
+            self.receive_ns.clear();
+            self.exchange_ns.clear();
+            self.latency_ns.clear();
+            self.channel.clear();
+            self.symbol.clear();
+            self.message_bytes.clear();
+            Ok(())
+        }
+    }
+
+    impl SampleSink for ParquetSink {
+        fn write(&mut self, sample: &Sample) -> std::io::Result<()> {
+            self.receive_ns.push(sample.receive_ns as i64);
+            self.exchange_ns.push(sample.exchange_ns as i64);
+            self.latency_ns.push(sample.latency_ns as i64);
+            self.channel.push(ByteArray::from(sample.channel.as_bytes().to_vec()));
+            self.symbol.push(ByteArray::from(sample.symbol.as_bytes().to_vec()));
+            self.message_bytes.push(sample.message_bytes as i64);
+
+            if self.receive_ns.len() >= PARQUET_ROW_GROUP_SIZE {
+                self.write_row_group().map_err(std::io::Error::other)?;
+            }
+            Ok(())
+        }
+
+        fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+            self.write_row_group().map_err(std::io::Error::other)?;
+            let file = self.writer.into_inner().map_err(std::io::Error::other)?;
+            file.sync_all()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // Unique per test and process, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("cex_connector_{}_{}", std::process::id(), name)))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn samples() -> Vec<Sample> {
+        vec![
+            Sample {
+                receive_ns: 1_700_000_000_002_000_000,
+                exchange_ns: 1_700_000_000_000_000_000,
+                latency_ns: 2_000_000,
+                channel: Arc::from("books5"),
+                symbol: Arc::from("BTC-USDT"),
+                message_bytes: 512,
+            },
+            Sample {
+                receive_ns: 1_700_000_000_003_000_000,
+                exchange_ns: 1_700_000_000_002_500_000,
+                latency_ns: 500_000,
+                channel: Arc::from("trades"),
+                symbol: Arc::from("odd,\"symbol\""),
+                message_bytes: 128,
+            },
+        ]
+    }
+
+    fn export(path: &Path, format: ExportFormat) -> ExportSummary {
+        let mut exporter = SampleExporter::create(path, format).unwrap();
+        for sample in samples() {
+            exporter.record(sample);
+        }
+        exporter.finish().unwrap()
+    }
+
+    #[test]
+    fn writes_csv_header_and_rows() {
+        let file = TempFile::new("samples.csv");
+        assert_eq!(ExportFormat::from_path(&file.0), ExportFormat::Csv);
+        let summary = export(&file.0, ExportFormat::Csv);
+        assert_eq!((summary.written, summary.dropped), (2, 0));
+
+        assert_eq!(std::fs::read_to_string(&file.0).unwrap(), "\
+receive_ns,exchange_ns,latency_ns,channel,symbol,message_bytes
+1700000000002000000,1700000000000000000,2000000,books5,BTC-USDT,512
+1700000000003000000,1700000000002500000,500000,trades,\"odd,\"\"symbol\"\"\",128
+");
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn writes_readable_parquet() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::RowAccessor;
+
+        let file = TempFile::new("samples.parquet");
+        assert_eq!(ExportFormat::from_path(&file.0), ExportFormat::Parquet);
+        assert_eq!(export(&file.0, ExportFormat::Parquet).written, 2);
+
+        // Opening the file reads the footer
+        let reader = SerializedFileReader::new(File::open(&file.0).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        let rows: Vec<(u64, u64, u64, String, String, u64)> = reader.get_row_iter(None).unwrap()
+            .map(|row| {
+                let row = row.unwrap();
+                (
+                    row.get_ulong(0).unwrap(),
+                    row.get_ulong(1).unwrap(),
+                    row.get_ulong(2).unwrap(),
+                    row.get_string(3).unwrap().clone(),
+                    row.get_string(4).unwrap().clone(),
+                    row.get_ulong(5).unwrap(),
+                )
+            })
+            .collect();
+        let expected: Vec<_> = samples().into_iter()
+            .map(|s| (s.receive_ns, s.exchange_ns, s.latency_ns, s.channel.to_string(), s.symbol.to_string(), s.message_bytes))
+            .collect();
+        assert_eq!(rows, expected);
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+
+// Replies larger than this are refused; venue REST replies used here are a
+// few KB
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// Status and body of an HTTP reply.
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Sends a JSON `POST` over HTTP/1.1 (`http://` or `https://`) and reads the
+/// whole reply. Each request uses its own connection, closed afterwards.
+pub fn post(url: &str, body: &str, timeout: Duration) -> io::Result<HttpResponse> {
+    let (tls, host, port, path) = parse_url(url)?;
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid(format!("no addresses found for {}", host)))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        path, host, body.len(), body
+    );
+
+    let raw = if tls {
+        let root_store = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.into() };
+        let config = ClientConfig::builder().with_root_certificates(root_store).with_no_client_auth();
+        let server_name = ServerName::try_from(host.clone()).map_err(|e| invalid(format!("invalid server name '{}': {}", host, e)))?;
+        let connection = ClientConnection::new(Arc::new(config), server_name).map_err(io::Error::other)?;
+        exchange(StreamOwned::new(connection, stream), &request)?
+    } else {
+        exchange(stream, &request)?
+    };
+    parse_response(&raw)
+}
+
+fn exchange(mut stream: impl Read + Write, request: &str) -> io::Result<Vec<u8>> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => raw.extend_from_slice(&buf[..n]),
+            // Servers often close TLS connections without a close_notify
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !raw.is_empty() => break,
+            Err(e) => return Err(e),
+        }
+        if raw.len() > MAX_RESPONSE_SIZE {
+            return Err(invalid("response too large".to_string()));
+        }
+    }
+    Ok(raw)
+}
+
+fn parse_response(raw: &[u8]) -> io::Result<HttpResponse> {
+    let header_end = raw.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("incomplete response headers".to_string()))?;
+    let head = std::str::from_utf8(&raw[..header_end]).map_err(|_| invalid("non-UTF-8 response headers".to_string()))?;
+    let mut body = &raw[header_end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line.split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid(format!("invalid status line '{}'", status_line)))?;
+
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else if name.eq_ignore_ascii_case("content-length") {
+            let length: usize = value.parse().map_err(|_| invalid(format!("invalid content length '{}'", value)))?;
+            body = body.get(..length).ok_or_else(|| invalid("truncated response body".to_string()))?;
+        }
+    }
+
+    let body = if chunked { dechunk(body)? } else { body.to_vec() };
+    let body = String::from_utf8(body).map_err(|_| invalid("non-UTF-8 response body".to_string()))?;
+    Ok(HttpResponse { status, body })
+}
+
+// Joins the chunks of a `Transfer-Encoding: chunked` body
+fn dechunk(mut body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(body.len());
+    loop {
+        let line_end = body.windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("truncated chunk".to_string()))?;
+        let size_line = std::str::from_utf8(&body[..line_end]).unwrap_or_default();
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| invalid(format!("invalid chunk size '{}'", size_hex)))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size {
+            return Err(invalid("truncated chunk".to_string()));
+        }
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+// Splits an `http(s)://host[:port][/path]` URL into whether it uses TLS,
+// host, port and path
+fn parse_url(url: &str) -> io::Result<(bool, String, u16, String)> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(invalid(format!("unsupported URL '{}'", url)));
+    };
+    let (host_port, path) = match rest.find('/') {
+        Some(path_start) => rest.split_at(path_start),
+        None => (rest, "/"),
+    };
+    let (host, port) = match host_port.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| invalid(format!("invalid port in '{}'", url)))?),
+        None => (host_port, if tls { 443 } else { 80 }),
+    };
+    Ok((tls, host.to_string(), port, path.to_string()))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Answers one request with `reply` and returns the request it received
+    fn serve_once(reply: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(reply.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn posts_and_reads_content_length_reply() {
+        let (url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\n{\"ok\":true}");
+        let response = post(&format!("{}/api/v1/test", url), "{}", Duration::from_secs(5)).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "{\"ok\":true}");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /api/v1/test HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Length: 2\r\n"));
+    }
+
+    #[test]
+    fn parses_non_200_reply() {
+        let response = parse_response(b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 4\r\n\r\nslow").unwrap();
+        assert_eq!(response.status, 429);
+        assert_eq!(response.body, "slow");
+    }
+
+    #[test]
+    fn rejects_truncated_replies() {
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort").is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n").is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\na\r\nshort").is_err());
+        assert!(parse_response(b"garbage\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn dechunks_bodies() {
+        assert_eq!(dechunk(b"4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\n\r\n").unwrap(), b"Wikipedia");
+        assert_eq!(dechunk(b"0\r\n\r\n").unwrap(), b"");
+        assert!(dechunk(b"zz\r\nWiki\r\n0\r\n\r\n").is_err());
+        assert!(dechunk(b"4\r\nWiki\r\n").is_err());
+    }
+}
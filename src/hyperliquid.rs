@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::refdata;
+use crate::trades::{Side, Trade};
+use crate::venue::{invalid, number, text_heartbeat, Adapter, BookUpdate, MarketEvent, RefData, Venue};
+use crate::websocket::Heartbeat;
+
+pub const PUBLIC_WS_URL: &str = "wss://api.hyperliquid.xyz/ws";
+
+/// Application-level keepalive. Hyperliquid closes connections that send
+/// nothing for 60 s and does not count WebSocket pings.
+pub const PING: &str = r#"{"method":"ping"}"#;
+
+// Minimal structs for Hyperliquid messages. Everything the server sends is
+// `{"channel": ..., "data": ...}`, with `data` differing per channel, so it
+// is kept as JSON and converted with `data`. Times are ms since the epoch.
+#[derive(Debug, Deserialize)]
+pub struct HyperliquidMessage {
+    pub channel: String,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+/// `l2Book` data: the whole book, up to 20 levels a side, on every push.
+#[derive(Debug, Deserialize)]
+pub struct HyperliquidBook {
+    pub coin: String,
+    pub time: u64,
+    /// Bids, then asks
+    pub levels: (Vec<HyperliquidLevel>, Vec<HyperliquidLevel>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HyperliquidLevel {
+    pub px: String,
+    pub sz: String,
+}
+
+/// `bbo` data. A side is null while it is empty.
+#[derive(Debug, Deserialize)]
+pub struct HyperliquidBbo {
+    pub coin: String,
+    pub time: u64,
+    pub bbo: (Option<HyperliquidLevel>, Option<HyperliquidLevel>),
+}
+
+/// `allMids` data: the mid price of every coin, by coin. It carries no time.
+#[derive(Debug, Deserialize)]
+pub struct HyperliquidMids {
+    pub mids: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HyperliquidTrade {
+    pub coin: String,
+    /// Taker side: `B` (buy) or `A` (sell)
+    pub side: String,
+    pub px: String,
+    pub sz: String,
+    pub time: u64,
+    /// Unique across coins, so not consecutive for any one coin
+    pub tid: u64,
+}
+
+/// `post` channel data: the reply to a `post` request.
+#[derive(Debug, Deserialize)]
+pub struct HyperliquidPost {
+    pub id: u64,
+    pub response: PostResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostResponse {
+    /// `info`, `action` or `error`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// For `info`, `{"type": ..., "data": ...}`; for `error`, the message
+    pub payload: serde_json::Value,
+}
+
+impl HyperliquidMessage {
+    /// Converts `data` to the channel's type; `T::default()` without data.
+    pub fn data<T: DeserializeOwned + Default>(&self) -> Result<T, serde_json::Error> {
+        match &self.data {
+            Some(data) => T::deserialize(data),
+            None => Ok(T::default()),
+        }
+    }
+
+    /// Logs subscription replies, pongs and errors. Returns false for channel
+    /// data and `post` replies, which the caller handles.
+    pub fn handle_event(&self) -> bool {
+        let data = || self.data.as_ref().map(|data| data.to_string()).unwrap_or_default();
+        match self.channel.as_str() {
+            "subscriptionResponse" => tracing::info!("hyperliquid: {}", data()),
+            "pong" => tracing::debug!("hyperliquid: pong"),
+            "error" => tracing::error!("hyperliquid error: {}", data()),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Hyperliquid coin for an instrument. Perpetuals are named by their coin,
+/// so `BTC-USD` or `BTC-USDT` becomes `BTC`. Spot names such as `PURR/USDC`
+/// or `@107` are used as given. Case is kept, as in `kPEPE`.
+pub fn symbol(instrument: &str) -> String {
+    if instrument.contains('/') || instrument.starts_with('@') {
+        return instrument.to_string();
+    }
+    instrument.split('-').next().unwrap_or(instrument).to_string()
+}
+
+/// One `subscribe` request per channel and coin; `allMids` covers every
+/// coin in a single subscription.
+pub fn subscribe_requests(channels: &[String], coins: &[String]) -> Vec<String> {
+    requests("subscribe", channels, coins)
+}
+
+pub fn unsubscribe_requests(channels: &[String], coins: &[String]) -> Vec<String> {
+    requests("unsubscribe", channels, coins)
+}
+
+fn requests(method: &str, channels: &[String], coins: &[String]) -> Vec<String> {
+    let request = |subscription: serde_json::Value| {
+        serde_json::json!({ "method": method, "subscription": subscription }).to_string()
+    };
+    channels.iter()
+        .flat_map(|channel| match channel.as_str() {
+            "allMids" => vec![request(serde_json::json!({ "type": "allMids" }))],
+            _ => coins.iter()
+                .map(|coin| request(serde_json::json!({ "type": channel, "coin": symbol(coin) })))
+                .collect(),
+        })
+        .collect()
+}
+
+/// `post` state of one connection: the last request id, and the info type
+/// of every request not yet replied to.
+#[derive(Debug, Default)]
+pub struct HyperliquidSession {
+    last_id: u64,
+    pending: BTreeMap<u64, &'static str>,
+}
+
+impl HyperliquidSession {
+    /// `post` info request with a fresh id, remembered until its reply.
+    pub fn info_request(&mut self, info_type: &'static str, mut payload: serde_json::Value) -> String {
+        self.last_id += 1;
+        self.pending.insert(self.last_id, info_type);
+        payload["type"] = info_type.into();
+        serde_json::json!({
+            "method": "post",
+            "id": self.last_id,
+            "request": { "type": "info", "payload": payload },
+        })
+        .to_string()
+    }
+
+    /// Asks for a coin's current `l2Book`, which is replied in the same form
+    /// as the pushes.
+    pub fn l2_book_request(&mut self, coin: &str) -> String {
+        self.info_request("l2Book", serde_json::json!({ "coin": symbol(coin) }))
+    }
+
+    /// The data of a `post` reply to an info request, converted to `T`.
+    /// Failed requests are logged and give `None`, as do other messages.
+    pub fn post_reply<T: DeserializeOwned>(&mut self, msg: &HyperliquidMessage) -> Result<Option<T>, serde_json::Error> {
+        if msg.channel != "post" {
+            return Ok(None);
+        }
+        let Some(data) = &msg.data else {
+            return Ok(None);
+        };
+        let post = HyperliquidPost::deserialize(data)?;
+        let request = self.pending.remove(&post.id).unwrap_or("unknown request");
+
+        match post.response.kind.as_str() {
+            "info" => match post.response.payload.get("data") {
+                Some(data) => T::deserialize(data).map(Some),
+                None => Ok(None),
+            },
+            "error" => {
+                tracing::error!("hyperliquid {} (id {}) failed: {}", request, post.id, post.response.payload);
+                Ok(None)
+            }
+            kind => {
+                tracing::info!("hyperliquid: {} (id {}) {} reply", request, post.id, kind);
+                Ok(None)
+            }
+        }
+    }
+}
+
+pub struct Hyperliquid;
+
+impl Venue for Hyperliquid {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        text_heartbeat(PING, None)
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        symbol(instrument)
+    }
+
+    fn book_channel(&self) -> Option<&'static str> {
+        Some("l2Book")
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "trades"
+    }
+
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["bbo"]
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(HyperliquidAdapter { refdata, session: HyperliquidSession::default() })
+    }
+}
+
+/// Turns `l2Book` pushes and replies into book snapshots, `bbo` pushes into
+/// BBOs and `trades` pushes into trades.
+pub struct HyperliquidAdapter {
+    refdata: RefData,
+    session: HyperliquidSession,
+}
+
+impl Adapter for HyperliquidAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        subscribe_requests(channels, symbols)
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        unsubscribe_requests(channels, symbols)
+    }
+
+    // Pushes are whole books, so asking for the current one is enough
+    fn resync_requests(&mut self, _channel: &str, symbol: &str) -> Vec<String> {
+        vec![self.session.l2_book_request(symbol)]
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        let msg: HyperliquidMessage = serde_json::from_str(text)?;
+        if msg.handle_event() {
+            return Ok(());
+        }
+
+        match msg.channel.as_str() {
+            "l2Book" => events.extend(msg.data::<Option<HyperliquidBook>>()?.map(book_event)),
+            "post" => events.extend(self.session.post_reply::<HyperliquidBook>(&msg)?.map(book_event)),
+            "bbo" => {
+                if let Some(HyperliquidBbo { coin, time, bbo: (bid, ask) }) = msg.data()? {
+                    let price = |level: Option<HyperliquidLevel>| level.map(|level| number("px", &level.px)).transpose();
+                    events.push(MarketEvent::Bbo {
+                        bid: price(bid)?,
+                        ask: price(ask)?,
+                        exchange_ns: Some(time * 1_000_000),
+                        symbol: coin,
+                    });
+                }
+            }
+            // The mid is all `allMids` carries, so each coin's becomes both
+            // sides of its BBO
+            "allMids" => {
+                if let Some(HyperliquidMids { mids }) = msg.data()? {
+                    for (coin, mid) in mids {
+                        let mid = number("mid", &mid)?;
+                        events.push(MarketEvent::Bbo { symbol: coin, bid: Some(mid), ask: Some(mid), exchange_ns: None });
+                    }
+                }
+            }
+            "trades" => {
+                for trade in msg.data::<Vec<HyperliquidTrade>>()? {
+                    let side = match trade.side.as_str() {
+                        "B" => Side::Buy,
+                        "A" => Side::Sell,
+                        other => return Err(invalid("side", other)),
+                    };
+                    events.push(MarketEvent::Trade(Trade {
+                        price: number("px", &trade.px)?,
+                        size: number("sz", &trade.sz)? * refdata::contract_multiplier(&self.refdata, &trade.coin),
+                        count: 1,
+                        exchange_ns: trade.time * 1_000_000,
+                        symbol: trade.coin,
+                        trade_id: trade.tid.to_string(),
+                        side,
+                    }));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Book event of an `l2Book` push or reply, which replaces the book.
+pub fn book_event(data: HyperliquidBook) -> MarketEvent {
+    let (bids, asks) = data.levels;
+    let levels = |levels: Vec<HyperliquidLevel>| levels.into_iter().map(|level| (level.px, level.sz)).collect();
+    MarketEvent::BookSnapshot(BookUpdate {
+        symbol: data.coin,
+        exchange_ns: Some(data.time * 1_000_000),
+        bids: levels(bids),
+        asks: levels(asks),
+        ..BookUpdate::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(adapter: &mut HyperliquidAdapter, text: &str) -> Vec<MarketEvent> {
+        let mut events = Vec::new();
+        adapter.parse(text, &mut events).unwrap();
+        events
+    }
+
+    fn adapter() -> HyperliquidAdapter {
+        HyperliquidAdapter { refdata: RefData::default(), session: HyperliquidSession::default() }
+    }
+
+    const BOOK: &str = r#"{"coin":"BTC","time":1714564800123,"levels":[[{"px":"60000.0","sz":"1.5","n":3}],[{"px":"60001.0","sz":"0.2","n":1}]]}"#;
+
+    #[test]
+    fn l2_book_pushes_are_snapshots() {
+        let events = parse(&mut adapter(), &format!(r#"{{"channel":"l2Book","data":{}}}"#, BOOK));
+        let [MarketEvent::BookSnapshot(update)] = events.as_slice() else {
+            panic!("expected a snapshot, got {:?}", events);
+        };
+        assert_eq!(update.symbol, "BTC");
+        assert_eq!(update.exchange_ns, Some(1_714_564_800_123_000_000));
+        assert_eq!(update.bids, vec![("60000.0".to_string(), "1.5".to_string())]);
+        assert_eq!(update.asks, vec![("60001.0".to_string(), "0.2".to_string())]);
+    }
+
+    #[test]
+    fn post_replies_are_matched_to_their_request() {
+        let mut adapter = adapter();
+        let request = adapter.resync_requests("l2Book", "BTC-USD").remove(0);
+        assert!(request.contains(r#""id":1"#) && request.contains(r#""coin":"BTC""#));
+
+        let reply = format!(
+            r#"{{"channel":"post","data":{{"id":1,"response":{{"type":"info","payload":{{"type":"l2Book","data":{}}}}}}}}}"#,
+            BOOK
+        );
+        assert!(matches!(parse(&mut adapter, &reply).as_slice(), [MarketEvent::BookSnapshot(update)] if update.symbol == "BTC"));
+        assert!(adapter.session.pending.is_empty());
+    }
+
+    #[test]
+    fn failed_post_requests_give_no_events() {
+        let mut adapter = adapter();
+        adapter.resync_requests("l2Book", "ETH");
+        let reply = r#"{"channel":"post","data":{"id":1,"response":{"type":"error","payload":"Unknown coin"}}}"#;
+        assert!(parse(&mut adapter, reply).is_empty());
+        assert!(adapter.session.pending.is_empty());
+    }
+
+    #[test]
+    fn bbo_sides_may_be_empty() {
+        let text = r#"{"channel":"bbo","data":{"coin":"BTC","time":1714564800123,"bbo":[{"px":"60000.0","sz":"1.5","n":3},null]}}"#;
+        let events = parse(&mut adapter(), text);
+        assert!(matches!(
+            events.as_slice(),
+            [MarketEvent::Bbo { symbol, bid: Some(bid), ask: None, exchange_ns: Some(_) }] if symbol == "BTC" && *bid == 60_000.0
+        ));
+    }
+
+    #[test]
+    fn all_mids_become_a_bbo_per_coin() {
+        let text = r#"{"channel":"allMids","data":{"mids":{"BTC":"60000.5","ETH":"3000.25"}}}"#;
+        let events = parse(&mut adapter(), text);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[1],
+            MarketEvent::Bbo { symbol, bid: Some(3000.25), ask: Some(3000.25), exchange_ns: None } if symbol == "ETH"
+        ));
+    }
+
+    #[test]
+    fn trade_sides_are_the_taker_side() {
+        let text = r#"{"channel":"trades","data":[
+            {"coin":"BTC","side":"B","px":"60000.0","sz":"0.1","time":1714564800123,"tid":7,"hash":"0x0"},
+            {"coin":"BTC","side":"A","px":"59999.0","sz":"0.2","time":1714564800124,"tid":8,"hash":"0x0"}
+        ]}"#;
+        let sides: Vec<Side> = parse(&mut adapter(), text).iter()
+            .map(|event| match event {
+                MarketEvent::Trade(trade) => trade.side,
+                other => panic!("expected a trade, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(sides, [Side::Buy, Side::Sell]);
+
+        let invalid = r#"{"channel":"trades","data":[{"coin":"BTC","side":"X","px":"1","sz":"1","time":1,"tid":9}]}"#;
+        assert!(adapter().parse(invalid, &mut Vec::new()).is_err());
+    }
+}
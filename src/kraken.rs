@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::latency::parse_utc_time_ns;
+use crate::orderbook::{BookChecksum, KrakenChecksum};
+use crate::refdata::{self, ReferentialData};
+use crate::trades::{Side, Trade};
+use crate::venue::{invalid, Adapter, BookUpdate, MarketEvent, RefData, Venue};
+use crate::websocket::Heartbeat;
+
+pub const PUBLIC_WS_URL: &str = "wss://ws.kraken.com/v2";
+
+/// Depth of a plain `book` subscription; Kraken also offers 25, 100, 500
+/// and 1000, as `book.<depth>`.
+pub const DEFAULT_BOOK_DEPTH: usize = 10;
+
+// Minimal structs for Kraken v2 messages. Channel pushes carry `channel`,
+// `type` and `data`, whose shape differs per channel, so it is kept as raw
+// JSON text and converted with `data`. Request replies carry `method` and
+// `success`.
+#[derive(Debug, Deserialize)]
+pub struct KrakenMessage {
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// `snapshot` or `update`
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub data: Option<Box<RawValue>>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub success: Option<bool>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub result: Option<KrakenResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KrakenResult {
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub symbol: Option<String>,
+}
+
+/// `book` entry. Snapshots carry no timestamp.
+#[derive(Debug, Deserialize)]
+pub struct KrakenBook {
+    pub symbol: String,
+    #[serde(default)]
+    pub bids: Vec<KrakenLevel>,
+    #[serde(default)]
+    pub asks: Vec<KrakenLevel>,
+    /// CRC32 of the top 10 levels of each side after this push
+    pub checksum: u32,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+}
+
+/// A quantity of `0` deletes the level. Price and quantity are JSON
+/// numbers, kept as the exact text Kraken sent.
+#[derive(Debug, Deserialize)]
+pub struct KrakenLevel {
+    pub price: Box<RawValue>,
+    pub qty: Box<RawValue>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KrakenTrade {
+    pub symbol: String,
+    /// Taker side, `buy` or `sell`
+    pub side: String,
+    pub price: f64,
+    pub qty: f64,
+    /// Sequential per pair
+    pub trade_id: u64,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KrakenTicker {
+    pub symbol: String,
+    pub bid: f64,
+    pub ask: f64,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+}
+
+/// `instrument` channel data, listing every pair.
+#[derive(Debug, Default, Deserialize)]
+pub struct KrakenInstruments {
+    #[serde(default)]
+    pub pairs: Vec<KrakenPair>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KrakenPair {
+    pub symbol: String,
+    /// Decimals of prices and quantities, which book checksums are computed
+    /// over
+    pub price_precision: u32,
+    pub qty_precision: u32,
+}
+
+impl KrakenMessage {
+    /// Converts `data` to the channel's type; `T::default()` without data.
+    pub fn data<T: DeserializeOwned + Default>(&self) -> Result<T, serde_json::Error> {
+        match &self.data {
+            Some(data) => serde_json::from_str(data.get()),
+            None => Ok(T::default()),
+        }
+    }
+
+    /// Logs request replies and status pushes. Returns false for channel
+    /// data, which the caller handles.
+    pub fn handle_event(&self) -> bool {
+        if let Some(method) = &self.method {
+            let result = self.result.as_ref();
+            let (channel, symbol) = (
+                result.and_then(|result| result.channel.as_deref()).unwrap_or_default(),
+                result.and_then(|result| result.symbol.as_deref()).unwrap_or_default(),
+            );
+            match self.success {
+                Some(false) => tracing::error!("kraken {} failed: {}", method, self.error.as_deref().unwrap_or_default()),
+                _ => tracing::info!("kraken: {} {} {}", method, channel, symbol),
+            }
+            return true;
+        }
+        match self.channel.as_deref() {
+            Some("status") => {
+                tracing::info!("kraken status: {}", self.data.as_ref().map(|data| data.get()).unwrap_or_default());
+                true
+            }
+            Some("heartbeat") | None => true,
+            Some(_) => false,
+        }
+    }
+}
+
+impl KrakenPair {
+    pub fn refdata(&self) -> ReferentialData {
+        ReferentialData {
+            uid: self.symbol.clone(),
+            contract_multiplier: 1.0,
+            payoff_type: "spot".to_string(),
+            inst_family: None,
+            strike: None,
+            expiry_ms: None,
+            option_type: None,
+            price_precision: Some(self.price_precision),
+            qty_precision: Some(self.qty_precision),
+        }
+    }
+}
+
+/// Kraken v2 symbol for a `BASE-QUOTE` instrument, e.g. `BTC/USD`.
+pub fn symbol(instrument: &str) -> String {
+    instrument.replacen('-', "/", 1).to_ascii_uppercase()
+}
+
+/// Depth of a `book` or `book.<depth>` channel; `None` for other channels.
+pub fn book_depth(channel: &str) -> Option<usize> {
+    match channel.split_once('.') {
+        None if channel == "book" => Some(DEFAULT_BOOK_DEPTH),
+        Some(("book", depth)) => depth.parse().ok(),
+        _ => None,
+    }
+}
+
+/// One `subscribe` request per channel, as Kraken takes one channel per
+/// request. `instrument` covers every pair. Tickers are pushed on every BBO
+/// change and trades without the snapshot of recent ones.
+pub fn subscribe_requests(channels: &[String], instruments: &[String]) -> Vec<String> {
+    requests("subscribe", channels, instruments)
+}
+
+pub fn unsubscribe_requests(channels: &[String], instruments: &[String]) -> Vec<String> {
+    requests("unsubscribe", channels, instruments)
+}
+
+fn requests(method: &str, channels: &[String], instruments: &[String]) -> Vec<String> {
+    let symbols: Vec<String> = instruments.iter().map(|instrument| symbol(instrument)).collect();
+    channels.iter()
+        .map(|channel| {
+            let mut params = serde_json::Map::new();
+            match book_depth(channel) {
+                Some(depth) => {
+                    params.insert("channel".into(), "book".into());
+                    params.insert("depth".into(), depth.into());
+                }
+                None => {
+                    params.insert("channel".into(), channel.as_str().into());
+                }
+            }
+            if channel != "instrument" {
+                params.insert("symbol".into(), symbols.clone().into());
+            }
+            if method == "subscribe" {
+                match channel.as_str() {
+                    "ticker" => {
+                        params.insert("event_trigger".into(), "bbo".into());
+                    }
+                    "trade" => {
+                        params.insert("snapshot".into(), false.into());
+                    }
+                    _ => {}
+                }
+            }
+            serde_json::json!({ "method": method, "params": params }).to_string()
+        })
+        .collect()
+}
+
+pub struct Kraken;
+
+impl Venue for Kraken {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::Ping
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        symbol(instrument)
+    }
+
+    fn book_channel(&self) -> Option<&'static str> {
+        Some("book")
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "trade"
+    }
+
+    // Tickers may carry no timestamp, so latency comes from the trades
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["ticker", "trade"]
+    }
+
+    fn book_checksum(&self) -> Option<&'static dyn BookChecksum> {
+        Some(&KrakenChecksum)
+    }
+
+    fn sequential_trade_ids(&self) -> bool {
+        true
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(KrakenAdapter {
+            refdata,
+            depth: DEFAULT_BOOK_DEPTH,
+            symbols: Vec::new(),
+            instruments: HashMap::new(),
+            instrument_subscribed: false,
+        })
+    }
+}
+
+/// Turns book, trade and ticker pushes into events. Book subscriptions
+/// bring in the `instrument` channel, whose pair precisions the book
+/// levels are formatted to for their checksums.
+pub struct KrakenAdapter {
+    refdata: RefData,
+    /// Depth of the book subscription, which Kraken keeps books to
+    depth: usize,
+    symbols: Vec<String>,
+    /// Precisions of the subscribed pairs
+    instruments: HashMap<String, ReferentialData>,
+    instrument_subscribed: bool,
+}
+
+impl Adapter for KrakenAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        for instrument in symbols {
+            let symbol = symbol(instrument);
+            if !self.symbols.contains(&symbol) {
+                self.symbols.push(symbol);
+            }
+        }
+        let mut channels = channels.to_vec();
+        if let Some(depth) = channels.iter().find_map(|channel| book_depth(channel)) {
+            self.depth = depth;
+            // Precisions should be known by the first snapshot
+            if !self.instrument_subscribed && !channels.iter().any(|channel| channel == "instrument") {
+                channels.insert(0, "instrument".to_string());
+            }
+            self.instrument_subscribed = true;
+        }
+        subscribe_requests(&channels, symbols)
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        unsubscribe_requests(channels, symbols)
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        let msg: KrakenMessage = serde_json::from_str(text)?;
+        if msg.handle_event() {
+            return Ok(());
+        }
+
+        match msg.channel.as_deref() {
+            Some("instrument") => {
+                let data: KrakenInstruments = msg.data()?;
+                for pair in data.pairs.iter().filter(|pair| self.symbols.contains(&pair.symbol)) {
+                    self.instruments.insert(pair.symbol.clone(), pair.refdata());
+                }
+            }
+            Some("book") => {
+                for entry in msg.data::<Vec<KrakenBook>>()? {
+                    let refdata = self.instruments.get(&entry.symbol);
+                    events.push(book_event(msg.kind.as_deref(), entry, self.depth, refdata));
+                }
+            }
+            Some("trade") => {
+                for trade in msg.data::<Vec<KrakenTrade>>()? {
+                    let side = match trade.side.as_str() {
+                        "buy" => Side::Buy,
+                        "sell" => Side::Sell,
+                        other => return Err(invalid("side", other)),
+                    };
+                    events.push(MarketEvent::Trade(Trade {
+                        price: trade.price,
+                        size: trade.qty * refdata::contract_multiplier(&self.refdata, &trade.symbol),
+                        count: 1,
+                        exchange_ns: parse_utc_time_ns(&trade.timestamp).ok_or_else(|| invalid("timestamp", &trade.timestamp))?,
+                        symbol: trade.symbol,
+                        trade_id: trade.trade_id.to_string(),
+                        side,
+                    }));
+                }
+            }
+            Some("ticker") => {
+                for ticker in msg.data::<Vec<KrakenTicker>>()? {
+                    events.push(MarketEvent::Bbo {
+                        bid: Some(ticker.bid),
+                        ask: Some(ticker.ask),
+                        exchange_ns: ticker.timestamp.as_deref().and_then(parse_utc_time_ns),
+                        symbol: ticker.symbol,
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Book event of a book push, for a book kept to the subscribed `depth`.
+///
+/// Prices and quantities arrive as JSON numbers whose trailing zeros may be
+/// dropped, so their text is padded to the pair's precision from `refdata`
+/// for the checksum. Without it they are kept as sent and the checksum fails
+/// whenever a trailing zero was dropped.
+pub fn book_event(kind: Option<&str>, data: KrakenBook, depth: usize, refdata: Option<&ReferentialData>) -> MarketEvent {
+    let price_precision = refdata.and_then(|instrument| instrument.price_precision);
+    let qty_precision = refdata.and_then(|instrument| instrument.qty_precision);
+    let levels = |levels: &[KrakenLevel]| -> Vec<(String, String)> {
+        levels.iter()
+            .map(|level| (pad_decimals(level.price.get(), price_precision), pad_decimals(level.qty.get(), qty_precision)))
+            .collect()
+    };
+    let update = BookUpdate {
+        exchange_ns: data.timestamp.as_deref().and_then(parse_utc_time_ns),
+        bids: levels(&data.bids),
+        asks: levels(&data.asks),
+        checksum: Some(data.checksum),
+        depth: Some(depth),
+        symbol: data.symbol,
+        ..BookUpdate::default()
+    };
+    match kind {
+        Some("update") => MarketEvent::BookDelta(update),
+        _ => MarketEvent::BookSnapshot(update),
+    }
+}
+
+// Appends zeros to a decimal number's text up to `precision` decimals; never
+// rounds, so the digits Kraken sent are kept
+fn pad_decimals(value: &str, precision: Option<u32>) -> String {
+    let mut padded = value.to_string();
+    let Some(precision) = precision else {
+        return padded;
+    };
+    let decimals = match value.split_once('.') {
+        Some((_, fraction)) => fraction.len(),
+        None if precision > 0 => {
+            padded.push('.');
+            0
+        }
+        None => 0,
+    };
+    for _ in decimals..precision as usize {
+        padded.push('0');
+    }
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::orderbook::{BookError, OrderBook};
+
+    #[test]
+    fn book_levels_keep_the_text_kraken_sent() {
+        let msg: KrakenMessage = serde_json::from_str(
+            r#"{"channel":"book","type":"snapshot","data":[{"symbol":"MATIC/USD","bids":[{"price":0.5657,"qty":1098.3947558}],"asks":[{"price":0.5660,"qty":0.10000000}],"checksum":1}]}"#,
+        ).unwrap();
+        let data: Vec<KrakenBook> = msg.data().unwrap();
+        assert_eq!(data[0].bids[0].price.get(), "0.5657");
+        assert_eq!(data[0].bids[0].qty.get(), "1098.3947558");
+        assert_eq!(data[0].asks[0].price.get(), "0.5660");
+        assert_eq!(data[0].asks[0].qty.get(), "0.10000000");
+    }
+
+    #[test]
+    fn pads_to_the_pair_precision_without_rounding() {
+        assert_eq!(pad_decimals("1098.3947558", Some(8)), "1098.39475580");
+        assert_eq!(pad_decimals("45283", Some(1)), "45283.0");
+        assert_eq!(pad_decimals("0.123456789", Some(8)), "0.123456789");
+        assert_eq!(pad_decimals("0.1", None), "0.1");
+    }
+
+    #[test]
+    fn applies_book_and_verifies_checksum() {
+        // Kraken's documented checksum example, sent with trailing zeros dropped
+        let asks: Vec<String> = (0..10).map(|i| format!(r#"{{"price":0.0{},"qty":0.000005}}"#, 5005 + 5 * i)).collect();
+        let bids: Vec<String> = [5000, 4995, 4990, 4980, 4975, 4970, 4965, 4960, 4955, 4950].iter()
+            .map(|price| format!(r#"{{"price":0.0{},"qty":0.000005}}"#, price))
+            .collect();
+        let text = format!(
+            r#"{{"channel":"book","type":"snapshot","data":[{{"symbol":"XBT/ETH","bids":[{}],"asks":[{}],"checksum":974947235}}]}}"#,
+            bids.join(","), asks.join(",")
+        );
+        let pair = r#"{"channel":"instrument","type":"snapshot","data":{"assets":[],"pairs":[{"symbol":"XBT/ETH","price_precision":5,"qty_precision":8}]}}"#;
+
+        let mut adapter = Kraken.adapter(RefData::default());
+        let requests = adapter.subscribe_requests(&["book".to_string()], &["XBT-ETH".to_string()]);
+        assert!(requests[0].contains(r#""channel":"instrument""#));
+        let mut events = Vec::new();
+        adapter.parse(pair, &mut events).unwrap();
+        adapter.parse(&text, &mut events).unwrap();
+        let [MarketEvent::BookSnapshot(update)] = events.as_slice() else {
+            panic!("expected one book snapshot, got {:?}", events);
+        };
+
+        let mut book = OrderBook::default();
+        update.apply(&mut book, true, Kraken.book_checksum()).unwrap();
+        assert_eq!(book.asks().next().unwrap().price, "0.05005");
+        assert_eq!(book.bids().next().unwrap().size, "0.00000500");
+
+        // Without the pair's precisions the levels keep the text as sent
+        let mut unformatted = Kraken.adapter(RefData::default());
+        events.clear();
+        unformatted.parse(&text, &mut events).unwrap();
+        let [MarketEvent::BookSnapshot(update)] = events.as_slice() else {
+            panic!("expected one book snapshot, got {:?}", events);
+        };
+        assert!(matches!(
+            update.apply(&mut OrderBook::default(), true, Some(&KrakenChecksum)),
+            Err(BookError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,401 @@
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::cli::FeedArgs;
+use crate::http;
+use crate::refdata;
+use crate::trades::{Side, Trade};
+use crate::venue::{invalid, number, text_heartbeat, timestamp, Adapter, MarketEvent, RefData, Venue};
+use crate::websocket::{Heartbeat, WebSocketConfig};
+
+/// Base of KuCoin's public REST API, where connect tokens are issued.
+pub const PUBLIC_REST_URL: &str = "https://api.kucoin.com";
+
+/// KuCoin's usual public WebSocket endpoint. Connections always use the
+/// endpoint returned with the token instead.
+pub const PUBLIC_WS_URL: &str = "wss://ws-api-spot.kucoin.com/";
+
+const BULLET_PUBLIC_PATH: &str = "/api/v1/bullet-public";
+
+/// Keepalive text. KuCoin drops connections that send no `ping` within
+/// the server's ping interval plus its ping timeout; WebSocket pings do not
+/// count.
+pub const PING: &str = r#"{"id":"keepalive","type":"ping"}"#;
+
+// Most symbols KuCoin accepts in one topic
+const MAX_SYMBOLS_PER_TOPIC: usize = 100;
+
+/// `bullet-public` reply.
+#[derive(Debug, Deserialize)]
+pub struct BulletResponse {
+    /// `200000` on success
+    pub code: String,
+    #[serde(default)]
+    pub msg: Option<String>,
+    #[serde(default)]
+    pub data: Option<Bullet>,
+}
+
+/// Token and servers for one WebSocket connection.
+#[derive(Debug, Deserialize)]
+pub struct Bullet {
+    pub token: String,
+    #[serde(rename = "instanceServers")]
+    pub instance_servers: Vec<InstanceServer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceServer {
+    pub endpoint: String,
+    /// `websocket`
+    pub protocol: String,
+    /// ms between the client's pings
+    pub ping_interval: u64,
+    /// ms the server waits for a ping past the interval
+    pub ping_timeout: u64,
+}
+
+/// Where and how to connect, from a `bullet-public` reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectInfo {
+    /// Endpoint with the token and a connect id
+    pub ws_url: String,
+    pub ping_interval: Duration,
+    /// How long past the interval KuCoin waits for a ping
+    pub ping_timeout: Duration,
+}
+
+// Minimal struct for KuCoin WebSocket messages. Topic data differs per
+// topic, so it is kept as JSON and converted with `data`.
+#[derive(Debug, Deserialize)]
+pub struct KucoinMessage {
+    #[serde(default)]
+    pub id: Option<String>,
+    /// `welcome`, `ack`, `pong`, `message` or `error`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// e.g. `/market/ticker:BTC-USDT`
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+    /// Set on errors
+    #[serde(default)]
+    pub code: Option<serde_json::Value>,
+}
+
+/// `/market/ticker` data.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KucoinTicker {
+    pub best_bid: String,
+    pub best_ask: String,
+    /// ms
+    pub time: u64,
+}
+
+/// `/market/match` data.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KucoinMatch {
+    pub symbol: String,
+    /// Taker side, `buy` or `sell`
+    pub side: String,
+    pub price: String,
+    pub size: String,
+    /// Not sequential
+    pub trade_id: String,
+    /// ns, as a string
+    pub time: String,
+}
+
+impl KucoinMessage {
+    /// Converts `data` to the topic's type; `T::default()` without data.
+    pub fn data<T: DeserializeOwned + Default>(&self) -> Result<T, serde_json::Error> {
+        match &self.data {
+            Some(data) => T::deserialize(data),
+            None => Ok(T::default()),
+        }
+    }
+
+    /// Topic name without its symbols, e.g. `/market/ticker`.
+    pub fn topic_name(&self) -> Option<&str> {
+        self.topic.as_deref().map(|topic| topic.split(':').next().unwrap_or(topic))
+    }
+
+    /// Logs welcomes, acks, pongs and errors. Returns false for topic data,
+    /// which the caller handles.
+    pub fn handle_event(&self) -> bool {
+        let id = self.id.as_deref().unwrap_or_default();
+        match self.kind.as_str() {
+            "message" => return false,
+            "welcome" => tracing::info!("kucoin: connected ({})", id),
+            "ack" => tracing::info!("kucoin: request {} acknowledged", id),
+            "pong" => tracing::debug!("kucoin: pong"),
+            "error" => tracing::error!(
+                "kucoin error {}: {}",
+                self.code.as_ref().map(|code| code.to_string()).unwrap_or_default(),
+                self.data.as_ref().map(|data| data.to_string()).unwrap_or_default()
+            ),
+            kind => tracing::debug!("kucoin: {} message", kind),
+        }
+        true
+    }
+}
+
+/// Asks `rest_url` for a public connect token, the first step of every
+/// KuCoin connection, and returns the WebSocket URL and ping interval to
+/// use.
+pub fn fetch_connect_info(rest_url: &str, timeout: Duration) -> std::io::Result<ConnectInfo> {
+    let url = format!("{}{}", rest_url.trim_end_matches('/'), BULLET_PUBLIC_PATH);
+    let response = http::post(&url, "", timeout)?;
+    if response.status != 200 {
+        return Err(std::io::Error::other(format!("{} returned HTTP {}: {}", url, response.status, response.body)));
+    }
+    let reply: BulletResponse = serde_json::from_str(&response.body)?;
+    connect_info(reply, crate::latency::current_timestamp_ns_hires())
+}
+
+/// Picks the first WebSocket server of a `bullet-public` reply. `connect_id`
+/// tags the connection in KuCoin's welcome message.
+pub fn connect_info(reply: BulletResponse, connect_id: u64) -> std::io::Result<ConnectInfo> {
+    let bullet = match reply.data {
+        Some(bullet) if reply.code == "200000" => bullet,
+        _ => {
+            return Err(std::io::Error::other(format!(
+                "bullet request failed ({}): {}",
+                reply.code, reply.msg.unwrap_or_default()
+            )))
+        }
+    };
+    let server = bullet.instance_servers.iter()
+        .find(|server| server.protocol == "websocket")
+        .ok_or_else(|| std::io::Error::other("bullet reply lists no websocket server"))?;
+
+    // The token goes in the query, which needs a path before it
+    let mut endpoint = server.endpoint.clone();
+    if endpoint.matches('/').count() < 3 {
+        endpoint.push('/');
+    }
+    let separator = if endpoint.contains('?') { '&' } else { '?' };
+    Ok(ConnectInfo {
+        ws_url: format!("{}{}token={}&connectId={}", endpoint, separator, bullet.token, connect_id),
+        ping_interval: Duration::from_millis(server.ping_interval),
+        ping_timeout: Duration::from_millis(server.ping_timeout),
+    })
+}
+
+/// KuCoin symbol for a `BASE-QUOTE` instrument; the two are the same.
+pub fn symbol(instrument: &str) -> String {
+    instrument.to_ascii_uppercase()
+}
+
+/// One `subscribe` request per channel and up to 100 symbols. A channel is
+/// a topic such as `/market/ticker`; `ticker` is short for it.
+pub fn subscribe_requests(channels: &[String], symbols: &[String]) -> Vec<String> {
+    requests("subscribe", channels, symbols)
+}
+
+pub fn unsubscribe_requests(channels: &[String], symbols: &[String]) -> Vec<String> {
+    requests("unsubscribe", channels, symbols)
+}
+
+fn requests(kind: &'static str, channels: &[String], symbols: &[String]) -> Vec<String> {
+    let symbols: Vec<String> = symbols.iter().map(|symbol| self::symbol(symbol)).collect();
+    channels.iter()
+        .flat_map(|channel| {
+            let topic = if channel.starts_with('/') { channel.clone() } else { format!("/market/{}", channel) };
+            symbols.chunks(MAX_SYMBOLS_PER_TOPIC)
+                .map(move |symbols| {
+                    serde_json::json!({
+                        "id": crate::latency::current_timestamp_ns_hires().to_string(),
+                        "type": kind,
+                        "topic": format!("{}:{}", topic, symbols.join(",")),
+                        "privateChannel": false,
+                        "response": true,
+                    })
+                    .to_string()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+pub struct Kucoin;
+
+impl Venue for Kucoin {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        text_heartbeat(PING, None)
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        symbol(instrument)
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "match"
+    }
+
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["ticker"]
+    }
+
+    // KuCoin hands out the endpoint, a token and the ping interval over REST
+    // first. Every ping is answered, so a connection quiet for an interval
+    // plus the timeout is as good as dropped.
+    fn endpoint(&self, feed: &FeedArgs, config: &mut WebSocketConfig) -> std::io::Result<String> {
+        let info = fetch_connect_info(feed.rest_url(), config.connect_timeout).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("Failed to get a KuCoin connect token from {}: {}", feed.rest_url(), e))
+        })?;
+        let stale_timeout = info.ping_interval + info.ping_timeout;
+        tracing::info!("kucoin: pinging every {:?}, stale after {:?}", info.ping_interval, stale_timeout);
+        config.ping_interval = info.ping_interval;
+        config.stale_timeout = Some(config.stale_timeout.map_or(stale_timeout, |timeout| timeout.min(stale_timeout)));
+        Ok(info.ws_url)
+    }
+
+    fn awaits_welcome(&self) -> bool {
+        true
+    }
+
+    fn is_welcome(&self, text: &str) -> Result<bool, serde_json::Error> {
+        let msg: KucoinMessage = serde_json::from_str(text)?;
+        msg.handle_event();
+        Ok(msg.kind == "welcome")
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(KucoinAdapter { refdata })
+    }
+}
+
+/// Turns `/market/ticker` data into BBOs and `/market/match` data into
+/// trades.
+pub struct KucoinAdapter {
+    refdata: RefData,
+}
+
+impl Adapter for KucoinAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        subscribe_requests(channels, symbols)
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        unsubscribe_requests(channels, symbols)
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        let msg: KucoinMessage = serde_json::from_str(text)?;
+        if msg.handle_event() {
+            return Ok(());
+        }
+
+        match msg.topic_name() {
+            // Tickers name their symbol only in the topic
+            Some("/market/ticker") => {
+                let ticker: KucoinTicker = msg.data()?;
+                let symbol = msg.topic.as_deref().and_then(|topic| topic.split_once(':')).map_or("", |(_, symbol)| symbol);
+                events.push(MarketEvent::Bbo {
+                    symbol: symbol.to_string(),
+                    bid: Some(number("bestBid", &ticker.best_bid)?),
+                    ask: Some(number("bestAsk", &ticker.best_ask)?),
+                    exchange_ns: Some(ticker.time * 1_000_000),
+                });
+            }
+            Some("/market/match") => {
+                let trade: KucoinMatch = msg.data()?;
+                let side = match trade.side.as_str() {
+                    "buy" => Side::Buy,
+                    "sell" => Side::Sell,
+                    other => return Err(invalid("side", other)),
+                };
+                events.push(MarketEvent::Trade(Trade {
+                    price: number("price", &trade.price)?,
+                    size: number("size", &trade.size)? * refdata::contract_multiplier(&self.refdata, &trade.symbol),
+                    count: 1,
+                    exchange_ns: timestamp("time", &trade.time)?,
+                    symbol: trade.symbol,
+                    trade_id: trade.trade_id,
+                    side,
+                }));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const BULLET: &str = r#"{"code":"200000","data":{"token":"2neAiuYvAU61ZD","instanceServers":[{"endpoint":"wss://ws-api-spot.kucoin.com/","encrypt":true,"protocol":"websocket","pingInterval":18000,"pingTimeout":10000}]}}"#;
+
+    // Stands in for the REST API: answers one request with `head` followed by
+    // `body`, and returns the request line it received
+    fn serve_bullet(head: String, body: String) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(body.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap().lines().next().unwrap_or_default().to_string()
+        });
+        (url, handle)
+    }
+
+    fn check(info: ConnectInfo) {
+        assert!(info.ws_url.starts_with("wss://ws-api-spot.kucoin.com/?token=2neAiuYvAU61ZD&connectId="));
+        assert!(info.ws_url.rsplit("connectId=").next().unwrap().parse::<u64>().is_ok());
+        assert_eq!(info.ping_interval, Duration::from_millis(18000));
+        assert_eq!(info.ping_timeout, Duration::from_millis(10000));
+    }
+
+    #[test]
+    fn fetches_connect_info_with_content_length() {
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n", BULLET.len());
+        let (url, server) = serve_bullet(head, BULLET.to_string());
+        check(fetch_connect_info(&url, Duration::from_secs(5)).unwrap());
+        assert_eq!(server.join().unwrap(), "POST /api/v1/bullet-public HTTP/1.1");
+    }
+
+    #[test]
+    fn fetches_connect_info_chunked() {
+        let (first, second) = BULLET.split_at(40);
+        let body = format!("{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n", first.len(), first, second.len(), second);
+        let head = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_string();
+        let (url, server) = serve_bullet(head, body);
+        check(fetch_connect_info(&url, Duration::from_secs(5)).unwrap());
+        assert_eq!(server.join().unwrap(), "POST /api/v1/bullet-public HTTP/1.1");
+    }
+
+    #[test]
+    fn rejects_failed_bullet_requests() {
+        let body = r#"{"code":"429000","msg":"Too many requests"}"#;
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+        let (url, _server) = serve_bullet(head, body.to_string());
+        assert!(fetch_connect_info(&url, Duration::from_secs(5)).is_err());
+
+        let head = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_string();
+        let (url, _server) = serve_bullet(head, String::new());
+        assert!(fetch_connect_info(&url, Duration::from_secs(5)).is_err());
+    }
+}
@@ -1,8 +1,23 @@
 use std::collections::VecDeque;
-use std::sync::OnceLock;
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{fence, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, Once, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub static HIGH_RES_TIMER: OnceLock<HighResTimer> = OnceLock::new();
+/// Recalibrates the process-wide timer; started once, with the timer
+static RECALIBRATION_THREAD: Once = Once::new();
+
+const CALIBRATION_PERIOD: Duration = Duration::from_millis(100);
+const RECALIBRATION_INTERVAL: Duration = Duration::from_secs(1);
+// Weight of each new rate measurement in the smoothed rate
+const RATE_SMOOTHING: f64 = 0.1;
+// Largest rate adjustment used to slew out an offset (500 ppm)
+const MAX_SLEW: f64 = 500e-6;
+// Offsets beyond this are treated as a realtime clock step
+const STEP_THRESHOLD_NS: i64 = 100_000_000;
+// Largest TSC offset between cores before the TSC is considered unusable
+#[cfg(target_arch = "x86_64")]
+const TSC_SYNC_TOLERANCE_NS: f64 = 1_000.0;
 
 #[cfg(target_arch = "x86_64")]
 unsafe fn rdtsc() -> u64 {
@@ -19,81 +34,626 @@ unsafe fn rdtsc() -> u64 {
     ((high as u64) << 32) | (low as u64)
 }
 
-pub struct HighResTimer {
-    #[cfg(target_arch = "x86_64")]
-    pub cycles_per_ns: f64,
+/// RDTSC after LFENCE, so it cannot execute before earlier instructions
+/// (such as the read that completed a message) have finished. Without
+/// `nomem` the asm is also a compiler barrier.
+#[cfg(target_arch = "x86_64")]
+unsafe fn rdtsc_lfence() -> u64 {
+    let mut high: u32;
+    let mut low: u32;
+
+    std::arch::asm!(
+        "lfence",
+        "rdtsc",
+        out("eax") low,
+        out("edx") high,
+        options(nostack),
+    );
+
+    ((high as u64) << 32) | (low as u64)
+}
+
+/// RDTSCP waits for earlier instructions; the trailing LFENCE keeps later
+/// ones from starting before it. Also returns `IA32_TSC_AUX`, which Linux
+/// sets to `(node << 12) | cpu`.
+#[cfg(target_arch = "x86_64")]
+unsafe fn rdtscp() -> (u64, u32) {
+    let mut high: u32;
+    let mut low: u32;
+    let mut aux: u32;
+
+    std::arch::asm!(
+        "rdtscp",
+        "lfence",
+        out("eax") low,
+        out("edx") high,
+        out("ecx") aux,
+        options(nostack),
+    );
+
+    (((high as u64) << 32) | (low as u64), aux)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn rdtscp_supported() -> bool {
+    use std::arch::x86_64::__cpuid;
+
+    __cpuid(0x8000_0000).eax >= 0x8000_0001 && __cpuid(0x8000_0001).edx & (1 << 27) != 0
+}
+
+/// Reads the virtual counter. `CNTVCT_EL0` is readable from EL0 on Linux and
+/// ticks at the constant `CNTFRQ_EL0` rate on every core.
+#[cfg(target_arch = "aarch64")]
+unsafe fn cntvct() -> u64 {
+    let ticks: u64;
+    std::arch::asm!("mrs {}, cntvct_el0", out(reg) ticks, options(nomem, nostack));
+    ticks
+}
+
+/// ISB before the counter read, the aarch64 counterpart of LFENCE+RDTSC.
+#[cfg(target_arch = "aarch64")]
+unsafe fn cntvct_isb() -> u64 {
+    let ticks: u64;
+    std::arch::asm!("isb", "mrs {}, cntvct_el0", out(reg) ticks, options(nostack));
+    ticks
+}
+
+#[cfg(target_arch = "aarch64")]
+fn cntfrq() -> u64 {
+    let frequency: u64;
+    unsafe {
+        std::arch::asm!("mrs {}, cntfrq_el0", out(reg) frequency, options(nomem, nostack));
+    }
+    frequency
+}
+
+/// True if CPUID reports an invariant TSC (constant rate, keeps running in
+/// deep C-states). Hypervisors often hide the CPUID bit, so on Linux the
+/// kernel's `constant_tsc` + `nonstop_tsc` flags are accepted as well.
+#[cfg(target_arch = "x86_64")]
+fn tsc_is_invariant() -> bool {
+    use std::arch::x86_64::__cpuid;
+
+    let max_extended_leaf = __cpuid(0x8000_0000).eax;
+    if max_extended_leaf >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0 {
+        return true;
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Ok(cpuinfo) = std::fs::read_to_string("/proc/cpuinfo") {
+        if let Some(flags) = cpuinfo.lines().find(|line| line.starts_with("flags")) {
+            let has = |flag: &str| flags.split_whitespace().any(|f| f == flag);
+            return has("constant_tsc") && has("nonstop_tsc");
+        }
+    }
+
+    false
+}
+
+/// Measures the TSC offset on every CPU this process may run on and returns
+/// the spread between the furthest apart, in nanoseconds.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn tsc_cross_core_spread_ns(ns_per_cycle: f64) -> Option<f64> {
+    let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut allowed) } != 0 {
+        return None;
+    }
+    let cpus: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &allowed) })
+        .collect();
+
+    let reference = Instant::now();
+    let offsets: Vec<f64> = cpus.into_iter()
+        .filter_map(|cpu| {
+            std::thread::spawn(move || {
+                let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+                unsafe { libc::CPU_SET(cpu, &mut set) };
+                if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+                    return None;
+                }
+                // Offset of TSC time against the shared monotonic clock, from
+                // the tightest of several bracketed samples
+                (0..20)
+                    .map(|_| {
+                        let before = unsafe { rdtsc() };
+                        let mono_ns = reference.elapsed().as_nanos() as f64;
+                        let after = unsafe { rdtsc() };
+                        let tsc_ns = (before as f64 + after as f64) / 2.0 * ns_per_cycle;
+                        (after.wrapping_sub(before), tsc_ns - mono_ns)
+                    })
+                    .min_by_key(|&(width, _)| width)
+                    .map(|(_, offset)| offset)
+            })
+            .join()
+            .ok()
+            .flatten()
+        })
+        .collect();
+
+    let min = offsets.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = offsets.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    (!offsets.is_empty()).then_some(max - min)
+}
+
+/// Counter the high-resolution timer reads ticks from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Time stamp counter read with `RDTSC`
     #[cfg(target_arch = "x86_64")]
-    start_cycles: u64,
+    Tsc,
+    /// Generic timer virtual counter read from `CNTVCT_EL0`
+    #[cfg(target_arch = "aarch64")]
+    Cntvct,
+    /// Nanoseconds from `Instant` (monotonic clock)
+    Monotonic,
+}
 
-    #[cfg(not(target_arch = "x86_64"))]
-    start_instant: Instant,
+impl ClockSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            ClockSource::Tsc => "RDTSC",
+            #[cfg(target_arch = "aarch64")]
+            ClockSource::Cntvct => "CNTVCT_EL0",
+            ClockSource::Monotonic => "Instant",
+        }
+    }
+}
+
+/// How the timestamp counter is read. Serialized reads cost a few extra
+/// cycles but cannot be reordered around the code being measured. On aarch64
+/// both serialized modes read `CNTVCT_EL0` after an ISB, without a core id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum TimestampReadMode {
+    /// Plain RDTSC, unserialized
+    #[default]
+    Rdtsc,
+    /// LFENCE; RDTSC
+    LfenceRdtsc,
+    /// RDTSCP; LFENCE, also reporting the core the read ran on
+    Rdtscp,
+}
 
-    start_time_ns: u64,
+impl TimestampReadMode {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => TimestampReadMode::LfenceRdtsc,
+            2 => TimestampReadMode::Rdtscp,
+            _ => TimestampReadMode::Rdtsc,
+        }
+    }
+}
+
+/// A high-resolution timestamp and, in `Rdtscp` mode, the core it was taken on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HiresTimestamp {
+    pub ns: u64,
+    pub core: Option<u32>,
+}
+
+/// Linear mapping from counter ticks to nanoseconds since the Unix epoch.
+#[derive(Debug, Clone, Copy)]
+struct Calibration {
+    base_ticks: u64,
+    base_ns: u64,
+    ns_per_tick: f64,
+}
+
+impl Calibration {
+    fn to_ns(self, ticks: u64) -> u64 {
+        // Saturate so a read racing a recalibration never lands before the anchor
+        let elapsed_ticks = ticks.saturating_sub(self.base_ticks) as f64;
+        self.base_ns + (elapsed_ticks * self.ns_per_tick) as u64
+    }
+}
+
+/// Seqlock around the calibration so readers never block on recalibration.
+struct CalibrationCell {
+    seq: AtomicU64,
+    base_ticks: AtomicU64,
+    base_ns: AtomicU64,
+    ns_per_tick: AtomicU64,
+}
+
+impl CalibrationCell {
+    fn new(calibration: Calibration) -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            base_ticks: AtomicU64::new(calibration.base_ticks),
+            base_ns: AtomicU64::new(calibration.base_ns),
+            ns_per_tick: AtomicU64::new(calibration.ns_per_tick.to_bits()),
+        }
+    }
+
+    fn load(&self) -> Calibration {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let calibration = Calibration {
+                base_ticks: self.base_ticks.load(Ordering::Relaxed),
+                base_ns: self.base_ns.load(Ordering::Relaxed),
+                ns_per_tick: f64::from_bits(self.ns_per_tick.load(Ordering::Relaxed)),
+            };
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return calibration;
+            }
+        }
+    }
+
+    /// Single writer only; callers serialize through `HighResTimer::recalibration`.
+    fn store(&self, calibration: Calibration) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.base_ticks.store(calibration.base_ticks, Ordering::Relaxed);
+        self.base_ns.store(calibration.base_ns, Ordering::Relaxed);
+        self.ns_per_tick.store(calibration.ns_per_tick.to_bits(), Ordering::Relaxed);
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+}
+
+/// Precision report of the high-resolution timer, the same on every architecture.
+#[derive(Debug, Clone, Copy)]
+pub struct TimerPrecision {
+    pub source: ClockSource,
+    pub read_mode: TimestampReadMode,
+    /// Counter ticks per nanosecond (1.0 for `Instant`)
+    pub frequency_ghz: f64,
+    /// Smallest time step the timer can report
+    pub resolution_ns: f64,
+    /// Average cost of one timestamp read
+    pub overhead_ns: f64,
+}
+
+/// Latest drift measurement of the timer against `CLOCK_REALTIME`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DriftEstimate {
+    /// Rate error of the timer before the last recalibration, in ppm
+    /// (positive: the timer was running fast)
+    pub drift_ppm: f64,
+    /// Timer minus realtime at the last recalibration
+    pub offset_ns: i64,
+    pub recalibrations: u64,
+}
+
+struct RecalibrationState {
+    last_ticks: u64,
+    last_realtime_ns: u64,
+    smoothed_ns_per_tick: f64,
+    drift: DriftEstimate,
+}
+
+pub struct HighResTimer {
+    source: ClockSource,
+    read_mode: AtomicU8,
+    start_instant: Instant,
+    calibration: CalibrationCell,
+    recalibration: Mutex<RecalibrationState>,
 }
 
 impl HighResTimer {
     fn new() -> Self {
         tracing::info!("Calibrating high-resolution timer...");
 
-        let start_time = SystemTime::now();
-        let start_time_ns = start_time.duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+        let source = Self::select_source();
+        let mut timer = Self {
+            source,
+            read_mode: AtomicU8::new(TimestampReadMode::default() as u8),
+            start_instant: Instant::now(),
+            calibration: CalibrationCell::new(Calibration { base_ticks: 0, base_ns: 0, ns_per_tick: 1.0 }),
+            recalibration: Mutex::new(RecalibrationState {
+                last_ticks: 0,
+                last_realtime_ns: 0,
+                smoothed_ns_per_tick: 1.0,
+                drift: DriftEstimate::default(),
+            }),
+        };
+
+        let ns_per_tick = timer.calibrate();
 
         #[cfg(target_arch = "x86_64")]
-        {
-            let start_cycles = unsafe { rdtsc() };
+        if timer.source == ClockSource::Tsc {
+            tracing::info!("TSC frequency: ~{:.2} GHz", 1.0 / ns_per_tick);
+            tracing::info!("Timer resolution: ~{:.2} ns per cycle (RDTSC)", ns_per_tick);
 
-            std::thread::sleep(Duration::from_millis(100));
+            #[cfg(target_os = "linux")]
+            match tsc_cross_core_spread_ns(ns_per_tick) {
+                Some(spread) if spread > TSC_SYNC_TOLERANCE_NS => {
+                    tracing::warn!("TSC differs by {:.0}ns across cores, falling back to Instant", spread);
+                    timer.source = ClockSource::Monotonic;
+                    timer.calibrate();
+                }
+                Some(spread) => tracing::info!("TSC cross-core spread: {:.0}ns", spread),
+                None => tracing::warn!("Could not check TSC consistency across cores"),
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        if timer.source == ClockSource::Cntvct {
+            tracing::info!(
+                "Counter frequency: {:.3} MHz (CNTFRQ_EL0), {:.3} MHz measured",
+                cntfrq() as f64 / 1e6,
+                1e3 / ns_per_tick
+            );
+            tracing::info!("Timer resolution: ~{:.2} ns per tick (CNTVCT_EL0)", ns_per_tick);
+        }
 
-            let end_cycles = unsafe { rdtsc() };
-            let end_time = SystemTime::now();
-            let elapsed_ns = end_time.duration_since(start_time).unwrap().as_nanos() as f64;
-            let elapsed_cycles = end_cycles.wrapping_sub(start_cycles) as f64;
+        if timer.source == ClockSource::Monotonic {
+            tracing::info!("Architecture: {} (using Instant)", std::env::consts::ARCH);
+            tracing::info!("Timer resolution: ~1-10 ns (monotonic clock)");
+        }
 
-            let cycles_per_ns = elapsed_cycles / elapsed_ns;
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        let _ = ns_per_tick;
 
-            tracing::info!("CPU frequency: ~{:.2} GHz", cycles_per_ns);
-            tracing::info!("Timer resolution: ~{:.2} ns per cycle (RDTSC)", 1.0 / cycles_per_ns);
+        timer
+    }
 
-            Self {
-                cycles_per_ns,
-                start_cycles,
-                start_time_ns,
+    fn select_source() -> ClockSource {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if tsc_is_invariant() {
+                return ClockSource::Tsc;
             }
+            tracing::warn!("TSC is not invariant, falling back to Instant");
         }
 
-        #[cfg(not(target_arch = "x86_64"))]
+        #[cfg(target_arch = "aarch64")]
         {
-            let start_instant = Instant::now();
+            if cntfrq() > 0 {
+                return ClockSource::Cntvct;
+            }
+            tracing::warn!("CNTFRQ_EL0 is not set, falling back to Instant");
+        }
 
-            tracing::info!("Architecture: {} (using Instant)", std::env::consts::ARCH);
-            tracing::info!("Timer resolution: ~1-10 ns (monotonic clock)");
+        ClockSource::Monotonic
+    }
+
+    /// Measures the tick rate against realtime over `CALIBRATION_PERIOD` and
+    /// anchors the mapping at the end of it. Returns ns per tick.
+    fn calibrate(&mut self) -> f64 {
+        let (start_ticks, start_ns) = self.reference_sample();
+        std::thread::sleep(CALIBRATION_PERIOD);
+        let (end_ticks, end_ns) = self.reference_sample();
+
+        let ns_per_tick = (end_ns - start_ns) as f64 / end_ticks.wrapping_sub(start_ticks) as f64;
+
+        self.calibration.store(Calibration { base_ticks: end_ticks, base_ns: end_ns, ns_per_tick });
+        let state = self.recalibration.get_mut().unwrap();
+        state.last_ticks = end_ticks;
+        state.last_realtime_ns = end_ns;
+        state.smoothed_ns_per_tick = ns_per_tick;
+        ns_per_tick
+    }
+
+    fn read_ticks(&self) -> u64 {
+        self.read_ticks_with_core().0
+    }
+
+    fn read_ticks_with_core(&self) -> (u64, Option<u32>) {
+        match self.source {
+            #[cfg(target_arch = "x86_64")]
+            ClockSource::Tsc => match self.read_mode() {
+                TimestampReadMode::Rdtsc => (unsafe { rdtsc() }, None),
+                TimestampReadMode::LfenceRdtsc => (unsafe { rdtsc_lfence() }, None),
+                TimestampReadMode::Rdtscp => {
+                    let (ticks, aux) = unsafe { rdtscp() };
+                    (ticks, Some(aux & 0xfff))
+                }
+            },
+            #[cfg(target_arch = "aarch64")]
+            ClockSource::Cntvct => match self.read_mode() {
+                TimestampReadMode::Rdtsc => (unsafe { cntvct() }, None),
+                TimestampReadMode::LfenceRdtsc | TimestampReadMode::Rdtscp => (unsafe { cntvct_isb() }, None),
+            },
+            ClockSource::Monotonic => (self.start_instant.elapsed().as_nanos() as u64, None),
+        }
+    }
+
+    /// Ticks and realtime read as close together as possible: the tightest
+    /// bracket out of a few attempts, with ticks at its midpoint.
+    fn reference_sample(&self) -> (u64, u64) {
+        (0..5)
+            .map(|_| {
+                let before = self.read_ticks();
+                let realtime_ns = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+                let after = self.read_ticks();
+                (after.wrapping_sub(before), before + after.wrapping_sub(before) / 2, realtime_ns)
+            })
+            .min_by_key(|&(width, _, _)| width)
+            .map(|(_, ticks, realtime_ns)| (ticks, realtime_ns))
+            .unwrap()
+    }
+
+    /// Re-measures the tick rate and offset against realtime and installs a
+    /// new mapping anchored at the current reading, so time stays continuous.
+    /// Offsets are slewed out over the next interval instead of stepped; only
+    /// a large forward realtime step is applied at once.
+    pub fn recalibrate(&self) {
+        let mut state = self.recalibration.lock().unwrap();
+
+        let (ticks, realtime_ns) = self.reference_sample();
+        let current = self.calibration.load();
+        let timer_ns = current.to_ns(ticks);
+        let offset_ns = timer_ns as i64 - realtime_ns as i64;
+
+        let elapsed_ticks = ticks.wrapping_sub(state.last_ticks);
+        let elapsed_ns = realtime_ns.saturating_sub(state.last_realtime_ns);
+        if elapsed_ticks == 0 || elapsed_ns == 0 {
+            return;
+        }
+        let measured = elapsed_ns as f64 / elapsed_ticks as f64;
+        state.smoothed_ns_per_tick += RATE_SMOOTHING * (measured - state.smoothed_ns_per_tick);
 
-            Self {
-                start_instant,
-                start_time_ns,
+        let mut base_ns = timer_ns;
+        let mut slew = 0.0;
+        if offset_ns < -STEP_THRESHOLD_NS {
+            tracing::warn!("Realtime clock stepped forward by {}ms, resyncing", -offset_ns / 1_000_000);
+            base_ns = realtime_ns;
+        } else {
+            if offset_ns > STEP_THRESHOLD_NS {
+                tracing::warn!("Realtime clock stepped back by {}ms, slewing", offset_ns / 1_000_000);
             }
+            let interval_ns = RECALIBRATION_INTERVAL.as_nanos() as f64;
+            slew = (-offset_ns as f64 / interval_ns).clamp(-MAX_SLEW, MAX_SLEW);
         }
+
+        // Anchor at a fresh reading: timestamps taken since the sample still
+        // used the old mapping, and the new one must not start below them
+        let ns_per_tick = state.smoothed_ns_per_tick * (1.0 + slew);
+        let anchor_ticks = self.read_ticks();
+        let since_sample_ns = (anchor_ticks.wrapping_sub(ticks) as f64 * ns_per_tick) as u64;
+        self.calibration.store(Calibration {
+            base_ticks: anchor_ticks,
+            base_ns: (base_ns + since_sample_ns).max(current.to_ns(anchor_ticks)),
+            ns_per_tick,
+        });
+
+        state.last_ticks = ticks;
+        state.last_realtime_ns = realtime_ns;
+        state.drift = DriftEstimate {
+            drift_ppm: (current.ns_per_tick / measured - 1.0) * 1e6,
+            offset_ns,
+            recalibrations: state.drift.recalibrations + 1,
+        };
     }
 
-    fn now_ns(&self) -> u64 {
-        #[cfg(target_arch = "x86_64")]
-        {
-            let current_cycles = unsafe { rdtsc() };
-            let elapsed_cycles = current_cycles.wrapping_sub(self.start_cycles) as f64;
-            let elapsed_ns = elapsed_cycles / self.cycles_per_ns;
-            self.start_time_ns + elapsed_ns as u64
+    /// Current ticks-per-nanosecond of the mapping (GHz for the TSC).
+    pub fn ticks_per_ns(&self) -> f64 {
+        1.0 / self.calibration.load().ns_per_tick
+    }
+
+    /// Resolution and per-read overhead of the timer in its current read
+    /// mode. Takes a thousand or so timer reads to measure.
+    pub fn precision(&self) -> TimerPrecision {
+        const READS: u64 = 1_000;
+
+        let resolution_ns = if self.source == ClockSource::Monotonic {
+            // Smallest step Instant reports between consecutive reads
+            let mut previous = self.read_ticks();
+            let mut smallest = u64::MAX;
+            for _ in 0..READS {
+                let ticks = self.read_ticks();
+                if ticks > previous {
+                    smallest = smallest.min(ticks - previous);
+                }
+                previous = ticks;
+            }
+            smallest as f64 * self.calibration.load().ns_per_tick
+        } else {
+            self.calibration.load().ns_per_tick
+        };
+
+        let start = self.now_ns();
+        let mut sink = 0u64;
+        for _ in 0..READS {
+            sink ^= self.now().ns;
         }
+        std::hint::black_box(sink);
+        let overhead_ns = self.now_ns().saturating_sub(start) as f64 / READS as f64;
 
-        #[cfg(not(target_arch = "x86_64"))]
-        {
-            let elapsed = self.start_instant.elapsed();
-            self.start_time_ns + elapsed.as_nanos() as u64
+        TimerPrecision {
+            source: self.source,
+            read_mode: self.read_mode(),
+            frequency_ghz: self.ticks_per_ns(),
+            resolution_ns,
+            overhead_ns,
+        }
+    }
+
+    pub fn drift(&self) -> DriftEstimate {
+        self.recalibration.lock().unwrap().drift
+    }
+
+    pub fn read_mode(&self) -> TimestampReadMode {
+        TimestampReadMode::from_u8(self.read_mode.load(Ordering::Relaxed))
+    }
+
+    /// Selects how the TSC is read. Has no effect on the `Instant` fallback.
+    /// `Rdtscp` falls back to `LfenceRdtsc` on CPUs without RDTSCP.
+    pub fn set_read_mode(&self, mode: TimestampReadMode) {
+        #[allow(unused_mut)]
+        let mut mode = mode;
+        #[cfg(target_arch = "x86_64")]
+        if mode == TimestampReadMode::Rdtscp && !rdtscp_supported() {
+            tracing::warn!("RDTSCP not supported, using LFENCE+RDTSC");
+            mode = TimestampReadMode::LfenceRdtsc;
         }
+        self.read_mode.store(mode as u8, Ordering::Relaxed);
+    }
+
+    fn now_ns(&self) -> u64 {
+        let calibration = self.calibration.load();
+        calibration.to_ns(self.read_ticks())
+    }
+
+    fn now(&self) -> HiresTimestamp {
+        let calibration = self.calibration.load();
+        let (ticks, core) = self.read_ticks_with_core();
+        HiresTimestamp { ns: calibration.to_ns(ticks), core }
     }
 }
 
+pub fn high_res_timer() -> &'static HighResTimer {
+    let timer = HIGH_RES_TIMER.get_or_init(HighResTimer::new);
+    RECALIBRATION_THREAD.call_once(|| {
+        std::thread::Builder::new()
+            .name("hires-recalibration".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(RECALIBRATION_INTERVAL);
+                timer.recalibrate();
+            })
+            .expect("failed to spawn timer recalibration thread");
+    });
+    timer
+}
+
 pub fn current_timestamp_ns_hires() -> u64 {
-    HIGH_RES_TIMER.get_or_init(HighResTimer::new).now_ns()
+    high_res_timer().now_ns()
+}
+
+/// Like `current_timestamp_ns_hires`, also returning the core id when the
+/// timer reads in `Rdtscp` mode.
+pub fn current_timestamp_hires() -> HiresTimestamp {
+    high_res_timer().now()
+}
+
+/// Parses an ISO 8601 / RFC 3339 UTC time (`YYYY-MM-DDTHH:MM:SS[.fraction]Z`) into ns
+/// since the epoch.
+pub fn parse_utc_time_ns(time: &str) -> Option<u64> {
+    let (date, clock) = time.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+    let mut clock = clock.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // Right-pad to nanoseconds, dropping digits beyond them
+    let nanos = fraction.bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0i64, |nanos, digit| nanos * 10 + (digit - b'0') as i64);
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second;
+    u64::try_from(seconds * 1_000_000_000 + nanos).ok()
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
 }
 
 #[derive(Debug)]
@@ -155,3 +715,371 @@ impl LatencyStats {
         self.max_latency_ns as f64 / 1_000_000.0
     }
 }
+
+// Log-linear buckets: values below 2^7 are exact, above that each power of
+// two is split into 64 buckets (under 1.6% relative error)
+const HISTOGRAM_SUB_BUCKET_BITS: u32 = 6;
+const HISTOGRAM_SUB_BUCKETS: usize = 1 << HISTOGRAM_SUB_BUCKET_BITS;
+const HISTOGRAM_BUCKETS: usize = (63 - HISTOGRAM_SUB_BUCKET_BITS as usize) * HISTOGRAM_SUB_BUCKETS + 2 * HISTOGRAM_SUB_BUCKETS;
+
+/// Fixed-size latency histogram for percentiles over a whole run, without
+/// keeping every sample.
+pub struct LatencyHistogram {
+    counts: Box<[u64]>,
+    count: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; HISTOGRAM_BUCKETS].into_boxed_slice(),
+            count: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency_ns: u64) {
+        self.counts[Self::bucket(latency_ns)] += 1;
+        self.count += 1;
+    }
+
+    /// Latency at quantile `q` (0.0..=1.0) in nanoseconds, or `None` when empty.
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(Self::bucket_value(bucket));
+            }
+        }
+        None
+    }
+
+    pub fn percentile_ms(&self, q: f64) -> f64 {
+        self.percentile(q).unwrap_or_default() as f64 / 1_000_000.0
+    }
+
+    fn bucket(value: u64) -> usize {
+        let shift = (64 - value.leading_zeros()).saturating_sub(HISTOGRAM_SUB_BUCKET_BITS + 1);
+        (shift as usize) * HISTOGRAM_SUB_BUCKETS + (value >> shift) as usize
+    }
+
+    /// Midpoint of the values that fall into `bucket`.
+    fn bucket_value(bucket: usize) -> u64 {
+        let shift = (bucket / HISTOGRAM_SUB_BUCKETS).saturating_sub(1) as u32;
+        let mantissa = (bucket - shift as usize * HISTOGRAM_SUB_BUCKETS) as u64;
+        (mantissa << shift) + ((1u64 << shift) >> 1)
+    }
+}
+
+/// Points along the receive pipeline at which an update is timestamped, in
+/// the order an update passes through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Timestamp assigned by the exchange
+    Exchange,
+    /// Kernel receive timestamp of the bytes that completed the frame
+    KernelReceived,
+    /// Socket read (and TLS decryption) that completed the frame returned
+    FrameReceived,
+    /// Frame decoded into a message and handed to the application
+    FrameDecoded,
+    JsonParsed,
+    BookApplied,
+    EventPublished,
+}
+
+pub const STAGE_COUNT: usize = 7;
+
+impl Stage {
+    pub const ALL: [Stage; STAGE_COUNT] = [
+        Stage::Exchange,
+        Stage::KernelReceived,
+        Stage::FrameReceived,
+        Stage::FrameDecoded,
+        Stage::JsonParsed,
+        Stage::BookApplied,
+        Stage::EventPublished,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Exchange => "exchange",
+            Stage::KernelReceived => "kernel rx",
+            Stage::FrameReceived => "frame rx",
+            Stage::FrameDecoded => "decoded",
+            Stage::JsonParsed => "parsed",
+            Stage::BookApplied => "book",
+            Stage::EventPublished => "published",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Timestamps (ns since the Unix epoch) collected for one update as it moves
+/// through the pipeline. Stages that were not reached stay unset.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimestamps {
+    timestamps: [Option<u64>; STAGE_COUNT],
+    cores: [Option<u32>; STAGE_COUNT],
+}
+
+impl StageTimestamps {
+    pub fn set(&mut self, stage: Stage, timestamp_ns: u64) {
+        self.timestamps[stage.index()] = Some(timestamp_ns);
+    }
+
+    /// Like `set`, for a high-resolution timestamp taken elsewhere on `core`.
+    pub fn set_on_core(&mut self, stage: Stage, timestamp_ns: u64, core: Option<u32>) {
+        self.timestamps[stage.index()] = Some(timestamp_ns);
+        self.cores[stage.index()] = core;
+    }
+
+    /// Records the current high-resolution time for `stage`, along with the
+    /// core it was taken on when the timer reads in `Rdtscp` mode.
+    pub fn mark(&mut self, stage: Stage) {
+        let now = current_timestamp_hires();
+        self.timestamps[stage.index()] = Some(now.ns);
+        self.cores[stage.index()] = now.core;
+    }
+
+    pub fn get(&self, stage: Stage) -> Option<u64> {
+        self.timestamps[stage.index()]
+    }
+
+    /// True if the stages of this update were timestamped on different cores,
+    /// i.e. the thread migrated mid-update and TSC deltas may be skewed.
+    pub fn migrated(&self) -> bool {
+        let mut cores = self.cores.iter().flatten();
+        match cores.next() {
+            Some(first) => cores.any(|core| core != first),
+            None => false,
+        }
+    }
+}
+
+/// Latency statistics for every stage (measured from the exchange timestamp)
+/// and for every pair of stages, so regressions can be pinned to the
+/// network, TLS, parser or book.
+#[derive(Debug)]
+pub struct PipelineStats {
+    per_stage: Vec<LatencyStats>,
+    // Indexed by from * STAGE_COUNT + to, only filled for from < to
+    per_pair: Vec<LatencyStats>,
+    /// Updates whose stages were timestamped on more than one core
+    pub migrated: u64,
+}
+
+impl Default for PipelineStats {
+    fn default() -> Self {
+        Self {
+            per_stage: (0..STAGE_COUNT).map(|_| LatencyStats::default()).collect(),
+            per_pair: (0..STAGE_COUNT * STAGE_COUNT).map(|_| LatencyStats::default()).collect(),
+            migrated: 0,
+        }
+    }
+}
+
+impl PipelineStats {
+    pub fn record(&mut self, timestamps: &StageTimestamps) {
+        let exchange_ns = timestamps.get(Stage::Exchange);
+        if timestamps.migrated() {
+            self.migrated += 1;
+        }
+
+        for from in Stage::ALL {
+            let Some(from_ns) = timestamps.get(from) else {
+                continue;
+            };
+
+            if let Some(exchange_ns) = exchange_ns {
+                if from != Stage::Exchange {
+                    self.per_stage[from.index()].add_measurement(from_ns.saturating_sub(exchange_ns));
+                }
+            }
+
+            for to in &Stage::ALL[from.index() + 1..] {
+                if let Some(to_ns) = timestamps.get(*to) {
+                    self.per_pair[from.index() * STAGE_COUNT + to.index()]
+                        .add_measurement(to_ns.saturating_sub(from_ns));
+                }
+            }
+        }
+    }
+
+    /// Latency from the exchange timestamp to `stage`.
+    pub fn stage(&self, stage: Stage) -> &LatencyStats {
+        &self.per_stage[stage.index()]
+    }
+
+    /// Latency from stage `from` to the later stage `to`.
+    pub fn pair(&self, from: Stage, to: Stage) -> &LatencyStats {
+        &self.per_pair[from.index() * STAGE_COUNT + to.index()]
+    }
+
+    /// Stages that have been reached at least once, in pipeline order.
+    pub fn active_stages(&self) -> Vec<Stage> {
+        Stage::ALL
+            .into_iter()
+            .filter(|&stage| {
+                self.stage(stage).count > 0
+                    || Stage::ALL.iter().any(|&other| {
+                        self.pair(stage, other).count > 0 || self.pair(other, stage).count > 0
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recalibration_keeps_time_monotonic() {
+        let timer = high_res_timer();
+        for _ in 0..100 {
+            let before = timer.now_ns();
+            timer.recalibrate();
+            let after = timer.now_ns();
+            assert!(after >= before, "time went back {} ns", before - after);
+        }
+    }
+
+    #[test]
+    fn read_to_decode_migration_is_flagged() {
+        let mut stages = StageTimestamps::default();
+        stages.set_on_core(Stage::FrameReceived, 100, Some(2));
+        stages.set_on_core(Stage::FrameDecoded, 150, Some(2));
+        assert!(!stages.migrated());
+
+        stages.set_on_core(Stage::JsonParsed, 200, Some(5));
+        assert!(stages.migrated());
+    }
+
+    #[test]
+    fn histogram_buckets_are_exact_below_128() {
+        for value in 0..128 {
+            assert_eq!(LatencyHistogram::bucket(value), value as usize);
+            assert_eq!(LatencyHistogram::bucket_value(value as usize), value);
+        }
+        // Above that, pairs of values share a bucket, then fours, ...
+        assert_eq!(LatencyHistogram::bucket(128), 128);
+        assert_eq!(LatencyHistogram::bucket(129), 128);
+        assert_eq!(LatencyHistogram::bucket(130), 129);
+        assert_eq!(LatencyHistogram::bucket_value(128), 129);
+        assert_eq!(LatencyHistogram::bucket(256), LatencyHistogram::bucket(255) + 1);
+        assert_eq!(LatencyHistogram::bucket(259), LatencyHistogram::bucket(256));
+        assert_eq!(LatencyHistogram::bucket(260), LatencyHistogram::bucket(256) + 1);
+    }
+
+    #[test]
+    fn histogram_buckets_are_contiguous_up_to_u64_max() {
+        for bits in 7..64 {
+            let power = 1u64 << bits;
+            assert_eq!(LatencyHistogram::bucket(power), LatencyHistogram::bucket(power - 1) + 1, "2^{}", bits);
+        }
+        assert_eq!(LatencyHistogram::bucket(u64::MAX), HISTOGRAM_BUCKETS - 1);
+
+        for value in [1_000, 123_456, 5_000_000, 987_654_321, 1 << 40, u64::MAX / 3] {
+            let estimate = LatencyHistogram::bucket_value(LatencyHistogram::bucket(value));
+            let error = estimate.abs_diff(value) as f64 / value as f64;
+            assert!(error < 0.016, "{} reported as {}", value, estimate);
+        }
+    }
+
+    #[test]
+    fn histogram_percentiles() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentile(0.5), None);
+
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.percentile(0.0), Some(1));
+        assert_eq!(histogram.percentile(0.50), Some(50));
+        assert_eq!(histogram.percentile(0.90), Some(90));
+        assert_eq!(histogram.percentile(0.99), Some(99));
+        assert_eq!(histogram.percentile(0.999), Some(100));
+        assert_eq!(histogram.percentile(1.0), Some(100));
+    }
+
+    #[test]
+    fn histogram_percentiles_of_a_tail() {
+        let mut histogram = LatencyHistogram::default();
+        for _ in 0..990 {
+            histogram.record(1_000_000);
+        }
+        for _ in 0..9 {
+            histogram.record(20_000_000);
+        }
+        histogram.record(500_000_000);
+
+        let bucketed = |ns| LatencyHistogram::bucket_value(LatencyHistogram::bucket(ns));
+        assert_eq!(histogram.percentile(0.50), Some(bucketed(1_000_000)));
+        assert_eq!(histogram.percentile(0.90), Some(bucketed(1_000_000)));
+        assert_eq!(histogram.percentile(0.99), Some(bucketed(1_000_000)));
+        assert_eq!(histogram.percentile(0.995), Some(bucketed(20_000_000)));
+        assert_eq!(histogram.percentile(0.999), Some(bucketed(20_000_000)));
+        assert_eq!(histogram.percentile(1.0), Some(bucketed(500_000_000)));
+        assert!((histogram.percentile_ms(0.50) - 1.0).abs() < 0.016);
+    }
+
+    fn stages(timestamps: &[(Stage, u64)]) -> StageTimestamps {
+        let mut stages = StageTimestamps::default();
+        for &(stage, ns) in timestamps {
+            stages.set(stage, ns);
+        }
+        stages
+    }
+
+    #[test]
+    fn pipeline_skips_missing_stages() {
+        let mut pipeline = PipelineStats::default();
+        pipeline.record(&stages(&[
+            (Stage::Exchange, 1_000),
+            (Stage::FrameReceived, 1_500),
+            (Stage::JsonParsed, 1_700),
+            (Stage::EventPublished, 2_000),
+        ]));
+
+        assert_eq!(pipeline.stage(Stage::FrameReceived).total_latency_ns, 500);
+        assert_eq!(pipeline.stage(Stage::JsonParsed).total_latency_ns, 700);
+        assert_eq!(pipeline.stage(Stage::EventPublished).total_latency_ns, 1_000);
+        assert_eq!(pipeline.stage(Stage::KernelReceived).count, 0);
+        assert_eq!(pipeline.stage(Stage::BookApplied).count, 0);
+
+        // Deltas bridge the stages that were not reached
+        assert_eq!(pipeline.pair(Stage::FrameReceived, Stage::JsonParsed).total_latency_ns, 200);
+        assert_eq!(pipeline.pair(Stage::JsonParsed, Stage::EventPublished).total_latency_ns, 300);
+        assert_eq!(pipeline.pair(Stage::FrameReceived, Stage::FrameDecoded).count, 0);
+        assert_eq!(pipeline.pair(Stage::JsonParsed, Stage::BookApplied).count, 0);
+        assert_eq!(
+            pipeline.active_stages(),
+            [Stage::Exchange, Stage::FrameReceived, Stage::JsonParsed, Stage::EventPublished]
+        );
+        assert_eq!(pipeline.migrated, 0);
+    }
+
+    #[test]
+    fn pipeline_without_exchange_time_only_records_deltas() {
+        let mut pipeline = PipelineStats::default();
+        pipeline.record(&stages(&[(Stage::FrameReceived, 1_500), (Stage::FrameDecoded, 1_600)]));
+        pipeline.record(&stages(&[(Stage::FrameReceived, 3_000), (Stage::FrameDecoded, 3_300)]));
+
+        assert!(Stage::ALL.iter().all(|&stage| pipeline.stage(stage).count == 0));
+        let decode = pipeline.pair(Stage::FrameReceived, Stage::FrameDecoded);
+        assert_eq!((decode.count, decode.total_latency_ns), (2, 400));
+        assert_eq!(pipeline.active_stages(), [Stage::FrameReceived, Stage::FrameDecoded]);
+    }
+}
+
@@ -1,30 +1,27 @@
+mod config;
 mod subscriber;
 mod refdata;
 mod websocket;
 
-use std::{collections::HashMap, env};
+use std::{env, path::PathBuf};
 
-use subscriber::SubscriptionMeta;
+use config::Config;
 
 
 #[tokio::main]
 async fn main() {
-    let mut referential_data_path = env::var("REF_DATA_PATH").unwrap_or_else(|_| {
-        println!("REF_DATA_PATH not set, using default");
-        "data".to_string()
+    let config_path = env::var("CEX_CONFIG").map(PathBuf::from).unwrap_or_else(|_| {
+        println!("CEX_CONFIG not set, using default");
+        PathBuf::from("config.toml")
     });
 
-    let sources: HashMap<String, SubscriptionMeta> = HashMap::from(
-        [("okx-perp".to_string(),
-        SubscriptionMeta {
-            exchange: "okx".to_string(),
-            channels: vec![],
-            ws_url: "".to_string(),
-            rest_url: "".to_string(),
-            max_symbols_per_sub: 200,
-            refdata_path: format!("{}/{}", referential_data_path, "okx.parquet"),
-        })]
-    );
+    let sources = match Config::load(&config_path) {
+        Ok(config) => config.sources,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
 
     let mut subscriber_managers = Vec::new();
 }
@@ -0,0 +1,407 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cli::{OutputFormat, ReportArgs};
+use crate::export::{ExportFormat, Sample, SampleExporter};
+use crate::latency::{self, LatencyHistogram, LatencyStats, PipelineStats, Stage, StageTimestamps};
+use crate::metrics::{self, Counter, Histogram};
+
+// Always print the first few updates, whatever their latency
+const ALWAYS_PRINT_FIRST: u64 = 5;
+
+// Metric handles for one channel/symbol, looked up the first time it is seen
+struct Feed {
+    channel: Arc<str>,
+    symbol: Arc<str>,
+    latency: Arc<Histogram>,
+    messages: Arc<Counter>,
+}
+
+/// Latency statistics, metrics and sample export for one exchange feed.
+///
+/// End-to-end latency runs from the exchange timestamp to the last stage an
+/// update reached: parsing when measuring live, the book update in `book`
+/// mode and the recorded receive time when replaying.
+pub struct Measurement {
+    exchange: &'static str,
+    stats_interval: Duration,
+    print_threshold_ns: u64,
+    format: OutputFormat,
+    feeds: Vec<Feed>,
+    stage_metrics: Vec<(Stage, Arc<Histogram>)>,
+    frame_bytes: Arc<Histogram>,
+    parse_errors: Arc<Counter>,
+    stats: LatencyStats,
+    histogram: LatencyHistogram,
+    pipeline: PipelineStats,
+    exporter: Option<SampleExporter>,
+    started: Instant,
+    last_stats_print: Instant,
+    receive_span_ns: Option<(u64, u64)>,
+}
+
+impl Measurement {
+    pub fn new(exchange: &'static str, report: &ReportArgs) -> std::io::Result<Self> {
+        let exporter = match &report.samples {
+            Some(path) => Some(SampleExporter::create(path, ExportFormat::from_path(Path::new(path)))?),
+            None => None,
+        };
+        let registry = metrics::metrics();
+
+        Ok(Self {
+            exchange,
+            stats_interval: report.stats_interval,
+            print_threshold_ns: (report.print_threshold_ms * 1_000_000.0) as u64,
+            format: report.format,
+            feeds: Vec::new(),
+            stage_metrics: Stage::ALL[1..].iter()
+                .map(|&stage| (stage, registry.stage_latency(exchange, stage)))
+                .collect(),
+            frame_bytes: registry.frame_bytes(exchange),
+            parse_errors: registry.parse_errors(exchange),
+            stats: LatencyStats::default(),
+            histogram: LatencyHistogram::default(),
+            pipeline: PipelineStats::default(),
+            exporter,
+            started: Instant::now(),
+            last_stats_print: Instant::now(),
+            receive_span_ns: None,
+        })
+    }
+
+    /// Counts a received data message of `bytes` bytes.
+    pub fn message_received(&self, bytes: u64) {
+        self.frame_bytes.observe(bytes);
+    }
+
+    pub fn parse_error(&self) {
+        self.parse_errors.inc();
+    }
+
+    /// Records one update. `stages` must include the exchange timestamp.
+    pub fn record(&mut self, channel: &str, symbol: &str, stages: &StageTimestamps, message_bytes: u64) {
+        let Some(exchange_ns) = stages.get(Stage::Exchange) else {
+            return;
+        };
+        let Some(local_ns) = Stage::ALL[1..].iter().rev().find_map(|&stage| stages.get(stage)) else {
+            return;
+        };
+        let latency_ns = local_ns.saturating_sub(exchange_ns);
+        let receive_ns = stages.get(Stage::FrameReceived).unwrap_or(local_ns);
+
+        self.stats.add_measurement(latency_ns);
+        self.histogram.record(latency_ns);
+        self.pipeline.record(stages);
+        self.receive_span_ns = Some(match self.receive_span_ns {
+            Some((first, last)) => (first.min(receive_ns), last.max(receive_ns)),
+            None => (receive_ns, receive_ns),
+        });
+
+        for (stage, histogram) in &self.stage_metrics {
+            if let Some(ns) = stages.get(*stage) {
+                histogram.observe(ns.saturating_sub(exchange_ns));
+            }
+        }
+
+        let feed = self.feed(channel, symbol);
+        feed.messages.inc();
+        feed.latency.observe(latency_ns);
+        let (channel, symbol) = (feed.channel.clone(), feed.symbol.clone());
+
+        if let Some(exporter) = &mut self.exporter {
+            exporter.record(Sample {
+                receive_ns,
+                exchange_ns,
+                latency_ns,
+                channel: channel.clone(),
+                symbol: symbol.clone(),
+                message_bytes,
+            });
+        }
+
+        if self.stats.count <= ALWAYS_PRINT_FIRST || latency_ns > self.print_threshold_ns {
+            let latency_ms = latency_ns as f64 / 1_000_000.0;
+            match self.format {
+                OutputFormat::Text => println!("Update #{}: {:.3}ms latency", self.stats.count, latency_ms),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({
+                        "type": "update",
+                        "exchange": self.exchange,
+                        "channel": &*channel,
+                        "symbol": &*symbol,
+                        "update": self.stats.count,
+                        "latency_ms": latency_ms,
+                    })
+                ),
+            }
+        }
+    }
+
+    fn feed(&mut self, channel: &str, symbol: &str) -> &Feed {
+        let index = match self.feeds.iter().position(|feed| &*feed.channel == channel && &*feed.symbol == symbol) {
+            Some(index) => index,
+            None => {
+                let registry = metrics::metrics();
+                self.feeds.push(Feed {
+                    channel: channel.into(),
+                    symbol: symbol.into(),
+                    latency: registry.latency(self.exchange, channel, symbol),
+                    messages: registry.messages(self.exchange, channel, symbol),
+                });
+                self.feeds.len() - 1
+            }
+        };
+        &self.feeds[index]
+    }
+
+    /// Prints periodic statistics once every stats interval.
+    pub fn maybe_print_stats(&mut self) {
+        if self.last_stats_print.elapsed() >= self.stats_interval && self.stats.count > 0 {
+            match self.format {
+                OutputFormat::Text => print_stats(&self.stats, &self.pipeline, self.stats_interval.as_secs()),
+                OutputFormat::Json => println!("{}", self.stats_json("stats")),
+            }
+            self.last_stats_print = Instant::now();
+        }
+    }
+
+    /// Flushes the sample export and prints the final report.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if let Some(exporter) = self.exporter.take() {
+            let summary = exporter.finish()?;
+            tracing::info!(
+                "Exported {} latency samples ({} dropped)",
+                summary.written, summary.dropped
+            );
+        }
+
+        if self.stats.count > 0 {
+            let span_secs = self.receive_span_ns
+                .map(|(first, last)| (last - first) as f64 / 1_000_000_000.0)
+                .unwrap_or_default();
+            match self.format {
+                OutputFormat::Text => {
+                    print_final_stats(&self.stats, &self.histogram, self.started.elapsed(), span_secs);
+                    print_stage_breakdown(&self.pipeline);
+                }
+                OutputFormat::Json => {
+                    let mut report = self.stats_json("final");
+                    report["uptime_secs"] = self.started.elapsed().as_secs_f64().into();
+                    report["updates_per_sec"] = rate(self.stats.count, span_secs).into();
+                    for (name, q) in [("p50_ms", 0.50), ("p90_ms", 0.90), ("p99_ms", 0.99), ("p999_ms", 0.999)] {
+                        report[name] = self.histogram.percentile_ms(q).into();
+                    }
+                    println!("{}", report);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn stats_json(&self, kind: &str) -> serde_json::Value {
+        let stages: Vec<serde_json::Value> = self.pipeline.active_stages()
+            .windows(2)
+            .filter(|pair| self.pipeline.pair(pair[0], pair[1]).count > 0)
+            .map(|pair| {
+                let stats = self.pipeline.pair(pair[0], pair[1]);
+                serde_json::json!({
+                    "from": pair[0].name(),
+                    "to": pair[1].name(),
+                    "avg_ms": stats.average_latency_ms(),
+                    "min_ms": stats.min_latency_ms(),
+                    "max_ms": stats.max_latency_ms(),
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "type": kind,
+            "exchange": self.exchange,
+            "count": self.stats.count,
+            "avg_ms": self.stats.average_latency_ms(),
+            "recent_avg_ms": self.stats.recent_average_ms(),
+            "min_ms": self.stats.min_latency_ms(),
+            "max_ms": self.stats.max_latency_ms(),
+            "stages": stages,
+            "thread_migrations": self.pipeline.migrated,
+        })
+    }
+}
+
+fn rate(count: u64, span_secs: f64) -> f64 {
+    if span_secs > 0.0 {
+        count as f64 / span_secs
+    } else {
+        0.0
+    }
+}
+
+fn print_stats(stats: &LatencyStats, pipeline: &PipelineStats, interval_secs: u64) {
+    println!("\n=== Latency Statistics (last {}s) ===", interval_secs);
+    println!("  Total measurements: {}", stats.count);
+    println!("  Average:            {:.3}ms", stats.average_latency_ms());
+    println!("  Recent avg (10):    {:.3}ms", stats.recent_average_ms());
+    println!("  Min:                {:.3}ms", stats.min_latency_ms());
+    println!("  Max:                {:.3}ms", stats.max_latency_ms());
+
+    if stats.last_10.len() >= 5 {
+        let recent: Vec<String> = stats.last_10.iter()
+            .map(|&ns| format!("{:.3}ms", ns as f64 / 1_000_000.0))
+            .collect();
+        println!("  Recent:             [{}]", recent.join(", "));
+    }
+
+    if stats.count > 10 {
+        let last_10: Vec<u64> = stats.last_10.iter().copied().collect();
+        let std_dev = calculate_std_dev(&last_10);
+        println!("  Std dev (recent):   {:.3}ms", std_dev / 1_000_000.0);
+    }
+
+    print_stage_breakdown(pipeline);
+
+    if let Some(timer) = latency::HIGH_RES_TIMER.get() {
+        let precision = timer.precision();
+        if precision.source == latency::ClockSource::Monotonic {
+            println!("  Timer:              {}", precision.source.name());
+        } else {
+            println!(
+                "  Timer:              {} at {:.3} GHz ({:?} reads)",
+                precision.source.name(), precision.frequency_ghz, precision.read_mode
+            );
+        }
+        println!(
+            "  Timer precision:    {:.1}ns resolution, {:.1}ns per read",
+            precision.resolution_ns, precision.overhead_ns
+        );
+        let drift = timer.drift();
+        if drift.recalibrations > 0 {
+            println!(
+                "  Clock drift:        {:+.2}ppm vs realtime (offset {:+}ns)",
+                drift.drift_ppm, drift.offset_ns
+            );
+        }
+    }
+
+    println!();
+}
+
+fn print_stage_breakdown(pipeline: &PipelineStats) {
+    let stages = pipeline.active_stages();
+    if stages.len() < 2 {
+        return;
+    }
+
+    println!(
+        "  Stage breakdown:          {:>10} {:>10} {:>10} {:>10} {:>12}",
+        "avg", "recent", "min", "max", "since exch"
+    );
+    for pair in stages.windows(2) {
+        let stats = pipeline.pair(pair[0], pair[1]);
+        if stats.count == 0 {
+            continue;
+        }
+        let since_exchange = pipeline.stage(pair[1]);
+        println!(
+            "    {:<24}{:>8.3}ms {:>8.3}ms {:>8.3}ms {:>8.3}ms {:>10.3}ms",
+            format!("{} -> {}", pair[0].name(), pair[1].name()),
+            stats.average_latency_ms(),
+            stats.recent_average_ms(),
+            stats.min_latency_ms(),
+            stats.max_latency_ms(),
+            since_exchange.average_latency_ms(),
+        );
+    }
+    if pipeline.migrated > 0 {
+        println!("    Thread migrations:    {} updates", pipeline.migrated);
+    }
+}
+
+fn print_final_stats(stats: &LatencyStats, histogram: &LatencyHistogram, uptime: Duration, span_secs: f64) {
+    println!("\n=== Final Latency Report ===");
+    println!("  Uptime:        {}", format_uptime(uptime));
+    println!("  Total updates: {} ({:.1}/s)", stats.count, rate(stats.count, span_secs));
+    println!("  Average:       {:.3}ms", stats.average_latency_ms());
+    println!("  Best:          {:.3}ms", stats.min_latency_ms());
+    println!("  p50:           {:.3}ms", histogram.percentile_ms(0.50));
+    println!("  p90:           {:.3}ms", histogram.percentile_ms(0.90));
+    println!("  p99:           {:.3}ms", histogram.percentile_ms(0.99));
+    println!("  p99.9:         {:.3}ms", histogram.percentile_ms(0.999));
+    println!("  Worst:         {:.3}ms", stats.max_latency_ms());
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn calculate_std_dev(values: &[u64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+
+    let mean = values.iter().sum::<u64>() as f64 / values.len() as f64;
+    let variance = values.iter()
+        .map(|&x| {
+            let diff = x as f64 - mean;
+            diff * diff
+        })
+        .sum::<f64>() / values.len() as f64;
+
+    variance.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement() -> Measurement {
+        let report = ReportArgs {
+            stats_interval: Duration::from_secs(5),
+            print_threshold_ms: 100.0,
+            format: OutputFormat::Text,
+            samples: None,
+        };
+        Measurement::new("test", &report).unwrap()
+    }
+
+    fn stages(timestamps: &[(Stage, u64)]) -> StageTimestamps {
+        let mut stages = StageTimestamps::default();
+        for &(stage, ns) in timestamps {
+            stages.set(stage, ns);
+        }
+        stages
+    }
+
+    #[test]
+    fn latency_runs_to_the_last_stage_reached() {
+        let mut measurement = measurement();
+        measurement.record("books", "BTC-USDT", &stages(&[
+            (Stage::Exchange, 1_000),
+            (Stage::FrameReceived, 1_500),
+            (Stage::JsonParsed, 1_800),
+        ]), 100);
+        measurement.record("books", "BTC-USDT", &stages(&[
+            (Stage::Exchange, 2_000),
+            (Stage::FrameReceived, 2_500),
+            (Stage::JsonParsed, 2_800),
+            (Stage::EventPublished, 3_500),
+        ]), 100);
+        // Replay keeps only the recorded receive time
+        measurement.record("books", "BTC-USDT", &stages(&[(Stage::Exchange, 4_000), (Stage::FrameReceived, 4_200)]), 100);
+
+        assert_eq!(measurement.stats.last_10, [800, 1_500, 200]);
+        assert_eq!(measurement.receive_span_ns, Some((1_500, 4_200)));
+    }
+
+    #[test]
+    fn skips_updates_without_exchange_or_local_time() {
+        let mut measurement = measurement();
+        measurement.record("books", "BTC-USDT", &stages(&[(Stage::FrameReceived, 1_500)]), 100);
+        measurement.record("books", "BTC-USDT", &stages(&[(Stage::Exchange, 1_000)]), 100);
+
+        assert_eq!(measurement.stats.count, 0);
+        assert_eq!(measurement.receive_span_ns, None);
+    }
+}
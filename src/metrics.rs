@@ -0,0 +1,481 @@
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::latency::Stage;
+
+pub static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+// Latency bucket upper bounds in nanoseconds, 1µs to 2.5s
+const LATENCY_BUCKETS_NS: &[u64] = &[
+    1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000, 25_000_000, 50_000_000,
+    100_000_000, 250_000_000, 500_000_000, 1_000_000_000, 2_500_000_000,
+];
+// Frame size bucket upper bounds in bytes
+const FRAME_SIZE_BUCKETS: &[u64] = &[64, 256, 1024, 4096, 16_384, 65_536, 262_144, 1_048_576];
+
+const NS_PER_SECOND: f64 = 1_000_000_000.0;
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Fixed-bucket histogram over integer observations (nanoseconds, bytes).
+/// Values are exported divided by `unit`, e.g. 1e9 to report seconds.
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [u64],
+    unit: f64,
+    // One per bound plus the +Inf bucket; not cumulative
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64], unit: f64) -> Self {
+        Self {
+            bounds,
+            unit,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        let bucket = self.bounds.partition_point(|&bound| bound < value);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Series {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+#[derive(Debug)]
+struct Family {
+    help: &'static str,
+    kind: &'static str,
+    // Keyed by rendered label set, e.g. `exchange="okx",symbol="BTC-USDT"`
+    series: BTreeMap<String, Series>,
+}
+
+/// Registry of connector metrics, rendered in the Prometheus text format.
+///
+/// Look up a metric handle once (this takes a lock and allocates), then
+/// update it from the hot path with plain atomic operations.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+impl Metrics {
+    /// Latency from the exchange timestamp to the last stage a message
+    /// reached, normally its publication.
+    pub fn latency(&self, exchange: &str, channel: &str, symbol: &str) -> Arc<Histogram> {
+        self.histogram(
+            "cex_latency_seconds",
+            "End-to-end latency from exchange timestamp to the last pipeline stage reached",
+            &[("exchange", exchange), ("channel", channel), ("symbol", symbol)],
+            LATENCY_BUCKETS_NS,
+            NS_PER_SECOND,
+        )
+    }
+
+    /// Latency from the exchange timestamp to a pipeline stage.
+    pub fn stage_latency(&self, exchange: &str, stage: Stage) -> Arc<Histogram> {
+        self.histogram(
+            "cex_stage_latency_seconds",
+            "Latency from exchange timestamp to each pipeline stage",
+            &[("exchange", exchange), ("stage", stage.name())],
+            LATENCY_BUCKETS_NS,
+            NS_PER_SECOND,
+        )
+    }
+
+    pub fn messages(&self, exchange: &str, channel: &str, symbol: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_messages_total",
+            "Market data messages received",
+            &[("exchange", exchange), ("channel", channel), ("symbol", symbol)],
+        )
+    }
+
+    /// Size of each received data message (all of its frames).
+    pub fn frame_bytes(&self, exchange: &str) -> Arc<Histogram> {
+        self.histogram(
+            "cex_ws_frame_bytes",
+            "Size of received WebSocket data messages in bytes",
+            &[("exchange", exchange)],
+            FRAME_SIZE_BUCKETS,
+            1.0,
+        )
+    }
+
+    pub fn parse_errors(&self, exchange: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_parse_errors_total",
+            "Messages that failed to parse",
+            &[("exchange", exchange)],
+        )
+    }
+
+    pub fn book_checksum_failures(&self, exchange: &str, symbol: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_book_checksum_failures_total",
+            "Order book checksum mismatches",
+            &[("exchange", exchange), ("symbol", symbol)],
+        )
+    }
+
+    pub fn trades(&self, exchange: &str, symbol: &str, side: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_trades_total",
+            "Public trades received",
+            &[("exchange", exchange), ("symbol", symbol), ("side", side)],
+        )
+    }
+
+    /// Trades missing from the stream, going by gaps in trade ids.
+    pub fn trade_id_gaps(&self, exchange: &str, symbol: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_trade_id_gaps_total",
+            "Trades skipped in the trade id sequence",
+            &[("exchange", exchange), ("symbol", symbol)],
+        )
+    }
+
+    /// Feed gaps detected from venue sequence numbers, each followed by a
+    /// resync.
+    pub fn sequence_gaps(&self, exchange: &str, symbol: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_sequence_gaps_total",
+            "Sequence gaps that forced a resync",
+            &[("exchange", exchange), ("symbol", symbol)],
+        )
+    }
+
+    pub fn liquidations(&self, exchange: &str, symbol: &str, side: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_liquidations_total",
+            "Liquidation orders, by the side of the liquidation order",
+            &[("exchange", exchange), ("symbol", symbol), ("side", side)],
+        )
+    }
+
+    /// Round trip from sending a trade request to receiving its ack.
+    pub fn order_ack_latency(&self, exchange: &str, op: &str) -> Arc<Histogram> {
+        self.histogram(
+            "cex_order_ack_latency_seconds",
+            "Round trip from sending a trade request to receiving its ack",
+            &[("exchange", exchange), ("op", op)],
+            LATENCY_BUCKETS_NS,
+            NS_PER_SECOND,
+        )
+    }
+
+    /// BBO moves a venue showed before the others in `compare` mode.
+    pub fn bbo_led(&self, exchange: &str, symbol: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_bbo_first_total",
+            "BBO moves this venue delivered before the other compared venues",
+            &[("exchange", exchange), ("symbol", symbol)],
+        )
+    }
+
+    pub fn reconnects(&self, exchange: &str) -> Arc<Counter> {
+        self.counter(
+            "cex_reconnects_total",
+            "WebSocket reconnections",
+            &[("exchange", exchange)],
+        )
+    }
+
+    /// 1 while the WebSocket connection is up, 0 otherwise.
+    pub fn connected(&self, exchange: &str) -> Arc<Gauge> {
+        self.gauge(
+            "cex_connected",
+            "Whether the WebSocket connection is up",
+            &[("exchange", exchange)],
+        )
+    }
+
+    pub fn counter(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)]) -> Arc<Counter> {
+        match self.series(name, help, "counter", labels, || Series::Counter(Arc::default())) {
+            Series::Counter(counter) => counter,
+            _ => panic!("metric {} is not a counter", name),
+        }
+    }
+
+    pub fn gauge(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)]) -> Arc<Gauge> {
+        match self.series(name, help, "gauge", labels, || Series::Gauge(Arc::default())) {
+            Series::Gauge(gauge) => gauge,
+            _ => panic!("metric {} is not a gauge", name),
+        }
+    }
+
+    fn histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        bounds: &'static [u64],
+        unit: f64,
+    ) -> Arc<Histogram> {
+        let make = || Series::Histogram(Arc::new(Histogram::new(bounds, unit)));
+        match self.series(name, help, "histogram", labels, make) {
+            Series::Histogram(histogram) => histogram,
+            _ => panic!("metric {} is not a histogram", name),
+        }
+    }
+
+    fn series(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        kind: &'static str,
+        labels: &[(&str, &str)],
+        make: impl FnOnce() -> Series,
+    ) -> Series {
+        let key = render_labels(labels);
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name).or_insert_with(|| Family {
+            help,
+            kind,
+            series: BTreeMap::new(),
+        });
+        family.series.entry(key).or_insert_with(make).clone()
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, family.kind);
+
+            for (labels, series) in &family.series {
+                match series {
+                    Series::Counter(counter) => write_sample(&mut out, name, "", labels, counter.get() as f64),
+                    Series::Gauge(gauge) => write_sample(&mut out, name, "", labels, gauge.get() as f64),
+                    Series::Histogram(histogram) => write_histogram(&mut out, name, labels, histogram),
+                }
+            }
+        }
+
+        out
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::default)
+}
+
+/// Starts the `/metrics` HTTP endpoint on a background thread and returns
+/// the address it is listening on.
+pub fn serve(addr: &str) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    std::thread::Builder::new()
+        .name("metrics-http".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = handle_request(stream) {
+                            tracing::debug!("Metrics request failed: {}", e);
+                        }
+                    }
+                    Err(e) => tracing::warn!("Metrics accept failed: {}", e),
+                }
+            }
+        })?;
+
+    tracing::info!("Serving metrics on http://{}/metrics", local_addr);
+    Ok(local_addr)
+}
+
+fn handle_request(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut request = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 || request.len() + n > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request_line = request.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|&b| b == b' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        (b"GET", b"/metrics") => ("200 OK", "text/plain; version=0.0.4", metrics().render()),
+        (b"GET", _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Method not allowed\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status, content_type, body.len(), body
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    labels.iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn write_sample(out: &mut String, name: &str, suffix: &str, labels: &str, value: f64) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{}{} {}", name, suffix, value);
+    } else {
+        let _ = writeln!(out, "{}{}{{{}}} {}", name, suffix, labels, value);
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    let mut cumulative = 0;
+
+    for (i, bucket) in histogram.buckets.iter().enumerate() {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let le = match histogram.bounds.get(i) {
+            Some(&bound) => format!("{}", bound as f64 / histogram.unit),
+            None => "+Inf".to_string(),
+        };
+        let bucket_labels = format!("{}{}le=\"{}\"", labels, separator, le);
+        write_sample(out, name, "_bucket", &bucket_labels, cumulative as f64);
+    }
+
+    let sum = histogram.sum.load(Ordering::Relaxed) as f64 / histogram.unit;
+    write_sample(out, name, "_sum", labels, sum);
+    write_sample(out, name, "_count", labels, histogram.count() as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_gauges_and_histograms() {
+        let metrics = Metrics::default();
+        metrics.gauge("cex_connected", "Whether the WebSocket connection is up", &[]).set(1);
+        metrics.trades("okx", "BTC-USDT", "buy").add(3);
+        metrics.trades("okx", "odd \"sym\\bol\"\n", "sell").inc();
+        let frames = metrics.frame_bytes("okx");
+        for bytes in [64, 100, 2_000_000] {
+            frames.observe(bytes);
+        }
+
+        assert_eq!(metrics.render(), "\
+# HELP cex_connected Whether the WebSocket connection is up
+# TYPE cex_connected gauge
+cex_connected 1
+# HELP cex_trades_total Public trades received
+# TYPE cex_trades_total counter
+cex_trades_total{exchange=\"okx\",symbol=\"BTC-USDT\",side=\"buy\"} 3
+cex_trades_total{exchange=\"okx\",symbol=\"odd \\\"sym\\\\bol\\\"\\n\",side=\"sell\"} 1
+# HELP cex_ws_frame_bytes Size of received WebSocket data messages in bytes
+# TYPE cex_ws_frame_bytes histogram
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"64\"} 1
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"256\"} 2
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"1024\"} 2
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"4096\"} 2
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"16384\"} 2
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"65536\"} 2
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"262144\"} 2
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"1048576\"} 2
+cex_ws_frame_bytes_bucket{exchange=\"okx\",le=\"+Inf\"} 3
+cex_ws_frame_bytes_sum{exchange=\"okx\"} 2000164
+cex_ws_frame_bytes_count{exchange=\"okx\"} 3
+");
+    }
+
+    #[test]
+    fn renders_latency_in_seconds() {
+        let metrics = Metrics::default();
+        metrics.latency("okx", "books", "BTC-USDT").observe(1_500_000);
+
+        let rendered = metrics.render();
+        let labels = "exchange=\"okx\",channel=\"books\",symbol=\"BTC-USDT\"";
+        for line in [
+            "# TYPE cex_latency_seconds histogram".to_string(),
+            format!("cex_latency_seconds_bucket{{{},le=\"0.000001\"}} 0", labels),
+            format!("cex_latency_seconds_bucket{{{},le=\"0.001\"}} 0", labels),
+            format!("cex_latency_seconds_bucket{{{},le=\"0.0025\"}} 1", labels),
+            format!("cex_latency_seconds_bucket{{{},le=\"2.5\"}} 1", labels),
+            format!("cex_latency_seconds_bucket{{{},le=\"+Inf\"}} 1", labels),
+            format!("cex_latency_seconds_sum{{{}}} 0.0015", labels),
+            format!("cex_latency_seconds_count{{{}}} 1", labels),
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "missing {}", line);
+        }
+    }
+}
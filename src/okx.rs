@@ -0,0 +1,660 @@
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::derivatives::{self, DerivativeEvent};
+use crate::orderbook::{BookChecksum, OkxChecksum};
+use crate::refdata;
+use crate::trades::{Side, Trade};
+use crate::venue::{invalid, number, optional, text_heartbeat, timestamp, Adapter, BookUpdate, MarketEvent, RefData, Venue};
+use crate::websocket::Heartbeat;
+
+pub const PUBLIC_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+/// Endpoint for `trades-all`, candles and other business channels
+pub const BUSINESS_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/business";
+
+/// Keepalive text, answered with a bare `pong`. OKX closes connections
+/// that receive nothing for 30 s.
+pub const PING: &str = "ping";
+pub const PONG: &str = "pong";
+
+// Minimal structs for OKX push message deserialization. `D` is the data
+// entry type of the channel, book levels by default.
+#[derive(Debug, Deserialize)]
+#[serde(bound(deserialize = "D: Deserialize<'de>"))]
+pub struct OkxMessage<D = OkxBookData> {
+    #[serde(default)]
+    pub event: Option<String>,
+    #[serde(default)]
+    pub arg: Option<OkxArg>,
+    /// `snapshot` or `update` on incremental book channels
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(default)]
+    pub data: Option<Vec<D>>,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OkxArg {
+    pub channel: String,
+    #[serde(rename = "instId", default)]
+    pub inst_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OkxBookData {
+    pub ts: String,
+    #[serde(default)]
+    pub asks: Vec<OkxLevel>,
+    #[serde(default)]
+    pub bids: Vec<OkxLevel>,
+    #[serde(default)]
+    pub checksum: Option<i32>,
+    #[serde(rename = "seqId", default)]
+    pub seq_id: Option<i64>,
+    #[serde(rename = "prevSeqId", default)]
+    pub prev_seq_id: Option<i64>,
+}
+
+/// `[price, size, deprecated, order count]`, with price and size kept as
+/// the exchange sent them (the checksum is computed over those strings).
+#[derive(Debug, Deserialize)]
+pub struct OkxLevel(String, String, IgnoredAny, IgnoredAny);
+
+impl OkxLevel {
+    pub fn price(&self) -> &str {
+        &self.0
+    }
+
+    pub fn size(&self) -> &str {
+        &self.1
+    }
+}
+
+/// `funding-rate` entry. Next-period fields are empty when not yet known.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxFundingRate {
+    pub inst_id: String,
+    pub funding_rate: String,
+    #[serde(default)]
+    pub next_funding_rate: String,
+    pub funding_time: String,
+    #[serde(default)]
+    pub next_funding_time: String,
+    pub ts: String,
+}
+
+/// `mark-price` entry.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxMarkPrice {
+    pub inst_id: String,
+    pub mark_px: String,
+    pub ts: String,
+}
+
+/// `open-interest` entry; `oi` is in contracts, `oiCcy` in the base currency.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOpenInterest {
+    pub inst_id: String,
+    pub oi: String,
+    #[serde(default)]
+    pub oi_ccy: String,
+    #[serde(default)]
+    pub oi_usd: String,
+    pub ts: String,
+}
+
+/// `liquidation-orders` entry: the liquidations of one instrument.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxLiquidation {
+    pub inst_id: String,
+    pub details: Vec<OkxLiquidationDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxLiquidationDetail {
+    /// Side of the liquidation order; `buy` closes a short
+    pub side: String,
+    #[serde(default)]
+    pub pos_side: String,
+    /// Bankruptcy price
+    pub bk_px: String,
+    /// Size in contracts
+    pub sz: String,
+    pub ts: String,
+}
+
+/// `price-limit` entry; the limits are empty when `enabled` is false.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxPriceLimit {
+    pub inst_id: String,
+    #[serde(default)]
+    pub buy_lmt: String,
+    #[serde(default)]
+    pub sell_lmt: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub ts: String,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// `opt-summary` entry: greeks and implied volatilities of one option.
+/// `delta`..`theta` are in coin terms, the `BS` variants Black-Scholes in USD.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOptSummary {
+    pub inst_id: String,
+    pub delta: String,
+    pub gamma: String,
+    pub vega: String,
+    pub theta: String,
+    #[serde(rename = "deltaBS")]
+    pub delta_bs: String,
+    #[serde(rename = "gammaBS")]
+    pub gamma_bs: String,
+    #[serde(rename = "vegaBS")]
+    pub vega_bs: String,
+    #[serde(rename = "thetaBS")]
+    pub theta_bs: String,
+    pub mark_vol: String,
+    #[serde(default)]
+    pub bid_vol: String,
+    #[serde(default)]
+    pub ask_vol: String,
+    #[serde(default)]
+    pub fwd_px: String,
+    pub ts: String,
+}
+
+/// Entry of the REST `GET /api/v5/public/instruments` response. Fields that
+/// do not apply to the instrument type are empty strings.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxInstrument {
+    pub inst_id: String,
+    #[serde(default)]
+    pub inst_family: String,
+    /// Contract value, in `ctValCcy`
+    #[serde(default)]
+    pub ct_val: String,
+    #[serde(default)]
+    pub ct_mult: String,
+    /// `linear` or `inverse` for swaps and futures
+    #[serde(default)]
+    pub ct_type: String,
+    /// Strike price
+    #[serde(default)]
+    pub stk: String,
+    /// Expiry, ms since the epoch
+    #[serde(default)]
+    pub exp_time: String,
+    /// `C` or `P`
+    #[serde(default)]
+    pub opt_type: String,
+}
+
+/// `trades` (aggregated) or `trades-all` entry.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxTrade {
+    pub inst_id: String,
+    /// On `trades`, the id of the last trade in the aggregation
+    pub trade_id: String,
+    pub px: String,
+    pub sz: String,
+    /// Taker side, `buy` or `sell`
+    pub side: String,
+    pub ts: String,
+    /// Trades aggregated into this entry; absent on `trades-all`
+    #[serde(default)]
+    pub count: Option<String>,
+}
+
+impl<D> OkxMessage<D> {
+    pub fn channel(&self) -> Option<&str> {
+        self.arg.as_ref().map(|arg| arg.channel.as_str())
+    }
+
+    pub fn inst_id(&self) -> Option<&str> {
+        self.arg.as_ref().and_then(|arg| arg.inst_id.as_deref())
+    }
+
+    /// Logs subscription replies and errors. Returns false for channel
+    /// data, which the caller handles.
+    pub fn handle_event(&self) -> bool {
+        let Some(event) = self.event.as_deref() else {
+            return false;
+        };
+        match event {
+            "error" => tracing::error!(
+                "okx error {}: {}",
+                self.code.as_deref().unwrap_or_default(), self.msg.as_deref().unwrap_or_default()
+            ),
+            _ => tracing::info!("okx: {} {} {}", event, self.channel().unwrap_or_default(), self.inst_id().unwrap_or_default()),
+        }
+        true
+    }
+}
+
+/// Whether a channel pushes book levels: `books`, `books5`, `bbo-tbt` and
+/// the tick-by-tick `books*-l2-tbt`.
+pub fn is_book_channel(channel: &str) -> bool {
+    channel.starts_with("books") || channel == "bbo-tbt"
+}
+
+/// Book event of a push on a book channel. Channels that push full books
+/// (`books5`, `bbo-tbt`) carry no `action` and are snapshots.
+pub fn book_event(symbol: &str, action: Option<&str>, data: OkxBookData) -> Result<MarketEvent, serde_json::Error> {
+    let levels = |levels: Vec<OkxLevel>| levels.into_iter().map(|OkxLevel(price, size, ..)| (price, size)).collect();
+    let update = BookUpdate {
+        symbol: symbol.to_string(),
+        exchange_ns: Some(timestamp("ts", &data.ts)? * 1_000_000),
+        bids: levels(data.bids),
+        asks: levels(data.asks),
+        prev_sequence: data.prev_seq_id,
+        sequence: data.seq_id,
+        checksum: data.checksum.map(|checksum| checksum as u32),
+        depth: None,
+    };
+    Ok(match action {
+        Some("update") => MarketEvent::BookDelta(update),
+        _ => MarketEvent::BookSnapshot(update),
+    })
+}
+
+/// `subscribe` request for every channel/symbol pair.
+pub fn subscribe_request(channels: &[String], symbols: &[String]) -> String {
+    request("subscribe", channels, symbols)
+}
+
+pub fn unsubscribe_request(channels: &[String], symbols: &[String]) -> String {
+    request("unsubscribe", channels, symbols)
+}
+
+/// Channels subscribed per instrument type rather than per instrument.
+const INST_TYPE_CHANNELS: &[&str] = &["liquidation-orders"];
+
+/// Channels subscribed per instrument family, e.g. `BTC-USD`.
+const INST_FAMILY_CHANNELS: &[&str] = &["opt-summary"];
+
+fn request(op: &str, channels: &[String], symbols: &[String]) -> String {
+    let mut args: Vec<serde_json::Value> = Vec::new();
+    for channel in channels {
+        for symbol in symbols {
+            let arg = if INST_TYPE_CHANNELS.contains(&channel.as_str()) {
+                // Spot pairs are liquidated as margin positions
+                let inst_type = match inst_type(symbol) {
+                    "SPOT" => "MARGIN",
+                    inst_type => inst_type,
+                };
+                serde_json::json!({ "channel": channel, "instType": inst_type })
+            } else if INST_FAMILY_CHANNELS.contains(&channel.as_str()) {
+                serde_json::json!({ "channel": channel, "instFamily": inst_family(symbol) })
+            } else {
+                serde_json::json!({ "channel": channel, "instId": symbol })
+            };
+            if !args.contains(&arg) {
+                args.push(arg);
+            }
+        }
+    }
+    serde_json::json!({ "op": op, "args": args }).to_string()
+}
+
+/// Instrument family of an OKX instrument id: its first two parts, e.g.
+/// `BTC-USD` for `BTC-USD-250328-100000-C`.
+pub fn inst_family(inst_id: &str) -> &str {
+    match inst_id.match_indices('-').nth(1) {
+        Some((end, _)) => &inst_id[..end],
+        None => inst_id,
+    }
+}
+
+/// Instrument type of an OKX instrument id: `BTC-USDT` is `SPOT`,
+/// `BTC-USDT-SWAP` `SWAP`, `BTC-USD-250328` `FUTURES` and
+/// `BTC-USD-250328-100000-C` `OPTION`.
+pub fn inst_type(inst_id: &str) -> &'static str {
+    let parts: Vec<&str> = inst_id.split('-').collect();
+    match parts.as_slice() {
+        [.., "SWAP"] => "SWAP",
+        [_, _, _, _, "C" | "P"] => "OPTION",
+        [_, _, expiry] if expiry.bytes().all(|b| b.is_ascii_digit()) => "FUTURES",
+        _ => "SPOT",
+    }
+}
+
+pub struct Okx;
+
+impl Venue for Okx {
+    fn public_ws_url(&self) -> &'static str {
+        PUBLIC_WS_URL
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        text_heartbeat(PING, Some(PONG))
+    }
+
+    fn symbol(&self, instrument: &str) -> String {
+        instrument.to_string()
+    }
+
+    fn book_channel(&self) -> Option<&'static str> {
+        Some("books")
+    }
+
+    fn trade_channel(&self) -> &'static str {
+        "trades"
+    }
+
+    fn compare_channels(&self) -> &'static [&'static str] {
+        &["bbo-tbt"]
+    }
+
+    fn book_checksum(&self) -> Option<&'static dyn BookChecksum> {
+        Some(&OkxChecksum)
+    }
+
+    fn sequential_trade_ids(&self) -> bool {
+        true
+    }
+
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter> {
+        Box::new(OkxAdapter { refdata })
+    }
+}
+
+/// Turns OKX pushes into events: book channels into book events, `trades`
+/// and `trades-all` into trades and the derivatives channels into
+/// derivative events.
+pub struct OkxAdapter {
+    refdata: RefData,
+}
+
+impl Adapter for OkxAdapter {
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![subscribe_request(channels, symbols)]
+    }
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String> {
+        vec![unsubscribe_request(channels, symbols)]
+    }
+
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error> {
+        let msg: OkxMessage<Box<RawValue>> = serde_json::from_str(text)?;
+        if msg.handle_event() {
+            return Ok(());
+        }
+        let (Some(channel), Some(data)) = (msg.channel(), &msg.data) else {
+            return Ok(());
+        };
+
+        for entry in data {
+            match channel {
+                "trades" | "trades-all" => events.push(MarketEvent::Trade(self.trade(entry_data(entry)?)?)),
+                channel if is_book_channel(channel) => {
+                    let symbol = msg.inst_id().unwrap_or_default();
+                    events.push(book_event(symbol, msg.action.as_deref(), entry_data(entry)?)?);
+                }
+                channel if derivatives::CHANNELS.contains(&channel) => {
+                    let derivatives = self.derivatives(channel, entry)?;
+                    events.extend(derivatives.into_iter().map(MarketEvent::Derivative));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OkxAdapter {
+    fn trade(&self, trade: OkxTrade) -> Result<Trade, serde_json::Error> {
+        let side = match trade.side.as_str() {
+            "buy" => Side::Buy,
+            "sell" => Side::Sell,
+            other => return Err(invalid("side", other)),
+        };
+        Ok(Trade {
+            price: number("px", &trade.px)?,
+            size: number("sz", &trade.sz)? * refdata::contract_multiplier(&self.refdata, &trade.inst_id),
+            count: trade.count.as_deref().map_or(Ok(1), |count| count.parse().map_err(|_| invalid("count", count)))?,
+            exchange_ns: timestamp("ts", &trade.ts)? * 1_000_000,
+            symbol: trade.inst_id,
+            trade_id: trade.trade_id,
+            side,
+        })
+    }
+
+    // Events of one entry of a derivatives channel; liquidations come for
+    // every instrument of a type, which the caller filters
+    fn derivatives(&self, channel: &str, entry: &RawValue) -> Result<Vec<DerivativeEvent>, serde_json::Error> {
+        match channel {
+            "funding-rate" => {
+                let funding: OkxFundingRate = entry_data(entry)?;
+                Ok(vec![DerivativeEvent::Funding {
+                    rate: number("fundingRate", &funding.funding_rate)?,
+                    next_rate: optional(&funding.next_funding_rate, |value| number("nextFundingRate", value))?,
+                    funding_time_ms: timestamp("fundingTime", &funding.funding_time)?,
+                    next_funding_time_ms: optional(&funding.next_funding_time, |value| timestamp("nextFundingTime", value))?,
+                    exchange_ns: timestamp("ts", &funding.ts)? * 1_000_000,
+                    symbol: funding.inst_id,
+                }])
+            }
+            "mark-price" => {
+                let mark: OkxMarkPrice = entry_data(entry)?;
+                Ok(vec![DerivativeEvent::MarkPrice {
+                    price: number("markPx", &mark.mark_px)?,
+                    exchange_ns: timestamp("ts", &mark.ts)? * 1_000_000,
+                    symbol: mark.inst_id,
+                }])
+            }
+            "open-interest" => {
+                let interest: OkxOpenInterest = entry_data(entry)?;
+                let contracts = number("oi", &interest.oi)?;
+                Ok(vec![DerivativeEvent::OpenInterest {
+                    size: match optional(&interest.oi_ccy, |value| number("oiCcy", value))? {
+                        Some(size) => size,
+                        None => contracts * refdata::contract_multiplier(&self.refdata, &interest.inst_id),
+                    },
+                    usd: optional(&interest.oi_usd, |value| number("oiUsd", value))?,
+                    exchange_ns: timestamp("ts", &interest.ts)? * 1_000_000,
+                    symbol: interest.inst_id,
+                    contracts,
+                }])
+            }
+            "liquidation-orders" => {
+                let liquidation: OkxLiquidation = entry_data(entry)?;
+                let multiplier = refdata::contract_multiplier(&self.refdata, &liquidation.inst_id);
+                liquidation.details.into_iter()
+                    .map(|detail| {
+                        Ok(DerivativeEvent::Liquidation {
+                            symbol: liquidation.inst_id.clone(),
+                            side: match detail.side.as_str() {
+                                "buy" => Side::Buy,
+                                "sell" => Side::Sell,
+                                other => return Err(invalid("side", other)),
+                            },
+                            price: number("bkPx", &detail.bk_px)?,
+                            size: number("sz", &detail.sz)? * multiplier,
+                            exchange_ns: timestamp("ts", &detail.ts)? * 1_000_000,
+                            pos_side: detail.pos_side,
+                        })
+                    })
+                    .collect()
+            }
+            "price-limit" => {
+                let limit: OkxPriceLimit = entry_data(entry)?;
+                let limit_px = |field, value: &str| match limit.enabled {
+                    true => optional(value, |value| number(field, value)),
+                    false => Ok(None),
+                };
+                Ok(vec![DerivativeEvent::PriceLimit {
+                    buy_limit: limit_px("buyLmt", &limit.buy_lmt)?,
+                    sell_limit: limit_px("sellLmt", &limit.sell_lmt)?,
+                    exchange_ns: timestamp("ts", &limit.ts)? * 1_000_000,
+                    symbol: limit.inst_id.clone(),
+                }])
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+fn entry_data<T: DeserializeOwned>(entry: &RawValue) -> Result<T, serde_json::Error> {
+    serde_json::from_str(entry.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+
+    const SWAP_MULTIPLIER: f64 = 0.01;
+
+    fn adapter() -> Box<dyn Adapter> {
+        let swap: refdata::ReferentialData = serde_json::from_value(serde_json::json!({
+            "uid": "BTC-USDT-SWAP",
+            "contract_multiplier": SWAP_MULTIPLIER,
+        })).unwrap();
+        Okx.adapter(Arc::new(HashMap::from([(swap.uid.clone(), swap)])))
+    }
+
+    // Events of one push, as JSON for comparison
+    fn parse(text: &str) -> Vec<serde_json::Value> {
+        let mut events = Vec::new();
+        adapter().parse(text, &mut events).unwrap();
+        events.into_iter()
+            .map(|event| match event {
+                MarketEvent::Derivative(event) => serde_json::to_value(event).unwrap(),
+                event => panic!("not a derivative event: {:?}", event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_funding_rate() {
+        let text = r#"{"arg":{"channel":"funding-rate","instId":"BTC-USD-SWAP"},"data":[{"fundingRate":"0.0001875391284828","fundingTime":"1700726400000","instId":"BTC-USD-SWAP","instType":"SWAP","method":"current_period","nextFundingRate":"","nextFundingTime":"1700755200000","premium":"0.0001233824646391","ts":"1700724675402"}]}"#;
+        assert_eq!(parse(text), [serde_json::json!({
+            "type": "funding",
+            "symbol": "BTC-USD-SWAP",
+            "rate": 0.0001875391284828,
+            "next_rate": null,
+            "funding_time_ms": 1700726400000u64,
+            "next_funding_time_ms": 1700755200000u64,
+            "exchange_ns": 1700724675402000000u64,
+        })]);
+    }
+
+    #[test]
+    fn parses_mark_price() {
+        let text = r#"{"arg":{"channel":"mark-price","instId":"BTC-USDT-SWAP"},"data":[{"instType":"SWAP","instId":"BTC-USDT-SWAP","markPx":"42310.6","ts":"1630049139746"}]}"#;
+        assert_eq!(parse(text), [serde_json::json!({
+            "type": "mark_price",
+            "symbol": "BTC-USDT-SWAP",
+            "price": 42310.6,
+            "exchange_ns": 1630049139746000000u64,
+        })]);
+    }
+
+    #[test]
+    fn parses_open_interest() {
+        // The second entry has no base currency size, which comes from the multiplier
+        let text = r#"{"arg":{"channel":"open-interest","instId":"BTC-USDT-SWAP"},"data":[{"instId":"BTC-USDT-SWAP","instType":"SWAP","oi":"2216113.01","oiCcy":"22161.1301","oiUsd":"1014000000","ts":"1715672086000"},{"instId":"BTC-USDT-SWAP","instType":"SWAP","oi":"2216200","oiCcy":"","oiUsd":"","ts":"1715672087000"}]}"#;
+        assert_eq!(parse(text), [
+            serde_json::json!({
+                "type": "open_interest",
+                "symbol": "BTC-USDT-SWAP",
+                "contracts": 2216113.01,
+                "size": 22161.1301,
+                "usd": 1014000000.0,
+                "exchange_ns": 1715672086000000000u64,
+            }),
+            serde_json::json!({
+                "type": "open_interest",
+                "symbol": "BTC-USDT-SWAP",
+                "contracts": 2216200.0,
+                "size": 2216200.0 * SWAP_MULTIPLIER,
+                "usd": null,
+                "exchange_ns": 1715672087000000000u64,
+            }),
+        ]);
+    }
+
+    #[test]
+    fn parses_liquidation_orders() {
+        let text = r#"{"arg":{"channel":"liquidation-orders","instType":"SWAP"},"data":[{"details":[{"bkLoss":"0","bkPx":"42000.1","ccy":"","posSide":"short","side":"buy","sz":"13","ts":"1692266434010"},{"bkLoss":"0","bkPx":"41000","ccy":"","posSide":"long","side":"sell","sz":"2","ts":"1692266434020"}],"instFamily":"BTC-USDT","instId":"BTC-USDT-SWAP","instType":"SWAP","uly":"BTC-USDT"}]}"#;
+        assert_eq!(parse(text), [
+            serde_json::json!({
+                "type": "liquidation",
+                "symbol": "BTC-USDT-SWAP",
+                "side": "buy",
+                "pos_side": "short",
+                "price": 42000.1,
+                "size": 13.0 * SWAP_MULTIPLIER,
+                "exchange_ns": 1692266434010000000u64,
+            }),
+            serde_json::json!({
+                "type": "liquidation",
+                "symbol": "BTC-USDT-SWAP",
+                "side": "sell",
+                "pos_side": "long",
+                "price": 41000.0,
+                "size": 2.0 * SWAP_MULTIPLIER,
+                "exchange_ns": 1692266434020000000u64,
+            }),
+        ]);
+
+        let invalid = text.replace(r#""side":"sell""#, r#""side":"short""#);
+        assert!(adapter().parse(&invalid, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn parses_price_limit() {
+        let text = r#"{"arg":{"channel":"price-limit","instId":"BTC-USD-250328"},"data":[{"instId":"BTC-USD-250328","buyLmt":"98000.5","sellLmt":"92000","ts":"1597026383085","enabled":true},{"instId":"BTC-USD-250328","buyLmt":"","sellLmt":"","ts":"1597026384085","enabled":false}]}"#;
+        assert_eq!(parse(text), [
+            serde_json::json!({
+                "type": "price_limit",
+                "symbol": "BTC-USD-250328",
+                "buy_limit": 98000.5,
+                "sell_limit": 92000.0,
+                "exchange_ns": 1597026383085000000u64,
+            }),
+            serde_json::json!({
+                "type": "price_limit",
+                "symbol": "BTC-USD-250328",
+                "buy_limit": null,
+                "sell_limit": null,
+                "exchange_ns": 1597026384085000000u64,
+            }),
+        ]);
+    }
+
+    #[test]
+    fn converts_trade_sizes_with_the_contract_multiplier() {
+        let text = r#"{"arg":{"channel":"trades","instId":"BTC-USDT-SWAP"},"data":[{"instId":"BTC-USDT-SWAP","tradeId":"130639474","px":"42219.9","sz":"5","side":"buy","ts":"1630048897897","count":"3"},{"instId":"BTC-USDT","tradeId":"130639475","px":"42219.9","sz":"0.12","side":"sell","ts":"1630048897898"}]}"#;
+        let mut events = Vec::new();
+        adapter().parse(text, &mut events).unwrap();
+        let trades: Vec<(&str, f64, u64)> = events.iter()
+            .map(|event| match event {
+                MarketEvent::Trade(trade) => (trade.symbol.as_str(), trade.size, trade.count),
+                event => panic!("not a trade: {:?}", event),
+            })
+            .collect();
+        // Spot instruments without reference data keep their size
+        assert_eq!(trades, [("BTC-USDT-SWAP", 5.0 * SWAP_MULTIPLIER, 3), ("BTC-USDT", 0.12, 1)]);
+    }
+}
@@ -0,0 +1,424 @@
+#![allow(dead_code)]
+
+// No crate-internal imports: the mock server example includes this file to
+// verify login signatures with the same code
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+pub const PRIVATE_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/private";
+/// Demo trading endpoint, for placing orders without real funds
+pub const DEMO_PRIVATE_WS_URL: &str = "wss://wspap.okx.com:8443/ws/v5/private";
+
+// Login signs a fixed request line, per the OKX docs
+pub const LOGIN_METHOD: &str = "GET";
+pub const LOGIN_PATH: &str = "/users/self/verify";
+
+pub const API_KEY_ENV: &str = "OKX_API_KEY";
+pub const SECRET_KEY_ENV: &str = "OKX_SECRET_KEY";
+pub const PASSPHRASE_ENV: &str = "OKX_PASSPHRASE";
+
+/// Private channels this module parses.
+pub const CHANNELS: [&str; 3] = ["orders", "positions", "account"];
+
+/// OKX API key, secret and passphrase.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    pub api_key: String,
+    pub secret_key: String,
+    pub passphrase: String,
+}
+
+// Keep the secret and passphrase out of logs and error messages
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &self.api_key)
+            .field("secret_key", &"<redacted>")
+            .field("passphrase", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Credentials {
+    /// Credentials from `OKX_API_KEY`, `OKX_SECRET_KEY` and `OKX_PASSPHRASE`,
+    /// if all three are set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok().filter(|value: &String| !value.is_empty());
+        Some(Self {
+            api_key: var(API_KEY_ENV)?,
+            secret_key: var(SECRET_KEY_ENV)?,
+            passphrase: var(PASSPHRASE_ENV)?,
+        })
+    }
+
+    /// `login` request signed at `timestamp` (Unix seconds).
+    pub fn login_request(&self, timestamp: u64) -> String {
+        let timestamp = timestamp.to_string();
+        serde_json::json!({
+            "op": "login",
+            "args": [{
+                "apiKey": self.api_key,
+                "passphrase": self.passphrase,
+                "timestamp": timestamp,
+                "sign": sign(&self.secret_key, &timestamp, LOGIN_METHOD, LOGIN_PATH),
+            }],
+        })
+        .to_string()
+    }
+}
+
+/// Base64 HMAC-SHA256 of `timestamp + method + path` keyed with the secret.
+pub fn sign(secret_key: &str, timestamp: &str, method: &str, path: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(method.as_bytes());
+    mac.update(path.as_bytes());
+    BASE64_STANDARD.encode(mac.finalize().into_bytes())
+}
+
+pub fn unix_timestamp_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// `subscribe` request for private channels. `orders` and `positions` are
+/// subscribed for every instrument type.
+pub fn subscribe_request(channels: &[String]) -> String {
+    let args: Vec<serde_json::Value> = channels.iter()
+        .map(|channel| match channel.as_str() {
+            "account" => serde_json::json!({ "channel": channel }),
+            _ => serde_json::json!({ "channel": channel, "instType": "ANY" }),
+        })
+        .collect();
+    serde_json::json!({ "op": "subscribe", "args": args }).to_string()
+}
+
+/// Trade operations over the private WebSocket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderOp {
+    Order,
+    BatchOrders,
+    AmendOrder,
+    CancelOrder,
+}
+
+impl OrderOp {
+    pub const ALL: [OrderOp; 4] = [OrderOp::Order, OrderOp::BatchOrders, OrderOp::AmendOrder, OrderOp::CancelOrder];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OrderOp::Order => "order",
+            OrderOp::BatchOrders => "batch-orders",
+            OrderOp::AmendOrder => "amend-order",
+            OrderOp::CancelOrder => "cancel-order",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+}
+
+/// `order` / `batch-orders` argument.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewOrder {
+    pub inst_id: String,
+    /// `cash` for spot, `cross` or `isolated` for margin and derivatives
+    pub td_mode: String,
+    pub side: String,
+    pub ord_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub px: Option<String>,
+    pub sz: String,
+    pub cl_ord_id: String,
+}
+
+/// `amend-order` argument; at least one of `new_sz` and `new_px` is required.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmendOrder {
+    pub inst_id: String,
+    pub cl_ord_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_sz: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_px: Option<String>,
+}
+
+/// `cancel-order` argument.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelOrder {
+    pub inst_id: String,
+    pub cl_ord_id: String,
+}
+
+/// Trade request with request id `id` (1-32 alphanumeric characters), echoed
+/// back in its ack.
+pub fn trade_request<A: Serialize>(id: &str, op: OrderOp, args: &[A]) -> String {
+    serde_json::json!({ "id": id, "op": op.name(), "args": args }).to_string()
+}
+
+/// Reply to a trade request.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderAck {
+    pub id: String,
+    pub op: String,
+    /// `0` when every order succeeded, `1` when all failed, `2` when some did
+    pub code: String,
+    #[serde(default)]
+    pub msg: String,
+    #[serde(default)]
+    pub data: Vec<OrderAckData>,
+    /// Gateway receive and send times, microseconds
+    #[serde(default)]
+    pub in_time: String,
+    #[serde(default)]
+    pub out_time: String,
+}
+
+impl OrderAck {
+    /// Time the request spent inside OKX, from `inTime` to `outTime`.
+    pub fn gateway_ns(&self) -> Option<u64> {
+        let in_time: u64 = self.in_time.parse().ok()?;
+        let out_time: u64 = self.out_time.parse().ok()?;
+        Some(out_time.saturating_sub(in_time) * 1_000)
+    }
+}
+
+/// Per-order result in an `OrderAck`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderAckData {
+    #[serde(default)]
+    pub cl_ord_id: String,
+    #[serde(default)]
+    pub ord_id: String,
+    /// `0` on success, otherwise an OKX error code
+    pub s_code: String,
+    #[serde(default)]
+    pub s_msg: String,
+}
+
+// Envelope shared by events and pushes; `data` is decoded per channel
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PrivateEnvelope {
+    #[serde(default)]
+    op: Option<String>,
+    #[serde(default)]
+    event: Option<String>,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    msg: Option<String>,
+    #[serde(default)]
+    conn_id: Option<String>,
+    #[serde(default)]
+    arg: Option<PrivateArg>,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrivateArg {
+    channel: String,
+}
+
+/// Order update from the `orders` channel. Numeric fields are kept as the
+/// strings OKX sends; unset ones are empty.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOrder {
+    pub inst_type: String,
+    pub inst_id: String,
+    pub ord_id: String,
+    #[serde(default)]
+    pub cl_ord_id: String,
+    #[serde(default)]
+    pub px: String,
+    pub sz: String,
+    pub side: String,
+    pub ord_type: String,
+    /// `live`, `partially_filled`, `filled`, `canceled` or `mmp_canceled`
+    pub state: String,
+    #[serde(default)]
+    pub fill_px: String,
+    #[serde(default)]
+    pub fill_sz: String,
+    #[serde(default)]
+    pub acc_fill_sz: String,
+    #[serde(default)]
+    pub avg_px: String,
+    pub u_time: String,
+}
+
+/// Position update from the `positions` channel.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxPosition {
+    pub inst_type: String,
+    pub inst_id: String,
+    #[serde(default)]
+    pub pos_id: String,
+    /// `long`, `short` or `net`
+    #[serde(default)]
+    pub pos_side: String,
+    pub pos: String,
+    #[serde(default)]
+    pub avg_px: String,
+    #[serde(default)]
+    pub upl: String,
+    #[serde(default)]
+    pub lever: String,
+    #[serde(default)]
+    pub liq_px: String,
+    #[serde(default)]
+    pub mgn_mode: String,
+    pub u_time: String,
+}
+
+/// Account update from the `account` channel.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxAccount {
+    pub u_time: String,
+    #[serde(default)]
+    pub total_eq: String,
+    #[serde(default)]
+    pub details: Vec<OkxBalance>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxBalance {
+    pub ccy: String,
+    #[serde(default)]
+    pub eq: String,
+    #[serde(default)]
+    pub cash_bal: String,
+    #[serde(default)]
+    pub avail_bal: String,
+    #[serde(default)]
+    pub frozen_bal: String,
+}
+
+/// A message from the private endpoint.
+#[derive(Debug, Clone)]
+pub enum PrivateEvent {
+    Login { conn_id: Option<String> },
+    Subscribed { channel: String },
+    /// Rejected request, e.g. `60009` for a failed login
+    Error { code: String, msg: String },
+    Orders(Vec<OkxOrder>),
+    Positions(Vec<OkxPosition>),
+    Account(Vec<OkxAccount>),
+    OrderAck(OrderAck),
+    /// Other events (`notice`, `channel-conn-count`, ...) and channels
+    Other { event: Option<String>, channel: Option<String> },
+}
+
+impl PrivateEvent {
+    pub fn parse(text: &str) -> Result<Self, serde_json::Error> {
+        let envelope: PrivateEnvelope = serde_json::from_str(text)?;
+        // Only trade request replies carry an `op`
+        if envelope.op.is_some() {
+            return serde_json::from_str(text).map(PrivateEvent::OrderAck);
+        }
+        let channel = envelope.arg.map(|arg| arg.channel);
+
+        if let Some(event) = envelope.event {
+            return Ok(match (event.as_str(), envelope.code.as_deref()) {
+                ("login", Some("0") | None) => PrivateEvent::Login { conn_id: envelope.conn_id },
+                ("login" | "error", _) => PrivateEvent::Error {
+                    code: envelope.code.unwrap_or_default(),
+                    msg: envelope.msg.unwrap_or_default(),
+                },
+                ("subscribe", _) => PrivateEvent::Subscribed { channel: channel.unwrap_or_default() },
+                _ => PrivateEvent::Other { event: Some(event), channel },
+            });
+        }
+
+        let data = envelope.data.unwrap_or_default();
+        Ok(match channel.as_deref() {
+            Some("orders") => PrivateEvent::Orders(serde_json::from_value(data)?),
+            Some("positions") => PrivateEvent::Positions(serde_json::from_value(data)?),
+            Some("account") => PrivateEvent::Account(serde_json::from_value(data)?),
+            _ => PrivateEvent::Other { event: None, channel },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            api_key: "985d5b66-57ce-40fb-b714-afc0b9787083".to_string(),
+            secret_key: "22582BD0CFF14C41EDBF1AB98506286D".to_string(),
+            passphrase: "passphrase".to_string(),
+        }
+    }
+
+    #[test]
+    fn sign_known_answer() {
+        let signature = sign("22582BD0CFF14C41EDBF1AB98506286D", "1538054050", LOGIN_METHOD, LOGIN_PATH);
+        assert_eq!(signature, "+LdIr8lkkvhr5hoA3g9TMC0+uQJ849ftAcocA/ouu4M=");
+    }
+
+    #[test]
+    fn login_request_is_signed() {
+        let request: serde_json::Value = serde_json::from_str(&credentials().login_request(1538054050)).unwrap();
+        assert_eq!(request["op"], "login");
+        let args = &request["args"][0];
+        assert_eq!(args["apiKey"], "985d5b66-57ce-40fb-b714-afc0b9787083");
+        assert_eq!(args["passphrase"], "passphrase");
+        assert_eq!(args["timestamp"], "1538054050");
+        assert_eq!(args["sign"], "+LdIr8lkkvhr5hoA3g9TMC0+uQJ849ftAcocA/ouu4M=");
+    }
+
+    #[test]
+    fn parses_login_ack() {
+        let event = PrivateEvent::parse(r#"{"event":"login","code":"0","msg":"","connId":"a4d3ae55"}"#).unwrap();
+        assert!(matches!(event, PrivateEvent::Login { conn_id: Some(id) } if id == "a4d3ae55"));
+    }
+
+    #[test]
+    fn parses_login_errors() {
+        let failed = r#"{"event":"error","code":"60009","msg":"Login failed.","connId":"a4d3ae55"}"#;
+        assert!(matches!(
+            PrivateEvent::parse(failed).unwrap(),
+            PrivateEvent::Error { code, msg } if code == "60009" && msg == "Login failed."
+        ));
+
+        let timestamp = r#"{"event":"login","code":"60007","msg":"Invalid sign","connId":"a4d3ae55"}"#;
+        assert!(matches!(
+            PrivateEvent::parse(timestamp).unwrap(),
+            PrivateEvent::Error { code, .. } if code == "60007"
+        ));
+    }
+
+    #[test]
+    fn parses_order_ack() {
+        let text = r#"{"id":"1512","op":"order","code":"0","msg":"","data":[{"clOrdId":"b1","ordId":"12345689","sCode":"0","sMsg":""}],"inTime":"1695190491421339","outTime":"1695190491423240"}"#;
+        let PrivateEvent::OrderAck(ack) = PrivateEvent::parse(text).unwrap() else {
+            panic!("expected an order ack");
+        };
+        assert_eq!(ack.id, "1512");
+        assert_eq!(ack.data[0].ord_id, "12345689");
+        assert_eq!(ack.gateway_ns(), Some(1_901_000));
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+use crate::okx::{self, OkxBookData, OkxMarkPrice, OkxMessage, OkxOptSummary};
+use crate::refdata::{OptionType, ReferentialData};
+use crate::venue::{number, optional, timestamp};
+
+/// OKX channels the option chain is built from. `opt-summary` is subscribed
+/// per instrument family, the others per option.
+pub const CHANNELS: &[&str] = &["opt-summary", "mark-price", "books5", "bbo-tbt"];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Greeks {
+    pub delta: f64,
+    pub gamma: f64,
+    pub vega: f64,
+    pub theta: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Level {
+    pub price: f64,
+    pub size: f64,
+}
+
+/// Latest market data of one option. Prices are in the settlement currency
+/// (e.g. BTC for `BTC-USD` options).
+#[derive(Debug, Clone, Serialize)]
+pub struct OptionQuote {
+    pub inst_id: String,
+    pub family: String,
+    pub option_type: OptionType,
+    pub strike: f64,
+    pub expiry_ms: u64,
+    pub bid: Option<Level>,
+    pub ask: Option<Level>,
+    pub mark_px: Option<f64>,
+    /// Implied volatilities, as fractions
+    pub mark_vol: Option<f64>,
+    pub bid_vol: Option<f64>,
+    pub ask_vol: Option<f64>,
+    pub fwd_px: Option<f64>,
+    /// In coin terms, as OKX quotes them
+    pub greeks: Option<Greeks>,
+    /// Black-Scholes, in USD
+    pub greeks_bs: Option<Greeks>,
+    /// Exchange time of the latest update
+    pub updated_ns: Option<u64>,
+}
+
+/// What an applied message updated, for latency measurement.
+#[derive(Debug)]
+pub struct ChainUpdate {
+    pub channel: String,
+    pub family: String,
+    pub exchange_ns: u64,
+}
+
+/// Option chains of one or more instrument families, built from reference
+/// data and kept current from `opt-summary`, `mark-price` and book pushes.
+pub struct OptionChain {
+    quotes: HashMap<String, OptionQuote>,
+    /// Pushes for options missing from the reference data
+    unknown: u64,
+}
+
+impl OptionChain {
+    /// Tracks the options of `families` in `refdata`, optionally only those
+    /// expiring on `expiries` (`YYMMDD`, as in the instrument id).
+    pub fn new(refdata: &HashMap<String, ReferentialData>, families: &[String], expiries: &[String]) -> Self {
+        let quotes = refdata.values()
+            .filter_map(|instrument| {
+                let option_type = instrument.option_type?;
+                let family = instrument.inst_family.as_deref().unwrap_or(okx::inst_family(&instrument.uid));
+                let expiry = instrument.uid.split('-').nth(2).unwrap_or_default();
+                if !families.iter().any(|wanted| wanted == family) {
+                    return None;
+                }
+                if !expiries.is_empty() && !expiries.iter().any(|wanted| wanted == expiry) {
+                    return None;
+                }
+                let quote = OptionQuote {
+                    inst_id: instrument.uid.clone(),
+                    family: family.to_string(),
+                    option_type,
+                    strike: instrument.strike?,
+                    expiry_ms: instrument.expiry_ms?,
+                    bid: None,
+                    ask: None,
+                    mark_px: None,
+                    mark_vol: None,
+                    bid_vol: None,
+                    ask_vol: None,
+                    fwd_px: None,
+                    greeks: None,
+                    greeks_bs: None,
+                    updated_ns: None,
+                };
+                Some((instrument.uid.clone(), quote))
+            })
+            .collect();
+        Self { quotes, unknown: 0 }
+    }
+
+    /// Instrument ids of every tracked option, by expiry and strike.
+    pub fn inst_ids(&self) -> Vec<String> {
+        self.sorted().into_iter().map(|quote| quote.inst_id.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quotes.is_empty()
+    }
+
+    /// Applies a text message. Returns `None` for subscription replies and
+    /// messages that updated no tracked option.
+    pub fn apply(&mut self, text: &str) -> Result<Option<ChainUpdate>, serde_json::Error> {
+        let msg: OkxMessage<serde_json::Value> = serde_json::from_str(text)?;
+        if msg.handle_event() {
+            return Ok(None);
+        }
+
+        let channel = msg.channel().unwrap_or_default().to_string();
+        let arg_inst_id = msg.inst_id().map(str::to_string);
+        let mut update: Option<ChainUpdate> = None;
+        for entry in msg.data.unwrap_or_default() {
+            let (inst_id, exchange_ns) = match channel.as_str() {
+                "opt-summary" => {
+                    let summary: OkxOptSummary = serde_json::from_value(entry)?;
+                    let exchange_ns = timestamp("ts", &summary.ts)? * 1_000_000;
+                    let Some(quote) = self.quote(&summary.inst_id) else {
+                        continue;
+                    };
+                    quote.greeks = Some(Greeks {
+                        delta: number("delta", &summary.delta)?,
+                        gamma: number("gamma", &summary.gamma)?,
+                        vega: number("vega", &summary.vega)?,
+                        theta: number("theta", &summary.theta)?,
+                    });
+                    quote.greeks_bs = Some(Greeks {
+                        delta: number("deltaBS", &summary.delta_bs)?,
+                        gamma: number("gammaBS", &summary.gamma_bs)?,
+                        vega: number("vegaBS", &summary.vega_bs)?,
+                        theta: number("thetaBS", &summary.theta_bs)?,
+                    });
+                    quote.mark_vol = optional(&summary.mark_vol, |value| number("markVol", value))?;
+                    // A zero volatility means there is no order on that side
+                    let side_vol = |field, value: &str| optional(value, |value| number(field, value)).map(|vol| vol.filter(|&vol| vol > 0.0));
+                    quote.bid_vol = side_vol("bidVol", &summary.bid_vol)?;
+                    quote.ask_vol = side_vol("askVol", &summary.ask_vol)?;
+                    quote.fwd_px = optional(&summary.fwd_px, |value| number("fwdPx", value))?;
+                    (summary.inst_id, exchange_ns)
+                }
+                "mark-price" => {
+                    let mark: OkxMarkPrice = serde_json::from_value(entry)?;
+                    let exchange_ns = timestamp("ts", &mark.ts)? * 1_000_000;
+                    let Some(quote) = self.quote(&mark.inst_id) else {
+                        continue;
+                    };
+                    quote.mark_px = Some(number("markPx", &mark.mark_px)?);
+                    (mark.inst_id, exchange_ns)
+                }
+                "books5" | "bbo-tbt" => {
+                    let book: OkxBookData = serde_json::from_value(entry)?;
+                    let exchange_ns = timestamp("ts", &book.ts)? * 1_000_000;
+                    let Some(inst_id) = arg_inst_id.clone() else {
+                        continue;
+                    };
+                    let best = |levels: &[okx::OkxLevel]| -> Result<Option<Level>, serde_json::Error> {
+                        levels.first()
+                            .map(|level| Ok(Level { price: number("price", level.price())?, size: number("size", level.size())? }))
+                            .transpose()
+                    };
+                    let (bid, ask) = (best(&book.bids)?, best(&book.asks)?);
+                    let Some(quote) = self.quote(&inst_id) else {
+                        continue;
+                    };
+                    (quote.bid, quote.ask) = (bid, ask);
+                    (inst_id, exchange_ns)
+                }
+                _ => continue,
+            };
+
+            if let Some(quote) = self.quotes.get_mut(&inst_id) {
+                quote.updated_ns = Some(exchange_ns);
+            }
+            if update.is_none() {
+                update = Some(ChainUpdate {
+                    channel: channel.clone(),
+                    family: okx::inst_family(&inst_id).to_string(),
+                    exchange_ns,
+                });
+            }
+        }
+        Ok(update)
+    }
+
+    fn quote(&mut self, inst_id: &str) -> Option<&mut OptionQuote> {
+        let quote = self.quotes.get_mut(inst_id);
+        if quote.is_none() {
+            self.unknown += 1;
+            tracing::debug!("{} is not in the reference data", inst_id);
+        }
+        quote
+    }
+
+    fn sorted(&self) -> Vec<&OptionQuote> {
+        let mut quotes: Vec<&OptionQuote> = self.quotes.values().collect();
+        quotes.sort_by(|a, b| {
+            (&a.family, a.expiry_ms).cmp(&(&b.family, b.expiry_ms))
+                .then(a.strike.total_cmp(&b.strike))
+                .then((a.option_type == OptionType::Put).cmp(&(b.option_type == OptionType::Put)))
+        });
+        quotes
+    }
+
+    // Calls and puts side by side, by family, expiry and strike
+    fn expiries(&self) -> Vec<Expiry<'_>> {
+        let mut expiries: Vec<Expiry> = Vec::new();
+        for quote in self.sorted() {
+            let expiry = match expiries.last_mut() {
+                Some(expiry) if expiry.family == quote.family && expiry.expiry_ms == quote.expiry_ms => expiry,
+                _ => {
+                    expiries.push(Expiry { family: &quote.family, expiry_ms: quote.expiry_ms, strikes: Vec::new() });
+                    expiries.last_mut().expect("just pushed")
+                }
+            };
+            let strike = match expiry.strikes.last_mut() {
+                Some(strike) if strike.strike == quote.strike => strike,
+                _ => {
+                    expiry.strikes.push(Strike { strike: quote.strike, call: None, put: None });
+                    expiry.strikes.last_mut().expect("just pushed")
+                }
+            };
+            match quote.option_type {
+                OptionType::Call => strike.call = Some(quote),
+                OptionType::Put => strike.put = Some(quote),
+            }
+        }
+        expiries
+    }
+
+    /// Prints a snapshot of the whole chain.
+    pub fn print(&self, format: OutputFormat) {
+        let expiries = self.expiries();
+        match format {
+            OutputFormat::Text => {
+                for expiry in &expiries {
+                    let code = expiry.strikes.iter()
+                        .flat_map(|strike| strike.call.or(strike.put))
+                        .find_map(|quote| quote.inst_id.split('-').nth(2))
+                        .unwrap_or_default();
+                    let fwd_px = expiry.strikes.iter().flat_map(|strike| [strike.call, strike.put]).flatten().find_map(|quote| quote.fwd_px);
+                    println!(
+                        "\n=== {} options expiring {} (forward {}) ===",
+                        expiry.family, code, fwd_px.map_or("-".to_string(), |px| format!("{:.2}", px))
+                    );
+                    println!(
+                        "{:>9} {:>9} {:>9} {:>7} {:>7} | {:^10} | {:>9} {:>9} {:>9} {:>7} {:>7}",
+                        "bid", "ask", "mark", "iv %", "delta", "strike", "bid", "ask", "mark", "iv %", "delta"
+                    );
+                    for strike in &expiry.strikes {
+                        println!("{} | {:^10} | {}", side_columns(strike.call), strike.strike, side_columns(strike.put));
+                    }
+                }
+                if self.unknown > 0 {
+                    println!("\n{} updates for options missing from the reference data", self.unknown);
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::json!({ "type": "option_chain", "expiries": expiries, "unknown_updates": self.unknown })
+            ),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Expiry<'a> {
+    family: &'a str,
+    expiry_ms: u64,
+    strikes: Vec<Strike<'a>>,
+}
+
+#[derive(Serialize)]
+struct Strike<'a> {
+    strike: f64,
+    call: Option<&'a OptionQuote>,
+    put: Option<&'a OptionQuote>,
+}
+
+fn side_columns(quote: Option<&OptionQuote>) -> String {
+    let column = |value: Option<f64>, width: usize, precision: usize| match value {
+        Some(value) => format!("{:>width$.precision$}", value),
+        None => format!("{:>width$}", "-"),
+    };
+    let Some(quote) = quote else {
+        return format!("{:>45}", "");
+    };
+    format!(
+        "{} {} {} {} {}",
+        column(quote.bid.map(|level| level.price), 9, 4),
+        column(quote.ask.map(|level| level.price), 9, 4),
+        column(quote.mark_px, 9, 4),
+        column(quote.mark_vol.map(|vol| vol * 100.0), 7, 2),
+        column(quote.greeks_bs.map(|greeks| greeks.delta), 7, 3),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refdata() -> HashMap<String, ReferentialData> {
+        let option = |uid: &str, strike: f64, expiry_ms: u64, option_type: &str| serde_json::json!({
+            "uid": uid,
+            "inst_family": okx::inst_family(uid),
+            "strike": strike,
+            "expiry_ms": expiry_ms,
+            "option_type": option_type,
+        });
+        let instruments = serde_json::json!([
+            option("BTC-USD-250328-100000-C", 100000.0, 1743148800000u64, "call"),
+            option("BTC-USD-250328-90000-P", 90000.0, 1743148800000u64, "put"),
+            option("BTC-USD-250627-100000-C", 100000.0, 1751011200000u64, "call"),
+            option("ETH-USD-250328-3000-C", 3000.0, 1743148800000u64, "call"),
+            // The family falls back to the one in the instrument id
+            { "uid": "BTC-USD-250328-90000-C", "strike": 90000.0, "expiry_ms": 1743148800000u64, "option_type": "call" },
+            // Not an option
+            { "uid": "BTC-USD-250328", "inst_family": "BTC-USD", "expiry_ms": 1743148800000u64 },
+        ]);
+        serde_json::from_value::<Vec<ReferentialData>>(instruments).unwrap()
+            .into_iter()
+            .map(|instrument| (instrument.uid.clone(), instrument))
+            .collect()
+    }
+
+    fn chain(expiries: &[&str]) -> OptionChain {
+        let expiries: Vec<String> = expiries.iter().map(|expiry| expiry.to_string()).collect();
+        OptionChain::new(&refdata(), &["BTC-USD".to_string()], &expiries)
+    }
+
+    fn summary(inst_id: &str, bid_vol: &str, ask_vol: &str) -> String {
+        format!(
+            r#"{{"arg":{{"channel":"opt-summary","instFamily":"BTC-USD"}},"data":[{{"instType":"OPTION","instId":"{}","uly":"BTC-USD","delta":"0.4512","gamma":"3.1","vega":"0.0012","theta":"-0.0031","deltaBS":"0.5102","gammaBS":"0.00004","vegaBS":"102.5","thetaBS":"-75.2","lever":"12.1","markVol":"0.5821","bidVol":"{}","askVol":"{}","realVol":"","volLv":"0.55","fwdPx":"96250.4","ts":"1739181000000"}}]}}"#,
+            inst_id, bid_vol, ask_vol
+        )
+    }
+
+    #[test]
+    fn tracks_options_of_the_wanted_families_and_expiries() {
+        assert_eq!(chain(&[]).inst_ids(), [
+            "BTC-USD-250328-90000-C",
+            "BTC-USD-250328-90000-P",
+            "BTC-USD-250328-100000-C",
+            "BTC-USD-250627-100000-C",
+        ]);
+        assert_eq!(chain(&["250627"]).inst_ids(), ["BTC-USD-250627-100000-C"]);
+        assert!(chain(&["251226"]).is_empty());
+    }
+
+    #[test]
+    fn applies_greeks_and_volatilities() {
+        let mut chain = chain(&[]);
+        let update = chain.apply(&summary("BTC-USD-250328-100000-C", "0.5611", "0.6013")).unwrap().unwrap();
+        assert_eq!((update.channel.as_str(), update.family.as_str()), ("opt-summary", "BTC-USD"));
+        assert_eq!(update.exchange_ns, 1739181000000000000);
+
+        let quote = &chain.quotes["BTC-USD-250328-100000-C"];
+        let greeks = quote.greeks.unwrap();
+        assert_eq!((greeks.delta, greeks.gamma, greeks.vega, greeks.theta), (0.4512, 3.1, 0.0012, -0.0031));
+        let greeks_bs = quote.greeks_bs.unwrap();
+        assert_eq!((greeks_bs.delta, greeks_bs.gamma, greeks_bs.vega, greeks_bs.theta), (0.5102, 0.00004, 102.5, -75.2));
+        assert_eq!((quote.mark_vol, quote.bid_vol, quote.ask_vol), (Some(0.5821), Some(0.5611), Some(0.6013)));
+        assert_eq!(quote.fwd_px, Some(96250.4));
+        assert_eq!(quote.updated_ns, Some(1739181000000000000));
+    }
+
+    #[test]
+    fn zero_or_missing_side_volatility_is_none() {
+        let mut chain = chain(&[]);
+        chain.apply(&summary("BTC-USD-250328-90000-P", "0", "")).unwrap().unwrap();
+        let quote = &chain.quotes["BTC-USD-250328-90000-P"];
+        assert_eq!((quote.bid_vol, quote.ask_vol), (None, None));
+        assert_eq!(quote.mark_vol, Some(0.5821));
+    }
+
+    #[test]
+    fn counts_updates_for_unknown_options() {
+        let mut chain = chain(&["250328"]);
+        // Another expiry, and an option missing from the reference data
+        assert!(chain.apply(&summary("BTC-USD-250627-100000-C", "0.5", "0.6")).unwrap().is_none());
+        let mark = r#"{"arg":{"channel":"mark-price","instId":"BTC-USD-250328-80000-P"},"data":[{"instType":"OPTION","instId":"BTC-USD-250328-80000-P","markPx":"0.0021","ts":"1739181000100"}]}"#;
+        assert!(chain.apply(mark).unwrap().is_none());
+        let book = r#"{"arg":{"channel":"books5","instId":"BTC-USD-250328-80000-P"},"data":[{"asks":[["0.0025","10","0","2"]],"bids":[["0.002","5","0","1"]],"instId":"BTC-USD-250328-80000-P","ts":"1739181000200","seqId":1}]}"#;
+        assert!(chain.apply(book).unwrap().is_none());
+        assert_eq!(chain.unknown, 3);
+
+        // Known options still update
+        let book = book.replace("80000-P", "90000-P");
+        let update = chain.apply(&book).unwrap().unwrap();
+        assert_eq!(update.channel, "books5");
+        let quote = &chain.quotes["BTC-USD-250328-90000-P"];
+        let (bid, ask) = (quote.bid.unwrap(), quote.ask.unwrap());
+        assert_eq!((bid.price, bid.size, ask.price, ask.size), (0.002, 5.0, 0.0025, 10.0));
+        assert_eq!(chain.unknown, 3);
+    }
+}
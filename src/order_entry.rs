@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+use crate::latency::{current_timestamp_ns_hires, LatencyHistogram, LatencyStats};
+use crate::metrics::{self, Histogram};
+use crate::okx_private::{self, OrderAck, OrderOp};
+use crate::websocket::{WebSocketClient, WebSocketError};
+
+/// Result of matching an ack to its request.
+#[derive(Debug)]
+pub struct AckOutcome {
+    pub op: OrderOp,
+    /// From just before the request was written to the ack's first byte
+    pub round_trip_ns: u64,
+    pub gateway_ns: Option<u64>,
+    pub accepted: Vec<String>,
+    /// `(clOrdId, sCode, sMsg)` of each order the exchange refused
+    pub rejected: Vec<(String, String, String)>,
+}
+
+struct Pending {
+    op: OrderOp,
+    sent_ns: u64,
+    sent_at: Instant,
+}
+
+// Round-trip statistics for one operation
+struct OpStats {
+    op: OrderOp,
+    sent: u64,
+    rejected: u64,
+    timed_out: u64,
+    /// Pending when the connection was lost
+    failed: u64,
+    round_trip: LatencyStats,
+    histogram: LatencyHistogram,
+    gateway: LatencyStats,
+    metric: Arc<Histogram>,
+}
+
+/// Sends trade requests and correlates their acks by request id, timing
+/// each round trip with the same high-resolution timer as market data.
+pub struct OrderEntry {
+    // Makes client order ids unique across runs on the same account
+    prefix: String,
+    next_request_id: u64,
+    next_order_id: u64,
+    pending: HashMap<String, Pending>,
+    ops: Vec<OpStats>,
+}
+
+impl OrderEntry {
+    pub fn new(exchange: &str) -> Self {
+        let registry = metrics::metrics();
+        Self {
+            prefix: format!("lat{}", okx_private::unix_timestamp_secs()),
+            next_request_id: 1,
+            next_order_id: 1,
+            pending: HashMap::new(),
+            ops: OrderOp::ALL.iter()
+                .map(|&op| OpStats {
+                    op,
+                    sent: 0,
+                    rejected: 0,
+                    timed_out: 0,
+                    failed: 0,
+                    round_trip: LatencyStats::default(),
+                    histogram: LatencyHistogram::default(),
+                    gateway: LatencyStats::default(),
+                    metric: registry.order_ack_latency(exchange, op.name()),
+                })
+                .collect(),
+        }
+    }
+
+    /// A new client order id (alphanumeric, at most 32 characters).
+    pub fn next_cl_ord_id(&mut self) -> String {
+        let id = format!("{}n{}", self.prefix, self.next_order_id);
+        self.next_order_id += 1;
+        id
+    }
+
+    /// Sends `op` with `args` and returns its request id.
+    pub fn send<A: Serialize>(&mut self, client: &mut WebSocketClient, op: OrderOp, args: &[A]) -> Result<String, WebSocketError> {
+        let id = self.next_request_id.to_string();
+        self.next_request_id += 1;
+        let request = okx_private::trade_request(&id, op, args);
+
+        let sent_ns = current_timestamp_ns_hires();
+        client.send_text(&request)?;
+        self.pending.insert(id.clone(), Pending { op, sent_ns, sent_at: Instant::now() });
+        self.op_stats(op).sent += 1;
+        Ok(id)
+    }
+
+    /// Matches an ack received at `receive_ns` to its request. Returns `None`
+    /// for acks of requests this session did not send (or already expired).
+    pub fn acknowledge(&mut self, ack: &OrderAck, receive_ns: u64) -> Option<AckOutcome> {
+        let pending = self.pending.remove(&ack.id)?;
+        let round_trip_ns = receive_ns.saturating_sub(pending.sent_ns);
+        let gateway_ns = ack.gateway_ns();
+
+        let (mut accepted, mut rejected) = (Vec::new(), Vec::new());
+        for order in &ack.data {
+            if order.s_code == "0" {
+                accepted.push(order.cl_ord_id.clone());
+            } else {
+                rejected.push((order.cl_ord_id.clone(), order.s_code.clone(), order.s_msg.clone()));
+            }
+        }
+        // A request refused as a whole (e.g. bad parameters) has no per-order data
+        if ack.data.is_empty() && ack.code != "0" {
+            rejected.push((String::new(), ack.code.clone(), ack.msg.clone()));
+        }
+
+        let stats = self.op_stats(pending.op);
+        stats.round_trip.add_measurement(round_trip_ns);
+        stats.histogram.record(round_trip_ns);
+        stats.metric.observe(round_trip_ns);
+        stats.rejected += rejected.len() as u64;
+        if let Some(gateway_ns) = gateway_ns {
+            stats.gateway.add_measurement(gateway_ns);
+        }
+
+        Some(AckOutcome { op: pending.op, round_trip_ns, gateway_ns, accepted, rejected })
+    }
+
+    /// Gives up on requests unacknowledged for longer than `timeout`,
+    /// returning their ids and operations.
+    pub fn expire(&mut self, timeout: Duration) -> Vec<(String, OrderOp)> {
+        let expired: Vec<(String, OrderOp)> = self.pending.iter()
+            .filter(|(_, pending)| pending.sent_at.elapsed() > timeout)
+            .map(|(id, pending)| (id.clone(), pending.op))
+            .collect();
+        for (id, op) in &expired {
+            self.pending.remove(id);
+            self.op_stats(*op).timed_out += 1;
+        }
+        expired
+    }
+
+    /// Gives up on every outstanding request, e.g. because the connection
+    /// they were sent on was lost. Returns their ids and operations.
+    pub fn fail_pending(&mut self) -> Vec<(String, OrderOp)> {
+        let failed: Vec<(String, OrderOp)> = self.pending.drain()
+            .map(|(id, pending)| (id, pending.op))
+            .collect();
+        for (_, op) in &failed {
+            self.op_stats(*op).failed += 1;
+        }
+        failed
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn op_stats(&mut self, op: OrderOp) -> &mut OpStats {
+        self.ops.iter_mut()
+            .find(|stats| stats.op == op)
+            .expect("every operation has stats")
+    }
+
+    pub fn print_summary(&self, format: OutputFormat) {
+        let used = self.ops.iter().filter(|stats| stats.sent > 0);
+        match format {
+            OutputFormat::Text => {
+                println!("\n=== Order ack round trips ===");
+                println!(
+                    "{:<13} {:>5} {:>8} {:>9} {:>6} {:>9} {:>9} {:>9} {:>9} {:>12}",
+                    "op", "sent", "rejected", "timed out", "failed", "p50 ms", "p99 ms", "avg ms", "max ms", "gateway ms"
+                );
+                for stats in used {
+                    if stats.round_trip.count == 0 {
+                        println!(
+                            "{:<13} {:>5} {:>8} {:>9} {:>6}",
+                            stats.op.name(), stats.sent, stats.rejected, stats.timed_out, stats.failed
+                        );
+                        continue;
+                    }
+                    println!(
+                        "{:<13} {:>5} {:>8} {:>9} {:>6} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>12.3}",
+                        stats.op.name(),
+                        stats.sent,
+                        stats.rejected,
+                        stats.timed_out,
+                        stats.failed,
+                        stats.histogram.percentile_ms(0.50),
+                        stats.histogram.percentile_ms(0.99),
+                        stats.round_trip.average_latency_ms(),
+                        stats.round_trip.max_latency_ms(),
+                        stats.gateway.average_latency_ms(),
+                    );
+                }
+            }
+            OutputFormat::Json => {
+                let ops: Vec<serde_json::Value> = used
+                    .map(|stats| serde_json::json!({
+                        "op": stats.op.name(),
+                        "sent": stats.sent,
+                        "acked": stats.round_trip.count,
+                        "rejected": stats.rejected,
+                        "timed_out": stats.timed_out,
+                        "failed": stats.failed,
+                        "p50_ms": stats.histogram.percentile_ms(0.50),
+                        "p99_ms": stats.histogram.percentile_ms(0.99),
+                        "avg_ms": stats.round_trip.average_latency_ms(),
+                        "max_ms": stats.round_trip.max_latency_ms(),
+                        "gateway_avg_ms": stats.gateway.average_latency_ms(),
+                    }))
+                    .collect();
+                println!("{}", serde_json::json!({ "type": "order_latency", "ops": ops }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::okx_private::PrivateEvent;
+
+    // Registers a request as if `send` had written it at `sent_ns`
+    fn sent(entry: &mut OrderEntry, op: OrderOp, sent_ns: u64) -> String {
+        let id = entry.next_request_id.to_string();
+        entry.next_request_id += 1;
+        entry.pending.insert(id.clone(), Pending { op, sent_ns, sent_at: Instant::now() });
+        entry.op_stats(op).sent += 1;
+        id
+    }
+
+    fn ack(id: &str, op: &str, code: &str, data: &str) -> OrderAck {
+        let text = format!(
+            r#"{{"id":"{}","op":"{}","code":"{}","msg":"","data":[{}],"inTime":"1695190491421339","outTime":"1695190491423240"}}"#,
+            id, op, code, data
+        );
+        match PrivateEvent::parse(&text).unwrap() {
+            PrivateEvent::OrderAck(ack) => ack,
+            event => panic!("not an ack: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn matches_acks_to_their_requests() {
+        let mut entry = OrderEntry::new("test");
+        let place = sent(&mut entry, OrderOp::Order, 1_000);
+        let cancel = sent(&mut entry, OrderOp::CancelOrder, 2_000);
+
+        // Acks may come back out of order
+        let data = r#"{"clOrdId":"c1","ordId":"2","sCode":"0","sMsg":""}"#;
+        let outcome = entry.acknowledge(&ack(&cancel, "cancel-order", "0", data), 5_000).unwrap();
+        assert_eq!(outcome.op, OrderOp::CancelOrder);
+        assert!(entry.has_pending());
+
+        // Unknown and repeated ids match nothing
+        assert!(entry.acknowledge(&ack("99", "order", "0", data), 6_000).is_none());
+        assert!(entry.acknowledge(&ack(&cancel, "cancel-order", "0", data), 6_000).is_none());
+
+        let outcome = entry.acknowledge(&ack(&place, "order", "0", data), 7_000).unwrap();
+        assert_eq!(outcome.op, OrderOp::Order);
+        assert!(!entry.has_pending());
+    }
+
+    #[test]
+    fn times_place_amend_and_cancel_round_trips() {
+        let mut entry = OrderEntry::new("test");
+        let place = sent(&mut entry, OrderOp::Order, 1_000_000);
+        let amend = sent(&mut entry, OrderOp::AmendOrder, 2_000_000);
+        let cancel = sent(&mut entry, OrderOp::CancelOrder, 3_000_000);
+
+        let placed = r#"{"clOrdId":"a1","ordId":"12345689","sCode":"0","sMsg":""}"#;
+        let outcome = entry.acknowledge(&ack(&place, "order", "0", placed), 3_500_000).unwrap();
+        assert_eq!(outcome.round_trip_ns, 2_500_000);
+        // outTime - inTime, in microseconds
+        assert_eq!(outcome.gateway_ns, Some(1_901_000));
+        assert_eq!(outcome.accepted, ["a1"]);
+        assert!(outcome.rejected.is_empty());
+
+        let refused = r#"{"clOrdId":"a1","ordId":"12345689","sCode":"51503","sMsg":"Order does not exist"}"#;
+        let outcome = entry.acknowledge(&ack(&amend, "amend-order", "1", refused), 2_700_000).unwrap();
+        assert_eq!(outcome.round_trip_ns, 700_000);
+        assert_eq!(outcome.rejected, [("a1".to_string(), "51503".to_string(), "Order does not exist".to_string())]);
+
+        // Refused as a whole, without per-order data
+        let outcome = entry.acknowledge(&ack(&cancel, "cancel-order", "60012", ""), 3_100_000).unwrap();
+        assert_eq!(outcome.round_trip_ns, 100_000);
+        assert_eq!(outcome.rejected, [(String::new(), "60012".to_string(), String::new())]);
+
+        let stats = |op| {
+            let stats = entry.ops.iter().find(|stats| stats.op == op).unwrap();
+            (stats.sent, stats.rejected, stats.round_trip.total_latency_ns, stats.gateway.total_latency_ns)
+        };
+        assert_eq!(stats(OrderOp::Order), (1, 0, 2_500_000, 1_901_000));
+        assert_eq!(stats(OrderOp::AmendOrder), (1, 1, 700_000, 1_901_000));
+        assert_eq!(stats(OrderOp::CancelOrder), (1, 1, 100_000, 1_901_000));
+        assert_eq!(stats(OrderOp::BatchOrders), (0, 0, 0, 0));
+    }
+}
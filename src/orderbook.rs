@@ -0,0 +1,285 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// OKX checksums cover the best 25 levels of each side, Kraken's the best 10
+const OKX_CHECKSUM_DEPTH: usize = 25;
+const KRAKEN_CHECKSUM_DEPTH: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookError {
+    InvalidLevel(String),
+    /// An update did not continue from the last applied sequence number
+    SequenceGap { expected: i64, received: i64 },
+    ChecksumMismatch { expected: u32, computed: u32 },
+    /// An update arrived before the initial snapshot
+    NoSnapshot,
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::InvalidLevel(level) => write!(f, "Invalid price level: {}", level),
+            BookError::SequenceGap { expected, received } => {
+                write!(f, "Sequence gap: expected prevSeqId {}, received {}", expected, received)
+            }
+            BookError::ChecksumMismatch { expected, computed } => {
+                write!(f, "Checksum mismatch: exchange sent {}, book has {}", expected, computed)
+            }
+            BookError::NoSnapshot => write!(f, "Update received before snapshot"),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+/// Price as a totally ordered float, used only for sorting levels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Price and size as received, so checksums match the exchange's formatting.
+#[derive(Debug, Clone)]
+pub struct Level {
+    pub price: String,
+    pub size: String,
+}
+
+/// Local order book built from a snapshot and incremental updates.
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    bids: BTreeMap<Reverse<Price>, Level>,
+    asks: BTreeMap<Price, Level>,
+    seq_id: Option<i64>,
+    has_snapshot: bool,
+}
+
+impl OrderBook {
+    /// Replaces the book with a full snapshot.
+    pub fn apply_snapshot<'a>(
+        &mut self,
+        bids: impl IntoIterator<Item = (&'a str, &'a str)>,
+        asks: impl IntoIterator<Item = (&'a str, &'a str)>,
+        seq_id: Option<i64>,
+    ) -> Result<(), BookError> {
+        self.clear();
+        self.apply_levels(bids, asks)?;
+        self.seq_id = seq_id;
+        self.has_snapshot = true;
+        Ok(())
+    }
+
+    /// Applies an incremental update; a size of zero removes the level.
+    ///
+    /// When both sequence numbers are known the update must continue from
+    /// the last one applied. Exchanges that repeat the previous sequence
+    /// number for heartbeat updates with no changes are accepted as well.
+    pub fn apply_update<'a>(
+        &mut self,
+        bids: impl IntoIterator<Item = (&'a str, &'a str)>,
+        asks: impl IntoIterator<Item = (&'a str, &'a str)>,
+        prev_seq_id: Option<i64>,
+        seq_id: Option<i64>,
+    ) -> Result<(), BookError> {
+        if !self.has_snapshot {
+            return Err(BookError::NoSnapshot);
+        }
+        if let (Some(expected), Some(received)) = (self.seq_id, prev_seq_id) {
+            if received != expected {
+                return Err(BookError::SequenceGap { expected, received });
+            }
+        }
+
+        self.apply_levels(bids, asks)?;
+        if seq_id.is_some() {
+            self.seq_id = seq_id;
+        }
+        Ok(())
+    }
+
+    fn apply_levels<'a>(
+        &mut self,
+        bids: impl IntoIterator<Item = (&'a str, &'a str)>,
+        asks: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<(), BookError> {
+        for (price, size) in bids {
+            let (key, level) = parse_level(price, size)?;
+            match level {
+                Some(level) => self.bids.insert(Reverse(key), level),
+                None => self.bids.remove(&Reverse(key)),
+            };
+        }
+        for (price, size) in asks {
+            let (key, level) = parse_level(price, size)?;
+            match level {
+                Some(level) => self.asks.insert(key, level),
+                None => self.asks.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    /// Drops all levels; updates are rejected until the next snapshot.
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.seq_id = None;
+        self.has_snapshot = false;
+    }
+
+    /// Bids from best (highest) to worst.
+    pub fn bids(&self) -> impl Iterator<Item = &Level> {
+        self.bids.values()
+    }
+
+    /// Asks from best (lowest) to worst.
+    pub fn asks(&self) -> impl Iterator<Item = &Level> {
+        self.asks.values()
+    }
+
+    pub fn seq_id(&self) -> Option<i64> {
+        self.seq_id
+    }
+
+    /// Drops levels beyond the best `depth` of each side. Venues that only
+    /// send updates within the subscribed depth do not delete levels pushed
+    /// out of it.
+    pub fn truncate(&mut self, depth: usize) {
+        while self.bids.len() > depth {
+            self.bids.pop_last();
+        }
+        while self.asks.len() > depth {
+            self.asks.pop_last();
+        }
+    }
+
+    /// Checks the book against a checksum the exchange sent.
+    pub fn verify_checksum(&self, checksum: &(impl BookChecksum + ?Sized), expected: u32) -> Result<(), BookError> {
+        let computed = checksum.compute(self);
+        if computed == expected {
+            Ok(())
+        } else {
+            Err(BookError::ChecksumMismatch { expected, computed })
+        }
+    }
+}
+
+/// An exchange's book checksum algorithm.
+pub trait BookChecksum {
+    fn compute(&self, book: &OrderBook) -> u32;
+}
+
+/// OKX book checksum: CRC32 over `bid:size:ask:size:...` for the best 25
+/// levels, interleaving sides and continuing with the longer one. OKX sends
+/// it as a signed 32-bit integer.
+pub struct OkxChecksum;
+
+impl BookChecksum for OkxChecksum {
+    fn compute(&self, book: &OrderBook) -> u32 {
+        let mut payload = String::with_capacity(OKX_CHECKSUM_DEPTH * 4 * 12);
+        let mut bids = book.bids().take(OKX_CHECKSUM_DEPTH);
+        let mut asks = book.asks().take(OKX_CHECKSUM_DEPTH);
+
+        loop {
+            let (bid, ask) = (bids.next(), asks.next());
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            for level in [bid, ask].into_iter().flatten() {
+                if !payload.is_empty() {
+                    payload.push(':');
+                }
+                payload.push_str(&level.price);
+                payload.push(':');
+                payload.push_str(&level.size);
+            }
+        }
+
+        crc32fast::hash(payload.as_bytes())
+    }
+}
+
+/// Kraken book checksum: CRC32 over the best 10 asks, then the best 10
+/// bids, each level as its price and quantity with the decimal point and
+/// leading zeros removed. Levels must be formatted to the pair's precision.
+pub struct KrakenChecksum;
+
+impl BookChecksum for KrakenChecksum {
+    fn compute(&self, book: &OrderBook) -> u32 {
+        let mut payload = String::with_capacity(KRAKEN_CHECKSUM_DEPTH * 2 * 24);
+        let levels = book.asks().take(KRAKEN_CHECKSUM_DEPTH).chain(book.bids().take(KRAKEN_CHECKSUM_DEPTH));
+        for level in levels {
+            for value in [&level.price, &level.size] {
+                let digits = value.replace('.', "");
+                payload.push_str(digits.trim_start_matches('0'));
+            }
+        }
+        crc32fast::hash(payload.as_bytes())
+    }
+}
+
+fn parse_level(price: &str, size: &str) -> Result<(Price, Option<Level>), BookError> {
+    let key = price.parse::<f64>()
+        .map_err(|_| BookError::InvalidLevel(format!("{}@{}", size, price)))?;
+    let quantity = size.parse::<f64>()
+        .map_err(|_| BookError::InvalidLevel(format!("{}@{}", size, price)))?;
+
+    let level = (quantity != 0.0).then(|| Level {
+        price: price.to_string(),
+        size: size.to_string(),
+    });
+    Ok((Price(key), level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn okx_checksum_matches_documented_example() {
+        let mut book = OrderBook::default();
+        book.apply_snapshot([("3366.1", "7"), ("3366", "6")], [("3366.8", "9"), ("3368", "8")], None).unwrap();
+        // "3366.1:7:3366.8:9:3366:6:3368:8"
+        book.verify_checksum(&OkxChecksum, -1881014294i32 as u32).unwrap();
+    }
+
+    #[test]
+    fn okx_checksum_continues_with_the_longer_side() {
+        let mut book = OrderBook::default();
+        book.apply_snapshot([("3366.1", "7"), ("3366", "6")], [("3366.8", "9")], None).unwrap();
+        let expected = crc32fast::hash(b"3366.1:7:3366.8:9:3366:6");
+        assert_eq!(OkxChecksum.compute(&book), expected);
+    }
+
+    #[test]
+    fn kraken_checksum_matches_documented_example() {
+        let asks = ["0.05005", "0.05010", "0.05015", "0.05020", "0.05025", "0.05030", "0.05035", "0.05040", "0.05045", "0.05050"];
+        let bids = ["0.05000", "0.04995", "0.04990", "0.04980", "0.04975", "0.04970", "0.04965", "0.04960", "0.04955", "0.04950"];
+        let mut book = OrderBook::default();
+        book.apply_snapshot(
+            bids.iter().map(|price| (*price, "0.00000500")),
+            asks.iter().map(|price| (*price, "0.00000500")),
+            None,
+        ).unwrap();
+        book.verify_checksum(&KrakenChecksum, 974947235).unwrap();
+
+        book.apply_update([("0.04950", "0")], [], None, None).unwrap();
+        assert!(matches!(
+            book.verify_checksum(&KrakenChecksum, 974947235),
+            Err(BookError::ChecksumMismatch { expected: 974947235, .. })
+        ));
+    }
+}
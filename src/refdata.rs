@@ -1,7 +1,114 @@
+#![allow(dead_code)]
 
-#[derive(Debug, Clone)]
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::okx::OkxInstrument;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    Call,
+    Put,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct ReferentialData {
     pub uid: String,
+    #[serde(default = "unit_multiplier")]
     pub contract_multiplier: f64,
-    pub payoff_type: String
-}
\ No newline at end of file
+    #[serde(default)]
+    pub payoff_type: String,
+    /// Underlying family of derivatives, e.g. `BTC-USD`
+    #[serde(default)]
+    pub inst_family: Option<String>,
+    #[serde(default)]
+    pub strike: Option<f64>,
+    /// Expiry of futures and options, ms since the epoch
+    #[serde(default)]
+    pub expiry_ms: Option<u64>,
+    #[serde(default)]
+    pub option_type: Option<OptionType>,
+    /// Decimals prices and sizes are quoted with, where book checksums
+    /// depend on them (Kraken)
+    #[serde(default)]
+    pub price_precision: Option<u32>,
+    #[serde(default)]
+    pub qty_precision: Option<u32>,
+}
+
+fn unit_multiplier() -> f64 {
+    1.0
+}
+
+// Saved response of OKX `GET /api/v5/public/instruments`
+#[derive(Deserialize)]
+struct OkxInstruments {
+    data: Vec<OkxInstrument>,
+}
+
+/// Loads instruments, keyed by `uid` (the venue's symbol), from a JSON array
+/// of `ReferentialData` or the body of OKX `GET /api/v5/public/instruments`.
+pub fn load(path: &Path) -> std::io::Result<HashMap<String, ReferentialData>> {
+    let contents = std::fs::read(path)?;
+    let instruments: Vec<ReferentialData> = if contents.trim_ascii_start().starts_with(b"{") {
+        let response: OkxInstruments = serde_json::from_slice(&contents)?;
+        response.data.into_iter()
+            .map(from_okx)
+            .collect::<Result<_, _>>()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+    } else {
+        serde_json::from_slice(&contents)?
+    };
+    Ok(instruments.into_iter().map(|instrument| (instrument.uid.clone(), instrument)).collect())
+}
+
+/// Converts an OKX instrument. The multiplier is the contract value in the
+/// base currency, except for inverse contracts, whose value is fixed in the
+/// quote currency; those keep 1 and are sized in contracts.
+fn from_okx(instrument: OkxInstrument) -> Result<ReferentialData, String> {
+    let number = |field: &str, value: &str| -> Result<Option<f64>, String> {
+        match value {
+            "" => Ok(None),
+            value => value.parse().map(Some).map_err(|_| format!("{}: invalid {} '{}'", instrument.inst_id, field, value)),
+        }
+    };
+
+    let option_type = match instrument.opt_type.as_str() {
+        "C" => Some(OptionType::Call),
+        "P" => Some(OptionType::Put),
+        _ => None,
+    };
+    let payoff_type = match (option_type, instrument.ct_type.as_str()) {
+        (Some(_), _) => "option",
+        (None, "") => "spot",
+        (None, ct_type) => ct_type,
+    };
+    let contract_multiplier = match payoff_type {
+        "inverse" => 1.0,
+        _ => number("ctVal", &instrument.ct_val)?.unwrap_or(1.0) * number("ctMult", &instrument.ct_mult)?.unwrap_or(1.0),
+    };
+
+    Ok(ReferentialData {
+        contract_multiplier,
+        payoff_type: payoff_type.to_string(),
+        inst_family: Some(instrument.inst_family.clone()).filter(|family| !family.is_empty()),
+        strike: number("stk", &instrument.stk)?,
+        expiry_ms: match instrument.exp_time.as_str() {
+            "" => None,
+            ms => Some(ms.parse().map_err(|_| format!("{}: invalid expTime '{}'", instrument.inst_id, ms))?),
+        },
+        option_type,
+        price_precision: None,
+        qty_precision: None,
+        uid: instrument.inst_id,
+    })
+}
+
+/// Multiplier turning `uid`'s exchange sizes into base units; 1 for
+/// instruments without reference data.
+pub fn contract_multiplier(refdata: &HashMap<String, ReferentialData>, uid: &str) -> f64 {
+    refdata.get(uid).map_or(1.0, |instrument| instrument.contract_multiplier)
+}
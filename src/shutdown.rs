@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs SIGINT/SIGTERM handlers that request a graceful shutdown.
+///
+/// The handlers are installed without `SA_RESTART`, so a blocking socket read
+/// in the main thread returns `Interrupted` and the read loop can check
+/// `requested()`. They are also one-shot (`SA_RESETHAND`): a second Ctrl+C
+/// kills the process if shutdown hangs.
+#[cfg(unix)]
+pub fn install() -> std::io::Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = handle_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install() -> std::io::Result<()> {
+    tracing::warn!("Graceful shutdown is only supported on Unix, Ctrl+C exits immediately");
+    Ok(())
+}
+
+pub fn requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(_signal: libc::c_int) {
+    // Only async-signal-safe work here
+    SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+}
@@ -1,8 +1,11 @@
+#![allow(dead_code)]
+
 use std::{collections::{HashMap, HashSet}, sync::Arc};
 
 use tokio::sync::{mpsc, watch, Mutex};
-use tokio_tungstenite::tungstenite::Message;
+use tungstenite::Message;
 
+use crate::config::SymbolFilter;
 use crate::refdata::ReferentialData;
 
 
@@ -13,6 +16,7 @@ pub enum DataSource {
     Rest
 }
 
+#[derive(Debug, Clone)]
 pub struct SubscriptionMeta {
     pub exchange: String,
     pub channels: Vec<String>,
@@ -20,6 +24,9 @@ pub struct SubscriptionMeta {
     pub rest_url: String,
     pub max_symbols_per_sub: usize,
     pub refdata_path: String,
+    /// Configured symbols that pass `symbol_filter`
+    pub symbols: Vec<String>,
+    pub symbol_filter: SymbolFilter,
 }
 
 pub struct SubscriberManager{
@@ -0,0 +1,245 @@
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+/// Kernel receive timestamping mode for the client socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KernelTimestamping {
+    #[default]
+    Off,
+    /// `SO_TIMESTAMPNS`: software receive timestamp in nanoseconds
+    Nanoseconds,
+    /// `SO_TIMESTAMPING`: software receive timestamp, plus the NIC hardware
+    /// timestamp when the interface has hardware timestamping enabled
+    Timestamping,
+}
+
+/// Receive timestamps reported by the kernel for a socket read, in
+/// nanoseconds since the Unix epoch (`CLOCK_REALTIME`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxTimestamp {
+    pub kernel_ns: u64,
+    pub hardware_ns: Option<u64>,
+}
+
+/// TCP stream that reads through `recvmsg` when kernel timestamping is
+/// enabled and keeps the timestamp of the most recent read.
+pub struct TimestampedTcpStream {
+    stream: TcpStream,
+    mode: KernelTimestamping,
+    last_rx: Option<RxTimestamp>,
+    // u64 storage keeps the control buffer aligned for cmsghdr
+    #[cfg(target_os = "linux")]
+    control: [u64; 16],
+}
+
+impl TimestampedTcpStream {
+    pub fn new(stream: TcpStream, mode: KernelTimestamping) -> std::io::Result<Self> {
+        let mode = enable_timestamping(&stream, mode)?;
+        Ok(Self {
+            stream,
+            mode,
+            last_rx: None,
+            #[cfg(target_os = "linux")]
+            control: [0; 16],
+        })
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Timestamp of the last read that returned data, if the kernel reported one.
+    pub fn last_rx_timestamp(&self) -> Option<RxTimestamp> {
+        self.last_rx
+    }
+
+    #[cfg(target_os = "linux")]
+    fn recv_timestamped(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        use std::os::fd::AsRawFd;
+
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = self.control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = std::mem::size_of_val(&self.control) as _;
+
+        let n = unsafe { libc::recvmsg(self.stream.as_raw_fd(), &mut msg, 0) };
+        if n < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if n > 0 {
+            self.last_rx = None;
+        }
+
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            let data = unsafe { libc::CMSG_DATA(cmsg) };
+
+            if header.cmsg_level == libc::SOL_SOCKET {
+                if header.cmsg_type == libc::SCM_TIMESTAMPNS {
+                    let ts = unsafe { std::ptr::read_unaligned(data as *const libc::timespec) };
+                    self.last_rx = Some(RxTimestamp {
+                        kernel_ns: timespec_to_ns(&ts),
+                        hardware_ns: None,
+                    });
+                } else if header.cmsg_type == libc::SCM_TIMESTAMPING {
+                    // [0] software, [1] deprecated, [2] raw hardware
+                    let ts = unsafe { std::ptr::read_unaligned(data as *const [libc::timespec; 3]) };
+                    let hardware_ns = timespec_to_ns(&ts[2]);
+                    self.last_rx = Some(RxTimestamp {
+                        kernel_ns: timespec_to_ns(&ts[0]),
+                        hardware_ns: (hardware_ns != 0).then_some(hardware_ns),
+                    });
+                }
+            }
+
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+
+        Ok(n as usize)
+    }
+}
+
+impl Read for TimestampedTcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        if self.mode != KernelTimestamping::Off {
+            return self.recv_timestamped(buf);
+        }
+
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TimestampedTcpStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Enables the requested timestamping on the socket and returns the mode
+/// actually in effect: `Off` if the kernel refuses it, and always outside
+/// Linux.
+#[cfg(target_os = "linux")]
+fn enable_timestamping(stream: &TcpStream, mode: KernelTimestamping) -> std::io::Result<KernelTimestamping> {
+    use std::os::fd::AsRawFd;
+
+    let (option, value) = match mode {
+        KernelTimestamping::Off => return Ok(mode),
+        KernelTimestamping::Nanoseconds => (libc::SO_TIMESTAMPNS, 1),
+        KernelTimestamping::Timestamping => (
+            libc::SO_TIMESTAMPING,
+            (libc::SOF_TIMESTAMPING_RX_SOFTWARE
+                | libc::SOF_TIMESTAMPING_SOFTWARE
+                | libc::SOF_TIMESTAMPING_RX_HARDWARE
+                | libc::SOF_TIMESTAMPING_RAW_HARDWARE) as libc::c_int,
+        ),
+    };
+
+    let rc = unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc != 0 {
+        tracing::warn!(
+            "Failed to enable kernel receive timestamps ({:?}): {}, continuing without them",
+            mode, std::io::Error::last_os_error()
+        );
+        return Ok(KernelTimestamping::Off);
+    }
+
+    tracing::info!("Kernel receive timestamps enabled ({:?})", mode);
+    Ok(mode)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_timestamping(_stream: &TcpStream, mode: KernelTimestamping) -> std::io::Result<KernelTimestamping> {
+    if mode != KernelTimestamping::Off {
+        tracing::warn!("Kernel receive timestamps are only supported on Linux, continuing without them");
+    }
+    Ok(KernelTimestamping::Off)
+}
+
+#[cfg(target_os = "linux")]
+fn timespec_to_ns(ts: &libc::timespec) -> u64 {
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    #[cfg(target_os = "linux")]
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    // Client and server ends of a loopback connection
+    fn loopback() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn now_ns() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reads_report_kernel_receive_timestamps() {
+        let (client, mut server) = loopback();
+        let mut stream = TimestampedTcpStream::new(client, KernelTimestamping::Nanoseconds).unwrap();
+        assert_eq!(stream.mode, KernelTimestamping::Nanoseconds);
+        assert_eq!(stream.last_rx_timestamp(), None);
+
+        // The kernel turns receive timestamping on from a work queue the
+        // first time any socket asks for it, so the first packets may go
+        // unstamped
+        let mut buf = [0u8; 16];
+        let (rx, before_ns) = (0..100)
+            .find_map(|_| {
+                let before_ns = now_ns();
+                server.write_all(b"hello").unwrap();
+                assert_eq!(stream.read(&mut buf).unwrap(), 5);
+                assert_eq!(&buf[..5], b"hello");
+                let rx = stream.last_rx_timestamp();
+                if rx.is_none() {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                Some((rx?, before_ns))
+            })
+            .expect("no receive timestamp");
+        assert!(rx.kernel_ns >= before_ns, "{} before the write at {}", rx.kernel_ns, before_ns);
+        assert!(rx.kernel_ns <= now_ns());
+        assert_eq!(rx.hardware_ns, None);
+    }
+
+    #[test]
+    fn off_reads_without_timestamps() {
+        let (client, mut server) = loopback();
+        let mut stream = TimestampedTcpStream::new(client, KernelTimestamping::Off).unwrap();
+        server.write_all(b"hi").unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(stream.read(&mut buf).unwrap(), 2);
+        assert_eq!(stream.last_rx_timestamp(), None);
+    }
+}
@@ -0,0 +1,429 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::cli::{Exchange, OutputFormat};
+use crate::metrics::{self, Counter};
+
+// Bars are closed this long after their end without a later trade, to allow
+// for trades still in flight
+const BAR_CLOSE_GRACE_MS: u64 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+}
+
+/// A public trade in venue-independent form. `side` is the taker's side and
+/// `size` is in base units (exchange size times the contract multiplier).
+#[derive(Debug, Clone, Serialize)]
+pub struct Trade {
+    pub symbol: String,
+    pub trade_id: String,
+    pub side: Side,
+    pub price: f64,
+    pub size: f64,
+    /// Trades the venue aggregated into this one (OKX `trades`); the id is
+    /// the last of them
+    pub count: u64,
+    pub exchange_ns: u64,
+}
+
+/// Open, high, low and close prices and volume of one symbol's trades in a
+/// time bucket.
+#[derive(Debug, Clone, Serialize)]
+pub struct Bar {
+    pub symbol: String,
+    /// Bucket start, ms since the epoch of the exchange clock
+    pub start_ms: u64,
+    pub interval_ms: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub buy_volume: f64,
+    /// Sum of price times size, for the VWAP
+    pub notional: f64,
+    pub trades: u64,
+}
+
+impl Bar {
+    fn open(symbol: &str, start_ms: u64, interval_ms: u64, trade: &Trade) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            start_ms,
+            interval_ms,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: 0.0,
+            buy_volume: 0.0,
+            notional: 0.0,
+            trades: 0,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume += trade.size;
+        if trade.side == Side::Buy {
+            self.buy_volume += trade.size;
+        }
+        self.notional += trade.price * trade.size;
+        self.trades += trade.count;
+    }
+
+    fn end_ms(&self) -> u64 {
+        self.start_ms + self.interval_ms
+    }
+
+    pub fn vwap(&self) -> f64 {
+        if self.volume > 0.0 {
+            self.notional / self.volume
+        } else {
+            self.close
+        }
+    }
+}
+
+/// What handling a trade turned up.
+#[derive(Debug, Default)]
+pub struct TradeOutcome {
+    /// Trades missing between the previous trade id and this one
+    pub missed: Option<u64>,
+    /// Bar closed by this trade starting a later bucket
+    pub closed_bar: Option<Bar>,
+}
+
+// Per-symbol trade id tracking, totals and the bar being built
+struct SymbolTrades {
+    symbol: String,
+    last_id: Option<u64>,
+    trades: u64,
+    volume: f64,
+    buy_volume: f64,
+    notional: f64,
+    missed: u64,
+    late: u64,
+    bar: Option<Bar>,
+    /// End of the last bar closed; earlier trades are late
+    closed_until_ms: Option<u64>,
+    buys: Arc<Counter>,
+    sells: Arc<Counter>,
+    gaps: Arc<Counter>,
+}
+
+impl SymbolTrades {
+    fn close_bar(&mut self) -> Option<Bar> {
+        let bar = self.bar.take()?;
+        self.closed_until_ms = Some(bar.end_ms());
+        Some(bar)
+    }
+}
+
+/// Checks trade id continuity and aggregates trades into totals and,
+/// optionally, time-bucketed bars.
+pub struct TradeAggregator {
+    exchange: Exchange,
+    bar_interval_ms: Option<u64>,
+    symbols: Vec<SymbolTrades>,
+}
+
+impl TradeAggregator {
+    pub fn new(exchange: Exchange, bar_interval: Option<Duration>) -> Self {
+        Self {
+            exchange,
+            bar_interval_ms: bar_interval.map(|interval| (interval.as_millis() as u64).max(1)),
+            symbols: Vec::new(),
+        }
+    }
+
+    pub fn handle(&mut self, trade: &Trade) -> TradeOutcome {
+        let (exchange, bar_interval_ms) = (self.exchange, self.bar_interval_ms);
+        let state = self.symbol(&trade.symbol);
+        let mut outcome = TradeOutcome::default();
+
+        state.trades += trade.count;
+        state.volume += trade.size;
+        state.notional += trade.price * trade.size;
+        match trade.side {
+            Side::Buy => {
+                state.buy_volume += trade.size;
+                state.buys.add(trade.count);
+            }
+            Side::Sell => state.sells.add(trade.count),
+        }
+
+        if exchange.venue().sequential_trade_ids() {
+            match (trade.trade_id.parse::<u64>(), state.last_id) {
+                (Ok(id), Some(last)) if id <= last => {
+                    tracing::debug!("{} trade {} repeated or out of order (last {})", trade.symbol, id, last);
+                }
+                (Ok(id), last) => {
+                    // An aggregated trade covers `count` ids ending at its own
+                    let first = id.saturating_sub(trade.count.max(1) - 1);
+                    if let Some(last) = last.filter(|&last| first > last + 1) {
+                        let missed = first - last - 1;
+                        state.missed += missed;
+                        state.gaps.add(missed);
+                        outcome.missed = Some(missed);
+                    }
+                    state.last_id = Some(id);
+                }
+                (Err(_), _) => tracing::debug!("{} trade id {} is not numeric", trade.symbol, trade.trade_id),
+            }
+        }
+
+        if let Some(interval_ms) = bar_interval_ms {
+            let trade_ms = trade.exchange_ns / 1_000_000;
+            let start_ms = trade_ms - trade_ms % interval_ms;
+            let earliest_ms = match &state.bar {
+                Some(bar) => bar.start_ms,
+                None => state.closed_until_ms.unwrap_or_default(),
+            };
+            match &mut state.bar {
+                _ if start_ms < earliest_ms => {
+                    state.late += trade.count;
+                    tracing::debug!("{} trade {} arrived after its bar closed", trade.symbol, trade.trade_id);
+                }
+                Some(bar) if start_ms == bar.start_ms => bar.add(trade),
+                _ => {
+                    let mut next = Bar::open(&trade.symbol, start_ms, interval_ms, trade);
+                    next.add(trade);
+                    outcome.closed_bar = state.close_bar();
+                    state.bar = Some(next);
+                }
+            }
+        }
+        outcome
+    }
+
+    /// Closes bars that ended more than a grace period before `now_ms`
+    /// (wall clock, ms since the epoch) and have not seen a later trade.
+    pub fn close_due(&mut self, now_ms: u64) -> Vec<Bar> {
+        self.symbols.iter_mut()
+            .filter(|state| state.bar.as_ref().is_some_and(|bar| bar.end_ms() + BAR_CLOSE_GRACE_MS <= now_ms))
+            .filter_map(SymbolTrades::close_bar)
+            .collect()
+    }
+
+    /// Closes every open bar, e.g. on shutdown.
+    pub fn close_all(&mut self) -> Vec<Bar> {
+        self.symbols.iter_mut().filter_map(SymbolTrades::close_bar).collect()
+    }
+
+    fn symbol(&mut self, symbol: &str) -> &mut SymbolTrades {
+        let index = match self.symbols.iter().position(|state| state.symbol == symbol) {
+            Some(index) => index,
+            None => {
+                let (registry, exchange) = (metrics::metrics(), self.exchange.name());
+                self.symbols.push(SymbolTrades {
+                    symbol: symbol.to_string(),
+                    last_id: None,
+                    trades: 0,
+                    volume: 0.0,
+                    buy_volume: 0.0,
+                    notional: 0.0,
+                    missed: 0,
+                    late: 0,
+                    bar: None,
+                    closed_until_ms: None,
+                    buys: registry.trades(exchange, symbol, Side::Buy.name()),
+                    sells: registry.trades(exchange, symbol, Side::Sell.name()),
+                    gaps: registry.trade_id_gaps(exchange, symbol),
+                });
+                self.symbols.len() - 1
+            }
+        };
+        &mut self.symbols[index]
+    }
+
+    pub fn print_summary(&self, format: OutputFormat) {
+        match format {
+            OutputFormat::Text => {
+                println!("\n=== {} trades ===", self.exchange.name());
+                println!(
+                    "{:<16} {:>9} {:>16} {:>7} {:>14} {:>8} {:>6}",
+                    "symbol", "trades", "volume", "buy %", "vwap", "missed", "late"
+                );
+                for state in &self.symbols {
+                    println!(
+                        "{:<16} {:>9} {:>16.6} {:>6.1}% {:>14.4} {:>8} {:>6}",
+                        state.symbol,
+                        state.trades,
+                        state.volume,
+                        percent(state.buy_volume, state.volume),
+                        ratio(state.notional, state.volume),
+                        state.missed,
+                        state.late,
+                    );
+                }
+            }
+            OutputFormat::Json => {
+                let symbols: Vec<serde_json::Value> = self.symbols.iter()
+                    .map(|state| serde_json::json!({
+                        "symbol": state.symbol,
+                        "trades": state.trades,
+                        "volume": state.volume,
+                        "buy_volume": state.buy_volume,
+                        "vwap": ratio(state.notional, state.volume),
+                        "missed": state.missed,
+                        "late": state.late,
+                    }))
+                    .collect();
+                println!(
+                    "{}",
+                    serde_json::json!({ "type": "trades", "exchange": self.exchange.name(), "symbols": symbols })
+                );
+            }
+        }
+    }
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator > 0.0 {
+        numerator / denominator
+    } else {
+        0.0
+    }
+}
+
+fn percent(part: f64, total: f64) -> f64 {
+    ratio(part, total) * 100.0
+}
+
+pub fn print_trade(trade: &Trade, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => println!(
+            "trade {:<16} {:<4} {:>16.6} @ {:<14} (id {}{})",
+            trade.symbol,
+            trade.side.name(),
+            trade.size,
+            trade.price,
+            trade.trade_id,
+            if trade.count > 1 { format!(", {} trades", trade.count) } else { String::new() },
+        ),
+        OutputFormat::Json => {
+            let mut json = serde_json::json!(trade);
+            json["type"] = "trade".into();
+            println!("{}", json);
+        }
+    }
+}
+
+pub fn print_bar(bar: &Bar, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => println!(
+            "bar   {:<16} {} o {} h {} l {} c {} v {:.6} (buy {:.1}%) vwap {:.4} trades {}",
+            bar.symbol,
+            bar.start_ms,
+            bar.open,
+            bar.high,
+            bar.low,
+            bar.close,
+            bar.volume,
+            percent(bar.buy_volume, bar.volume),
+            bar.vwap(),
+            bar.trades,
+        ),
+        OutputFormat::Json => {
+            let mut json = serde_json::json!(bar);
+            json["type"] = "bar".into();
+            json["vwap"] = bar.vwap().into();
+            println!("{}", json);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(id: u64, count: u64, exchange_ms: u64) -> Trade {
+        Trade {
+            symbol: "BTC-USDT".to_string(),
+            trade_id: id.to_string(),
+            side: Side::Buy,
+            price: 60_000.0 + id as f64,
+            size: 0.5,
+            count,
+            exchange_ns: exchange_ms * 1_000_000,
+        }
+    }
+
+    #[test]
+    fn counts_ids_skipped_before_aggregated_trades() {
+        let mut aggregator = TradeAggregator::new(Exchange::Okx, None);
+        assert_eq!(aggregator.handle(&trade(10, 1, 0)).missed, None);
+        // Trades 13 to 15 aggregated, so 11 and 12 were missed
+        assert_eq!(aggregator.handle(&trade(15, 3, 0)).missed, Some(2));
+        assert_eq!(aggregator.handle(&trade(18, 3, 0)).missed, None);
+        assert_eq!(aggregator.symbols[0].missed, 2);
+        assert_eq!(aggregator.symbols[0].trades, 7);
+    }
+
+    #[test]
+    fn repeated_and_out_of_order_ids_are_not_gaps() {
+        let mut aggregator = TradeAggregator::new(Exchange::Okx, None);
+        aggregator.handle(&trade(10, 1, 0));
+        assert_eq!(aggregator.handle(&trade(10, 1, 0)).missed, None);
+        assert_eq!(aggregator.handle(&trade(8, 1, 0)).missed, None);
+        assert_eq!(aggregator.handle(&trade(11, 1, 0)).missed, None);
+        assert_eq!(aggregator.symbols[0].last_id, Some(11));
+        assert_eq!(aggregator.symbols[0].missed, 0);
+    }
+
+    #[test]
+    fn rolls_bars_over_at_bucket_boundaries() {
+        let mut aggregator = TradeAggregator::new(Exchange::Okx, Some(Duration::from_secs(1)));
+        assert!(aggregator.handle(&trade(1, 1, 1_000)).closed_bar.is_none());
+        assert!(aggregator.handle(&trade(3, 1, 1_999)).closed_bar.is_none());
+        assert!(aggregator.handle(&trade(2, 1, 1_500)).closed_bar.is_none());
+
+        let bar = aggregator.handle(&trade(4, 2, 2_000)).closed_bar.unwrap();
+        assert_eq!((bar.start_ms, bar.interval_ms, bar.trades), (1_000, 1_000, 3));
+        assert_eq!((bar.open, bar.high, bar.low, bar.close), (60_001.0, 60_003.0, 60_001.0, 60_002.0));
+        assert_eq!(bar.volume, 1.5);
+
+        let open = aggregator.close_all();
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].start_ms, open[0].trades), (2_000, 2));
+    }
+
+    #[test]
+    fn trades_for_a_closed_bar_are_late() {
+        let mut aggregator = TradeAggregator::new(Exchange::Okx, Some(Duration::from_secs(1)));
+        aggregator.handle(&trade(1, 1, 1_200));
+        let closed = aggregator.close_due(2_000 + BAR_CLOSE_GRACE_MS);
+        assert_eq!(closed.len(), 1);
+
+        // Still inside the closed bucket: counted late rather than emitted again
+        let outcome = aggregator.handle(&trade(2, 1, 1_800));
+        assert!(outcome.closed_bar.is_none());
+        assert!(aggregator.close_all().is_empty());
+        assert_eq!(aggregator.symbols[0].late, 1);
+
+        // The next bucket opens a bar as usual
+        aggregator.handle(&trade(3, 1, 2_100));
+        let open = aggregator.close_all();
+        assert_eq!(open[0].start_ms, 2_000);
+        aggregator.handle(&trade(4, 1, 900));
+        assert_eq!(aggregator.symbols[0].late, 2);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::cli::{Exchange, FeedArgs};
+use crate::derivatives::DerivativeEvent;
+use crate::orderbook::{BookChecksum, BookError, OrderBook};
+use crate::refdata::ReferentialData;
+use crate::trades::Trade;
+use crate::websocket::{Heartbeat, WebSocketConfig};
+
+/// Instruments by venue symbol, for contract multipliers.
+pub type RefData = Arc<HashMap<String, ReferentialData>>;
+
+/// A market data update in venue-independent form. Every venue's `Adapter`
+/// turns its messages into these, and every command works from them.
+#[derive(Debug, Clone)]
+pub enum MarketEvent {
+    /// A whole book, replacing the previous one
+    BookSnapshot(BookUpdate),
+    /// Changed book levels; a size of `0` removes the level
+    BookDelta(BookUpdate),
+    Trade(Trade),
+    /// Best bid and ask prices, `None` for an empty side
+    Bbo {
+        symbol: String,
+        bid: Option<f64>,
+        ask: Option<f64>,
+        exchange_ns: Option<u64>,
+    },
+    Derivative(DerivativeEvent),
+    /// A message carrying nothing but an exchange time, e.g. a Coinbase
+    /// heartbeat; still a latency sample
+    Heartbeat { symbol: String, exchange_ns: u64 },
+    /// The symbol's feed fell out of sync and is being resubscribed; its
+    /// book starts again from the next snapshot
+    OutOfSync { symbol: String },
+}
+
+/// Book levels of one push. Prices and sizes are kept as the venue sent
+/// them, which checksums are computed over.
+#[derive(Debug, Clone, Default)]
+pub struct BookUpdate {
+    pub symbol: String,
+    pub exchange_ns: Option<u64>,
+    pub bids: Vec<(String, String)>,
+    pub asks: Vec<(String, String)>,
+    /// Sequence number the update continues from, and its own
+    pub prev_sequence: Option<i64>,
+    pub sequence: Option<i64>,
+    /// Checksum of the book after the update, in the venue's algorithm
+    pub checksum: Option<u32>,
+    /// Levels per side the venue keeps the book to, when it does not delete
+    /// levels pushed out of it
+    pub depth: Option<usize>,
+}
+
+impl MarketEvent {
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketEvent::BookSnapshot(update) | MarketEvent::BookDelta(update) => &update.symbol,
+            MarketEvent::Trade(trade) => &trade.symbol,
+            MarketEvent::Derivative(event) => event.symbol(),
+            MarketEvent::Bbo { symbol, .. }
+            | MarketEvent::Heartbeat { symbol, .. }
+            | MarketEvent::OutOfSync { symbol } => symbol,
+        }
+    }
+
+    /// Exchange time of the update, when the venue sent one.
+    pub fn exchange_ns(&self) -> Option<u64> {
+        match self {
+            MarketEvent::BookSnapshot(update) | MarketEvent::BookDelta(update) => update.exchange_ns,
+            MarketEvent::Trade(trade) => Some(trade.exchange_ns),
+            MarketEvent::Derivative(event) => Some(event.exchange_ns()),
+            MarketEvent::Bbo { exchange_ns, .. } => *exchange_ns,
+            MarketEvent::Heartbeat { exchange_ns, .. } => Some(*exchange_ns),
+            MarketEvent::OutOfSync { .. } => None,
+        }
+    }
+}
+
+impl BookUpdate {
+    /// Applies the update to `book` as a snapshot or a delta, keeps the book
+    /// to the venue's depth and, given the venue's algorithm, verifies the
+    /// checksum.
+    pub fn apply(&self, book: &mut OrderBook, snapshot: bool, checksum: Option<&dyn BookChecksum>) -> Result<(), BookError> {
+        let (bids, asks) = (levels(&self.bids), levels(&self.asks));
+        if snapshot {
+            book.apply_snapshot(bids, asks, self.sequence)?;
+        } else {
+            book.apply_update(bids, asks, self.prev_sequence, self.sequence)?;
+        }
+        if let Some(depth) = self.depth {
+            book.truncate(depth);
+        }
+        match (self.checksum, checksum) {
+            (Some(expected), Some(checksum)) => book.verify_checksum(checksum, expected),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn levels(levels: &[(String, String)]) -> impl Iterator<Item = (&str, &str)> {
+    levels.iter().map(|(price, size)| (price.as_str(), size.as_str()))
+}
+
+/// What sets one venue apart from the others: its endpoint, keepalive,
+/// symbol naming and default channels. Commands work with any venue through
+/// this, from `Exchange::venue`.
+pub trait Venue: Sync {
+    fn public_ws_url(&self) -> &'static str;
+
+    /// How the venue expects connections to be kept alive.
+    fn heartbeat(&self) -> Heartbeat;
+
+    /// This venue's name for a `BASE-QUOTE` instrument.
+    fn symbol(&self, instrument: &str) -> String;
+
+    /// Incremental book channel `book` subscribes to by default; `None` if
+    /// the venue's books are not supported.
+    fn book_channel(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Channel carrying public trades.
+    fn trade_channel(&self) -> &'static str;
+
+    /// Channels `compare` subscribes to, for the BBO and exchange timestamps.
+    fn compare_channels(&self) -> &'static [&'static str];
+
+    /// Algorithm of the checksums on the venue's book pushes.
+    fn book_checksum(&self) -> Option<&'static dyn BookChecksum> {
+        None
+    }
+
+    /// Whether each symbol's trades are numbered consecutively, so a jump in
+    /// ids means trades were missed.
+    fn sequential_trade_ids(&self) -> bool {
+        false
+    }
+
+    /// URL to connect to, adjusting `config` to what the venue asks for.
+    fn endpoint(&self, feed: &FeedArgs, _config: &mut WebSocketConfig) -> std::io::Result<String> {
+        Ok(feed.ws_url().to_string())
+    }
+
+    /// Whether the venue sends a welcome message before it accepts
+    /// subscriptions.
+    fn awaits_welcome(&self) -> bool {
+        false
+    }
+
+    /// Whether `text` is the welcome message.
+    fn is_welcome(&self, _text: &str) -> Result<bool, serde_json::Error> {
+        Ok(true)
+    }
+
+    /// A fresh adapter for one connection. `refdata` converts contract
+    /// sizes to base units.
+    fn adapter(&self, refdata: RefData) -> Box<dyn Adapter>;
+}
+
+/// One connection's side of a venue: builds its requests and turns its
+/// messages into `MarketEvent`s, keeping whatever state that takes.
+pub trait Adapter: Send {
+    /// Requests subscribing to every channel/symbol pair.
+    fn subscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String>;
+
+    fn unsubscribe_requests(&mut self, channels: &[String], symbols: &[String]) -> Vec<String>;
+
+    /// Requests that restart a symbol's book on `channel` from a fresh
+    /// snapshot: by default, resubscribing.
+    fn resync_requests(&mut self, channel: &str, symbol: &str) -> Vec<String> {
+        let (channels, symbols) = ([channel.to_string()], [symbol.to_string()]);
+        [self.unsubscribe_requests(&channels, &symbols), self.subscribe_requests(&channels, &symbols)].concat()
+    }
+
+    /// Requests sent once per connection, after subscribing.
+    fn session_requests(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Parses a text message, appending its events. Subscription replies
+    /// and other control messages add none.
+    fn parse(&mut self, text: &str, events: &mut Vec<MarketEvent>) -> Result<(), serde_json::Error>;
+
+    /// Requests the venue expects since the last call, such as heartbeat
+    /// answers and resubscriptions, for the caller to send.
+    fn take_requests(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+impl Exchange {
+    pub fn venue(self) -> &'static dyn Venue {
+        match self {
+            Exchange::Okx => &crate::okx::Okx,
+            Exchange::Binance => &crate::binance::Binance,
+            Exchange::Bybit => &crate::bybit::Bybit,
+            Exchange::Coinbase => &crate::coinbase::Coinbase,
+            Exchange::Kraken => &crate::kraken::Kraken,
+            Exchange::Deribit => &crate::deribit::Deribit,
+            Exchange::Hyperliquid => &crate::hyperliquid::Hyperliquid,
+            Exchange::Kucoin => &crate::kucoin::Kucoin,
+        }
+    }
+}
+
+/// `Heartbeat::Text` with a venue's keepalive strings.
+pub fn text_heartbeat(ping: &str, pong: Option<&str>) -> Heartbeat {
+    Heartbeat::Text { ping: ping.to_string(), pong: pong.map(str::to_string) }
+}
+
+pub fn number(field: &str, value: &str) -> Result<f64, serde_json::Error> {
+    value.parse().map_err(|_| invalid(field, value))
+}
+
+pub fn timestamp(field: &str, value: &str) -> Result<u64, serde_json::Error> {
+    value.parse().map_err(|_| invalid(field, value))
+}
+
+// Venues such as OKX send unknown values as empty strings
+pub fn optional<T>(value: &str, parse: impl FnOnce(&str) -> Result<T, serde_json::Error>) -> Result<Option<T>, serde_json::Error> {
+    if value.is_empty() {
+        Ok(None)
+    } else {
+        parse(value).map(Some)
+    }
+}
+
+pub fn invalid(field: &str, value: &str) -> serde_json::Error {
+    serde::de::Error::custom(format!("invalid {} '{}'", field, value))
+}
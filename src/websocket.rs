@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use std::io::{Read, Write, BufRead, BufReader};
+use std::io::{Read, Write};
 use std::net::{TcpStream, ToSocketAddrs};
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
@@ -14,6 +14,8 @@ use rustls::pki_types::ServerName;
 use sha1::{Sha1, Digest};
 use base64::prelude::*;
 
+use crate::timestamping::{KernelTimestamping, RxTimestamp, TimestampedTcpStream};
+
 // WebSocket opcodes
 const OPCODE_CONTINUATION: u8 = 0x0;
 const OPCODE_TEXT: u8 = 0x1;
@@ -23,18 +25,25 @@ const OPCODE_PING: u8 = 0x9;
 const OPCODE_PONG: u8 = 0xa;
 
 // WebSocket close codes
-const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_NORMAL: u16 = 1000;
 
 // Configuration constants
 const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024; // 16MB max frame size
+const MAX_FRAME_HEADER_SIZE: usize = 14; // 2 + 8 (extended length) + 4 (mask)
+const MAX_CONTROL_PAYLOAD: usize = 125;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+const WRITE_BUFFER_SIZE: usize = 4 * 1024;
 const WEBSOCKET_MAGIC_STRING: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 const PING_INTERVAL: Duration = Duration::from_secs(30);
+// Longest a blocking read waits before checking whether a heartbeat is due
+// or the connection has gone stale
+const HEARTBEAT_TICK: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum WebSocketError {
     Io(std::io::Error),
-    InvalidUtf8(std::string::FromUtf8Error),
+    InvalidUtf8(std::str::Utf8Error),
     ProtocolError(String),
     HandshakeError(String),
     ConnectionClosed,
@@ -42,6 +51,8 @@ pub enum WebSocketError {
     InvalidCloseCode(u16),
     TlsError(rustls::Error),
     DnsError(String),
+    /// Nothing was received for the configured stale timeout
+    Stale(Duration),
 }
 
 impl fmt::Display for WebSocketError {
@@ -56,6 +67,7 @@ impl fmt::Display for WebSocketError {
             WebSocketError::InvalidCloseCode(code) => write!(f, "Invalid close code: {}", code),
             WebSocketError::TlsError(e) => write!(f, "TLS error: {}", e),
             WebSocketError::DnsError(s) => write!(f, "DNS error: {}", s),
+            WebSocketError::Stale(timeout) => write!(f, "Nothing received for {:?}", timeout),
         }
     }
 }
@@ -68,9 +80,15 @@ impl From<std::io::Error> for WebSocketError {
     }
 }
 
+impl From<std::str::Utf8Error> for WebSocketError {
+    fn from(err: std::str::Utf8Error) -> Self {
+        WebSocketError::InvalidUtf8(err)
+    }
+}
+
 impl From<std::string::FromUtf8Error> for WebSocketError {
     fn from(err: std::string::FromUtf8Error) -> Self {
-        WebSocketError::InvalidUtf8(err)
+        WebSocketError::InvalidUtf8(err.utf8_error())
     }
 }
 
@@ -82,6 +100,22 @@ impl From<rustls::Error> for WebSocketError {
 
 pub type Result<T> = std::result::Result<T, WebSocketError>;
 
+/// How the client keeps a connection alive. Keepalives go out every
+/// `ping_interval`, whether or not messages arrive in between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Heartbeat {
+    /// WebSocket ping frames
+    Ping,
+    /// A text message: a bare `ping` (OKX) or a JSON request such as Bybit's
+    /// `{"op":"ping"}`. Replies equal to `pong` are returned as `Pong`
+    /// messages instead of text.
+    Text { ping: String, pong: Option<String> },
+    /// Send nothing and only answer the server's pings. Ping frames are
+    /// answered by the client; application-level requests such as Deribit's
+    /// `test_request` are left to the caller.
+    Respond,
+}
+
 #[derive(Debug, Clone)]
 pub struct WebSocketConfig {
     pub connect_timeout: Duration,
@@ -89,9 +123,23 @@ pub struct WebSocketConfig {
     pub write_timeout: Option<Duration>,
     pub max_frame_size: usize,
     pub ping_interval: Duration,
+    pub heartbeat: Heartbeat,
+    /// Fail reads with `WebSocketError::Stale` once nothing at all has been
+    /// received for this long, so that the caller can reconnect
+    pub stale_timeout: Option<Duration>,
     pub user_agent: String,
+    /// Switch the socket to non-blocking mode once the handshake completes,
+    /// for use with `try_read_message` and `WebSocketPoller`
+    pub non_blocking: bool,
+    /// Ask the kernel for receive timestamps (Linux only), see `last_rx_timestamp`
+    pub kernel_timestamps: KernelTimestamping,
+    /// Clock sampled after each socket read that returns data, see `last_read_ns`
+    pub read_clock: Option<ReadClock>,
 }
 
+/// Returns the time and, when the clock knows it, the core it was read on.
+pub type ReadClock = fn() -> (u64, Option<u32>);
+
 impl Default for WebSocketConfig {
     fn default() -> Self {
         Self {
@@ -100,14 +148,28 @@ impl Default for WebSocketConfig {
             write_timeout: Some(DEFAULT_TIMEOUT),
             max_frame_size: MAX_FRAME_SIZE,
             ping_interval: PING_INTERVAL,
+            heartbeat: Heartbeat::Ping,
+            stale_timeout: None,
             user_agent: "RustWebSocketTLS/1.0".to_string(),
+            non_blocking: false,
+            kernel_timestamps: KernelTimestamping::Off,
+            read_clock: None,
         }
     }
 }
 
 enum StreamType {
-    Plain(TcpStream),
-    Tls(StreamOwned<ClientConnection, TcpStream>),
+    Plain(TimestampedTcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TimestampedTcpStream>>),
+}
+
+impl StreamType {
+    fn tcp(&self) -> &TimestampedTcpStream {
+        match self {
+            StreamType::Plain(stream) => stream,
+            StreamType::Tls(stream) => stream.get_ref(),
+        }
+    }
 }
 
 impl Read for StreamType {
@@ -139,9 +201,26 @@ pub struct WebSocketClient {
     stream: StreamType,
     config: WebSocketConfig,
     last_ping: Instant,
+    // When data was last seen to have arrived, updated while waiting for more
+    last_received: Instant,
+    // Whether a socket read returned data since `last_received` was updated
+    received: bool,
     closed: bool,
+    // Receive buffer; read_buf[read_start..read_end] holds bytes not yet parsed
+    read_buf: Vec<u8>,
+    read_start: usize,
+    read_end: usize,
+    // `read_clock` sample taken after the last socket read that returned data
+    last_read_ns: Option<u64>,
+    last_read_core: Option<u32>,
+    // Length of the last returned frame, released on the next read so that
+    // borrowed message views stay valid until then
+    pending_consume: usize,
+    // Scratch buffer outgoing frames are encoded into before a single write
+    write_buf: Vec<u8>,
     // Reassembly buffer for fragmented messages
     fragment_buffer: Vec<u8>,
+    // Opcode of the fragmented message in progress, 0 when none
     fragment_opcode: u8,
 }
 
@@ -171,6 +250,9 @@ impl WebSocketClient {
 
         let tcp_stream = TcpStream::connect_timeout(&socket_addrs[0], config.connect_timeout)?;
         tcp_stream.set_nodelay(true)?;
+        tcp_stream.set_read_timeout(config.read_timeout)?;
+        tcp_stream.set_write_timeout(config.write_timeout)?;
+        let tcp_stream = TimestampedTcpStream::new(tcp_stream, config.kernel_timestamps)?;
 
         let stream = if parsed_url.scheme == "wss" {
             let root_store = rustls::RootCertStore {
@@ -187,32 +269,34 @@ impl WebSocketClient {
             let client_conn = ClientConnection::new(Arc::new(tls_config), server_name)?;
             let tls_stream = StreamOwned::new(client_conn, tcp_stream);
 
-            StreamType::Tls(tls_stream)
+            StreamType::Tls(Box::new(tls_stream))
         } else {
             StreamType::Plain(tcp_stream)
         };
 
-        match &stream {
-            StreamType::Plain(tcp) => {
-                tcp.set_read_timeout(config.read_timeout)?;
-                tcp.set_write_timeout(config.write_timeout)?;
-            }
-            StreamType::Tls(tls_stream) => {
-                tls_stream.get_ref().set_read_timeout(config.read_timeout)?;
-                tls_stream.get_ref().set_write_timeout(config.write_timeout)?;
-            }
-        }
-
         let mut client = WebSocketClient {
             stream,
             config,
             last_ping: Instant::now(),
+            last_received: Instant::now(),
+            received: false,
             closed: false,
+            read_buf: vec![0u8; READ_BUFFER_SIZE],
+            read_start: 0,
+            read_end: 0,
+            last_read_ns: None,
+            last_read_core: None,
+            pending_consume: 0,
+            write_buf: Vec::with_capacity(WRITE_BUFFER_SIZE),
             fragment_buffer: Vec::new(),
             fragment_opcode: 0,
         };
 
         client.perform_handshake(&parsed_url.host, &parsed_url.path)?;
+        client.tcp_stream().set_read_timeout(heartbeat_read_timeout(client.config.read_timeout))?;
+        if client.config.non_blocking {
+            client.set_nonblocking(true)?;
+        }
         Ok(client)
     }
 
@@ -235,9 +319,24 @@ impl WebSocketClient {
         self.stream.write_all(request.as_bytes())?;
         self.stream.flush()?;
 
-        let mut reader = BufReader::new(&mut self.stream);
-        let mut response_line = String::new();
-        reader.read_line(&mut response_line)?;
+        // Read the response through the frame buffer so that any frames the
+        // server sends right after the handshake are not lost
+        let header_end = loop {
+            if let Some(pos) = find_header_end(&self.read_buf[..self.read_end]) {
+                break pos;
+            }
+            if self.read_end == self.read_buf.len() {
+                return Err(WebSocketError::HandshakeError(
+                    "Handshake response too large".to_string()
+                ));
+            }
+            self.fill_buf()?;
+        };
+
+        let response = std::str::from_utf8(&self.read_buf[..header_end])
+            .map_err(|_| WebSocketError::HandshakeError("Handshake response is not valid UTF-8".to_string()))?;
+        let mut lines = response.split("\r\n");
+        let response_line = lines.next().unwrap_or_default();
 
         tracing::debug!("Server response: {}", response_line.trim());
 
@@ -248,9 +347,7 @@ impl WebSocketClient {
         }
 
         let mut headers = HashMap::new();
-        loop {
-            let mut line = String::new();
-            reader.read_line(&mut line)?;
+        for line in lines {
             if line.trim().is_empty() {
                 break;
             }
@@ -261,6 +358,7 @@ impl WebSocketClient {
         }
 
         self.validate_handshake_headers(&headers, &key)?;
+        self.read_start = header_end;
 
         tracing::info!("WebSocket handshake successful");
         Ok(())
@@ -309,11 +407,29 @@ impl WebSocketClient {
         self.send_frame(OPCODE_BINARY, data)
     }
 
+    /// Sends one text message split into the given fragments. All fragments
+    /// go out in a single write.
+    pub fn send_text_fragmented(&mut self, fragments: &[&str]) -> Result<()> {
+        if self.closed {
+            return Err(WebSocketError::ConnectionClosed);
+        }
+        self.send_fragmented(OPCODE_TEXT, fragments.iter().map(|f| f.as_bytes()))
+    }
+
+    /// Sends one binary message split into the given fragments. All fragments
+    /// go out in a single write.
+    pub fn send_binary_fragmented(&mut self, fragments: &[&[u8]]) -> Result<()> {
+        if self.closed {
+            return Err(WebSocketError::ConnectionClosed);
+        }
+        self.send_fragmented(OPCODE_BINARY, fragments.iter().copied())
+    }
+
     pub fn send_ping(&mut self, data: &[u8]) -> Result<()> {
         if self.closed {
             return Err(WebSocketError::ConnectionClosed);
         }
-        if data.len() > 125 {
+        if data.len() > MAX_CONTROL_PAYLOAD {
             return Err(WebSocketError::ProtocolError(
                 "Ping payload too large (max 125 bytes)".to_string()
             ));
@@ -323,11 +439,44 @@ impl WebSocketClient {
         Ok(())
     }
 
+    fn send_keepalive(&mut self) -> Result<()> {
+        // Moved out while sending, which needs `&mut self`
+        let heartbeat = std::mem::replace(&mut self.config.heartbeat, Heartbeat::Respond);
+        let result = match &heartbeat {
+            Heartbeat::Ping => self.send_ping(b"ping"),
+            Heartbeat::Text { ping, .. } => self.send_text(ping),
+            Heartbeat::Respond => Ok(()),
+        };
+        self.config.heartbeat = heartbeat;
+        result?;
+        self.last_ping = Instant::now();
+        Ok(())
+    }
+
+    /// Sends the heartbeat if it is due and fails once the connection has
+    /// been quiet for longer than `stale_timeout`. Called while a read waits.
+    fn keep_alive(&mut self) -> Result<()> {
+        let now = Instant::now();
+        if now.duration_since(self.last_ping) >= self.config.ping_interval {
+            self.send_keepalive()?;
+        }
+        if std::mem::take(&mut self.received) {
+            self.last_received = now;
+        }
+        if let Some(stale_timeout) = self.config.stale_timeout {
+            if now.duration_since(self.last_received) >= stale_timeout {
+                self.closed = true;
+                return Err(WebSocketError::Stale(stale_timeout));
+            }
+        }
+        Ok(())
+    }
+
     pub fn send_pong(&mut self, data: &[u8]) -> Result<()> {
         if self.closed {
             return Err(WebSocketError::ConnectionClosed);
         }
-        if data.len() > 125 {
+        if data.len() > MAX_CONTROL_PAYLOAD {
             return Err(WebSocketError::ProtocolError(
                 "Pong payload too large (max 125 bytes)".to_string()
             ));
@@ -335,6 +484,62 @@ impl WebSocketClient {
         self.send_frame(OPCODE_PONG, data)
     }
 
+    /// Encodes several messages back to back and sends them in one write,
+    /// e.g. a burst of subscribe requests. A close, if present, must come last.
+    pub fn send_batch(&mut self, messages: &[WebSocketMessageRef<'_>]) -> Result<()> {
+        if self.closed {
+            return Err(WebSocketError::ConnectionClosed);
+        }
+
+        self.write_buf.clear();
+        let mut closing = false;
+        let mut has_ping = false;
+
+        for message in messages {
+            if closing {
+                return Err(WebSocketError::ProtocolError(
+                    "Close must be the last message in a batch".to_string()
+                ));
+            }
+            match *message {
+                WebSocketMessageRef::Text(text) => self.encode_frame(true, OPCODE_TEXT, text.as_bytes())?,
+                WebSocketMessageRef::Binary(data) => self.encode_frame(true, OPCODE_BINARY, data)?,
+                WebSocketMessageRef::Ping(data) => {
+                    if data.len() > MAX_CONTROL_PAYLOAD {
+                        return Err(WebSocketError::ProtocolError(
+                            "Ping payload too large (max 125 bytes)".to_string()
+                        ));
+                    }
+                    self.encode_frame(true, OPCODE_PING, data)?;
+                    has_ping = true;
+                }
+                WebSocketMessageRef::Pong(data) => {
+                    if data.len() > MAX_CONTROL_PAYLOAD {
+                        return Err(WebSocketError::ProtocolError(
+                            "Pong payload too large (max 125 bytes)".to_string()
+                        ));
+                    }
+                    self.encode_frame(true, OPCODE_PONG, data)?;
+                }
+                WebSocketMessageRef::Close { code, reason } => {
+                    let mut payload = [0u8; MAX_CONTROL_PAYLOAD];
+                    let len = encode_close_payload(&mut payload, code, reason)?;
+                    self.encode_frame(true, OPCODE_CLOSE, &payload[..len])?;
+                    closing = true;
+                }
+            }
+        }
+
+        self.write_frames()?;
+        if has_ping {
+            self.last_ping = Instant::now();
+        }
+        if closing {
+            self.closed = true;
+        }
+        Ok(())
+    }
+
     pub fn close(&mut self) -> Result<()> {
         self.close_with_code(CLOSE_NORMAL, "")
     }
@@ -344,208 +549,411 @@ impl WebSocketClient {
             return Ok(());
         }
 
-        if !is_valid_close_code(code) {
-            return Err(WebSocketError::InvalidCloseCode(code));
-        }
+        let mut payload = [0u8; MAX_CONTROL_PAYLOAD];
+        let len = encode_close_payload(&mut payload, Some(code), reason)?;
 
-        let reason_bytes = reason.as_bytes();
-        if reason_bytes.len() > 123 {
-            return Err(WebSocketError::ProtocolError(
-                "Close reason too long (max 123 bytes)".to_string()
-            ));
-        }
-
-        let mut payload = Vec::with_capacity(2 + reason_bytes.len());
-        payload.extend_from_slice(&code.to_be_bytes());
-        payload.extend_from_slice(reason_bytes);
-
-        self.send_frame(OPCODE_CLOSE, &payload)?;
+        self.send_frame(OPCODE_CLOSE, &payload[..len])?;
         self.closed = true;
         Ok(())
     }
 
     fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        self.write_buf.clear();
+        self.encode_frame(true, opcode, payload)?;
+        self.write_frames()
+    }
+
+    fn send_fragmented<'a>(&mut self, opcode: u8, fragments: impl ExactSizeIterator<Item = &'a [u8]>) -> Result<()> {
+        self.write_buf.clear();
+
+        let count = fragments.len();
+        if count == 0 {
+            // A message with no fragments is an empty message
+            self.encode_frame(true, opcode, &[])?;
+        }
+        for (i, fragment) in fragments.enumerate() {
+            let frame_opcode = if i == 0 { opcode } else { OPCODE_CONTINUATION };
+            self.encode_frame(i + 1 == count, frame_opcode, fragment)?;
+        }
+
+        self.write_frames()
+    }
+
+    /// Appends one masked frame to the scratch write buffer.
+    fn encode_frame(&mut self, fin: bool, opcode: u8, payload: &[u8]) -> Result<()> {
         if payload.len() > self.config.max_frame_size {
             return Err(WebSocketError::FrameTooLarge);
         }
 
-        let mut frame = Vec::new();
+        let buf = &mut self.write_buf;
+        buf.reserve(MAX_FRAME_HEADER_SIZE + payload.len());
 
-        // First byte: FIN (1) + RSV (000) + Opcode (4 bits)
-        frame.push(0x80 | opcode);
+        // First byte: FIN + RSV (000) + Opcode (4 bits)
+        buf.push(if fin { 0x80 | opcode } else { opcode });
 
         let mut mask_key = [0u8; 4];
         rand::thread_rng().fill_bytes(&mut mask_key);
 
         let payload_len = payload.len();
         if payload_len < 126 {
-            frame.push(0x80 | payload_len as u8);
+            buf.push(0x80 | payload_len as u8);
         } else if payload_len < 65536 {
-            frame.push(0x80 | 126);
-            frame.extend_from_slice(&(payload_len as u16).to_be_bytes());
+            buf.push(0x80 | 126);
+            buf.extend_from_slice(&(payload_len as u16).to_be_bytes());
         } else {
-            frame.push(0x80 | 127);
-            frame.extend_from_slice(&(payload_len as u64).to_be_bytes());
+            buf.push(0x80 | 127);
+            buf.extend_from_slice(&(payload_len as u64).to_be_bytes());
         }
 
-        frame.extend_from_slice(&mask_key);
+        buf.extend_from_slice(&mask_key);
 
-        for (i, &byte) in payload.iter().enumerate() {
-            frame.push(byte ^ mask_key[i % 4]);
-        }
+        let payload_start = buf.len();
+        buf.extend_from_slice(payload);
+        apply_mask(&mut buf[payload_start..], mask_key);
+        Ok(())
+    }
 
-        self.stream.write_all(&frame)?;
-        self.stream.flush()?;
+    /// Writes everything encoded in the scratch buffer with a single write.
+    fn write_frames(&mut self) -> Result<()> {
+        // In non-blocking mode the socket may refuse part of the write; spin
+        // until it drains rather than leave a partial frame on the wire
+        let mut written = 0;
+        while written < self.write_buf.len() {
+            match self.stream.write(&self.write_buf[written..]) {
+                Ok(0) => return Err(WebSocketError::Io(std::io::ErrorKind::WriteZero.into())),
+                Ok(n) => written += n,
+                Err(e) if is_retryable(&e) => std::hint::spin_loop(),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        loop {
+            match self.stream.flush() {
+                Ok(()) => break,
+                Err(e) if is_retryable(&e) => std::hint::spin_loop(),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.write_buf.clear();
         Ok(())
     }
 
     pub fn read_message(&mut self) -> Result<WebSocketMessage> {
+        self.read_message_ref().map(WebSocketMessageRef::into_owned)
+    }
+
+    /// Reads the next message without copying its payload. The returned view
+    /// borrows the client's receive buffers and is valid until the next read.
+    pub fn read_message_ref(&mut self) -> Result<WebSocketMessageRef<'_>> {
         if self.closed {
             return Err(WebSocketError::ConnectionClosed);
         }
 
         if self.last_ping.elapsed() > self.config.ping_interval {
-            if let Err(e) = self.send_ping(b"ping") {
+            if let Err(e) = self.send_keepalive() {
                 tracing::warn!("Failed to send keepalive ping: {}", e);
             }
         }
 
-        loop {
+        // The previous message may have been reassembled into the fragment buffer
+        if self.fragment_opcode == 0 {
+            self.fragment_buffer.clear();
+        }
+
+        let (opcode, payload) = loop {
             let frame = self.read_frame()?;
+            let payload = frame.payload_start..frame.payload_start + frame.payload_len;
 
             // Handle control frames immediately regardless of fragmentation state
             match frame.opcode {
                 OPCODE_PING => {
-                    self.send_pong(&frame.payload)?;
-                    return Ok(WebSocketMessage::Ping(frame.payload));
-                }
-                OPCODE_PONG => {
-                    return Ok(WebSocketMessage::Pong(frame.payload));
+                    let mut data = [0u8; MAX_CONTROL_PAYLOAD];
+                    data[..frame.payload_len].copy_from_slice(&self.read_buf[payload.clone()]);
+                    self.send_pong(&data[..frame.payload_len])?;
+                    break (OPCODE_PING, Some(payload));
                 }
+                OPCODE_PONG => break (OPCODE_PONG, Some(payload)),
                 OPCODE_CLOSE => {
                     self.closed = true;
-                    let (code, reason) = if frame.payload.len() >= 2 {
-                        let code = u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
-                        let reason = if frame.payload.len() > 2 {
-                            String::from_utf8_lossy(&frame.payload[2..]).to_string()
-                        } else {
-                            String::new()
-                        };
-                        (Some(code), reason)
-                    } else {
-                        (None, String::new())
-                    };
-                    return Ok(WebSocketMessage::Close { code, reason });
+                    break (OPCODE_CLOSE, Some(payload));
                 }
                 _ => {}
             }
 
             if frame.opcode == OPCODE_CONTINUATION {
-                if self.fragment_buffer.is_empty() {
+                if self.fragment_opcode == 0 {
                     return Err(WebSocketError::ProtocolError(
                         "Unexpected continuation frame".to_string()
                     ));
                 }
-                self.fragment_buffer.extend_from_slice(&frame.payload);
             } else {
                 // New data frame
-                if !self.fragment_buffer.is_empty() {
+                if self.fragment_opcode != 0 {
                     return Err(WebSocketError::ProtocolError(
                         "New data frame while fragmented message is in progress".to_string()
                     ));
                 }
                 if frame.fin {
-                    // Complete unfragmented message — fast path
-                    return match frame.opcode {
-                        OPCODE_TEXT => Ok(WebSocketMessage::Text(String::from_utf8(frame.payload)?)),
-                        OPCODE_BINARY => Ok(WebSocketMessage::Binary(frame.payload)),
-                        _ => Err(WebSocketError::ProtocolError(format!("Unknown opcode: {}", frame.opcode))),
-                    };
+                    // Complete unfragmented message — fast path, no copy
+                    break (frame.opcode, Some(payload));
                 }
                 self.fragment_opcode = frame.opcode;
-                self.fragment_buffer = frame.payload;
             }
 
+            self.fragment_buffer.extend_from_slice(&self.read_buf[payload]);
+
             if frame.fin {
-                // Final fragment — reassemble
-                let opcode = self.fragment_opcode;
-                let payload = std::mem::take(&mut self.fragment_buffer);
-                return match opcode {
-                    OPCODE_TEXT => Ok(WebSocketMessage::Text(String::from_utf8(payload)?)),
-                    OPCODE_BINARY => Ok(WebSocketMessage::Binary(payload)),
-                    _ => Err(WebSocketError::ProtocolError(format!("Unknown opcode: {}", opcode))),
+                // Final fragment — message is in the fragment buffer
+                break (std::mem::take(&mut self.fragment_opcode), None);
+            }
+        };
+
+        let data = match payload {
+            Some(range) => &self.read_buf[range],
+            None => &self.fragment_buffer[..],
+        };
+
+        match opcode {
+            OPCODE_TEXT => match &self.config.heartbeat {
+                Heartbeat::Text { pong: Some(pong), .. } if data == pong.as_bytes() => Ok(WebSocketMessageRef::Pong(data)),
+                _ => Ok(WebSocketMessageRef::Text(std::str::from_utf8(data)?)),
+            },
+            OPCODE_BINARY => Ok(WebSocketMessageRef::Binary(data)),
+            OPCODE_PING => Ok(WebSocketMessageRef::Ping(data)),
+            OPCODE_PONG => Ok(WebSocketMessageRef::Pong(data)),
+            OPCODE_CLOSE => {
+                let (code, reason) = if data.len() >= 2 {
+                    let code = u16::from_be_bytes([data[0], data[1]]);
+                    (Some(code), std::str::from_utf8(&data[2..])?)
+                } else {
+                    (None, "")
                 };
+                Ok(WebSocketMessageRef::Close { code, reason })
             }
+            _ => Err(WebSocketError::ProtocolError(format!("Unknown opcode: {}", opcode))),
         }
     }
 
-    fn read_frame(&mut self) -> Result<WebSocketFrame> {
-        let mut header = [0u8; 2];
-        self.stream.read_exact(&mut header)?;
-
-        let fin = (header[0] & 0x80) != 0;
-        let rsv = (header[0] & 0x70) >> 4;
-        let opcode = header[0] & 0x0f;
-        let masked = (header[1] & 0x80) != 0;
-        let mut payload_len = (header[1] & 0x7f) as u64;
-
-        if rsv != 0 {
-            return Err(WebSocketError::ProtocolError(
-                "Reserved bits must be zero".to_string()
-            ));
+    /// Non-blocking read for busy-poll loops. Returns `Ok(None)` when no
+    /// complete message is available yet; partially received frames and
+    /// fragments are kept and resumed on the next call.
+    pub fn try_read_message(&mut self) -> Result<Option<WebSocketMessageRef<'_>>> {
+        match self.read_message_ref() {
+            Ok(message) => Ok(Some(message)),
+            Err(WebSocketError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
         }
+    }
 
-        if masked {
-            return Err(WebSocketError::ProtocolError(
-                "Server frames must not be masked".to_string()
-            ));
+    /// Ensures a complete frame is buffered and returns its location in `read_buf`.
+    fn read_frame(&mut self) -> Result<FrameHeader> {
+        self.read_start += std::mem::take(&mut self.pending_consume);
+        if self.read_start == self.read_end {
+            self.read_start = 0;
+            self.read_end = 0;
         }
 
-        if payload_len == 126 {
-            let mut len_bytes = [0u8; 2];
-            self.stream.read_exact(&mut len_bytes)?;
-            payload_len = u16::from_be_bytes(len_bytes) as u64;
-        } else if payload_len == 127 {
-            let mut len_bytes = [0u8; 8];
-            self.stream.read_exact(&mut len_bytes)?;
-            payload_len = u64::from_be_bytes(len_bytes);
+        loop {
+            let available = &self.read_buf[self.read_start..self.read_end];
+            let needed = match parse_frame_header(available, self.config.max_frame_size)? {
+                Some(header) => {
+                    let frame_len = header.header_len + header.payload_len;
+                    if available.len() >= frame_len {
+                        self.pending_consume = frame_len;
+                        return Ok(FrameHeader {
+                            payload_start: self.read_start + header.header_len,
+                            ..header
+                        });
+                    }
+                    frame_len
+                }
+                None => MAX_FRAME_HEADER_SIZE,
+            };
 
-            if payload_len & 0x8000_0000_0000_0000 != 0 {
-                return Err(WebSocketError::ProtocolError(
-                    "Invalid payload length".to_string()
-                ));
-            }
+            self.reserve(needed);
+            self.wait_for_data()?;
         }
+    }
 
-        if payload_len as usize > self.config.max_frame_size {
-            return Err(WebSocketError::FrameTooLarge);
+    /// Reads more frame data. Socket reads time out every `HEARTBEAT_TICK`
+    /// at most, so heartbeats go out and staleness is detected while the
+    /// server is quiet; `read_timeout` still bounds the whole wait.
+    fn wait_for_data(&mut self) -> Result<()> {
+        let mut waiting_since = None;
+        loop {
+            let e = match self.fill_buf() {
+                Err(WebSocketError::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => e,
+                result => return result,
+            };
+            self.keep_alive()?;
+            if self.config.non_blocking {
+                return Err(e.into());
+            }
+            let waiting_since = *waiting_since.get_or_insert_with(Instant::now);
+            if let Some(read_timeout) = self.config.read_timeout {
+                if waiting_since.elapsed() + HEARTBEAT_TICK.min(read_timeout) >= read_timeout {
+                    return Err(e.into());
+                }
+            }
         }
+    }
 
-        let mut payload = vec![0u8; payload_len as usize];
-        if payload_len > 0 {
-            self.stream.read_exact(&mut payload)?;
+    /// Makes room for `len` bytes starting at `read_start`, compacting the
+    /// buffer and growing it only for frames larger than any seen before.
+    fn reserve(&mut self, len: usize) {
+        if self.read_start + len <= self.read_buf.len() {
+            return;
         }
-
-        if is_control_frame(opcode) {
-            if !fin {
-                return Err(WebSocketError::ProtocolError(
-                    "Control frames must not be fragmented".to_string()
-                ));
-            }
-            if payload.len() > 125 {
-                return Err(WebSocketError::ProtocolError(
-                    "Control frame payload too large".to_string()
-                ));
-            }
+        self.read_buf.copy_within(self.read_start..self.read_end, 0);
+        self.read_end -= self.read_start;
+        self.read_start = 0;
+        if len > self.read_buf.len() {
+            self.read_buf.resize(len, 0);
         }
+    }
 
-        Ok(WebSocketFrame { fin, opcode, payload })
+    fn fill_buf(&mut self) -> Result<()> {
+        let n = self.stream.read(&mut self.read_buf[self.read_end..])?;
+        if n == 0 {
+            return Err(WebSocketError::ConnectionClosed);
+        }
+        self.read_end += n;
+        self.received = true;
+        if let Some(clock) = self.config.read_clock {
+            let (ns, core) = clock();
+            self.last_read_ns = Some(ns);
+            self.last_read_core = core;
+        }
+        Ok(())
     }
 
     pub fn is_closed(&self) -> bool {
         self.closed
     }
 
+    pub fn set_nonblocking(&mut self, non_blocking: bool) -> Result<()> {
+        self.tcp_stream().set_nonblocking(non_blocking)?;
+        self.config.non_blocking = non_blocking;
+        Ok(())
+    }
+
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.tcp_stream().set_read_timeout(heartbeat_read_timeout(timeout))?;
+        self.config.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// True if a complete frame is already buffered and can be read without
+    /// touching the socket.
+    pub fn has_buffered_frame(&self) -> bool {
+        let start = self.read_start + self.pending_consume;
+        let available = &self.read_buf[start..self.read_end];
+        match parse_frame_header(available, self.config.max_frame_size) {
+            Ok(Some(header)) => available.len() >= header.header_len + header.payload_len,
+            Ok(None) => false,
+            // Let the next read surface the error
+            Err(_) => true,
+        }
+    }
+
+    /// Kernel receive timestamp of the socket read that completed the most
+    /// recently returned message. `None` unless `kernel_timestamps` is enabled
+    /// and supported on this platform.
+    pub fn last_rx_timestamp(&self) -> Option<RxTimestamp> {
+        self.stream.tcp().last_rx_timestamp()
+    }
+
+    /// `read_clock` time at which the socket read that completed the most
+    /// recently returned message finished, including TLS decryption.
+    pub fn last_read_ns(&self) -> Option<u64> {
+        self.last_read_ns
+    }
+
+    /// Core the `last_read_ns` sample was taken on, if the clock reports it.
+    pub fn last_read_core(&self) -> Option<u32> {
+        self.last_read_core
+    }
+
+    fn tcp_stream(&self) -> &TcpStream {
+        self.stream.tcp().get_ref()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for WebSocketClient {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.tcp_stream().as_raw_fd()
+    }
+}
+
+/// Waits on many non-blocking clients from a single thread using `poll(2)`.
+///
+/// After `wait` reports a client as ready, drain it with `try_read_message`
+/// until it returns `Ok(None)`: TLS may decrypt more than one frame from a
+/// single readiness event, and bytes left in the client's buffers do not wake
+/// `poll` again.
+#[cfg(unix)]
+#[derive(Default)]
+pub struct WebSocketPoller {
+    pollfds: Vec<libc::pollfd>,
+    ready: Vec<usize>,
+}
+
+#[cfg(unix)]
+impl WebSocketPoller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the indices of `clients` with data to read. Clients holding an
+    /// already buffered frame are returned immediately. `None` waits forever;
+    /// `Some(Duration::ZERO)` only checks.
+    pub fn wait(&mut self, clients: &[WebSocketClient], timeout: Option<Duration>) -> Result<&[usize]> {
+        use std::os::fd::AsRawFd;
+
+        self.ready.clear();
+        self.ready.extend(
+            clients.iter()
+                .enumerate()
+                .filter(|(_, client)| !client.is_closed() && client.has_buffered_frame())
+                .map(|(i, _)| i),
+        );
+        if !self.ready.is_empty() {
+            return Ok(&self.ready);
+        }
+
+        self.pollfds.clear();
+        self.pollfds.extend(clients.iter().map(|client| libc::pollfd {
+            fd: if client.is_closed() { -1 } else { client.as_raw_fd() },
+            events: libc::POLLIN,
+            revents: 0,
+        }));
+
+        let timeout_ms = match timeout {
+            Some(t) => t.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+
+        loop {
+            let rc = unsafe {
+                libc::poll(self.pollfds.as_mut_ptr(), self.pollfds.len() as libc::nfds_t, timeout_ms)
+            };
+            if rc >= 0 {
+                break;
+            }
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        }
+
+        self.ready.extend(
+            self.pollfds.iter()
+                .enumerate()
+                .filter(|(_, pfd)| pfd.revents & (libc::POLLIN | libc::POLLERR | libc::POLLHUP) != 0)
+                .map(|(i, _)| i),
+        );
+        Ok(&self.ready)
+    }
 }
 
 impl Drop for WebSocketClient {
@@ -557,11 +965,13 @@ impl Drop for WebSocketClient {
     }
 }
 
-#[derive(Debug)]
-struct WebSocketFrame {
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
     fin: bool,
     opcode: u8,
-    payload: Vec<u8>,
+    header_len: usize,
+    payload_start: usize,
+    payload_len: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -573,6 +983,31 @@ pub enum WebSocketMessage {
     Close { code: Option<u16>, reason: String },
 }
 
+/// Borrowed view of a message, valid until the next read on the client.
+#[derive(Debug, Clone, Copy)]
+pub enum WebSocketMessageRef<'a> {
+    Text(&'a str),
+    Binary(&'a [u8]),
+    Ping(&'a [u8]),
+    Pong(&'a [u8]),
+    Close { code: Option<u16>, reason: &'a str },
+}
+
+impl WebSocketMessageRef<'_> {
+    pub fn into_owned(self) -> WebSocketMessage {
+        match self {
+            WebSocketMessageRef::Text(text) => WebSocketMessage::Text(text.to_string()),
+            WebSocketMessageRef::Binary(data) => WebSocketMessage::Binary(data.to_vec()),
+            WebSocketMessageRef::Ping(data) => WebSocketMessage::Ping(data.to_vec()),
+            WebSocketMessageRef::Pong(data) => WebSocketMessage::Pong(data.to_vec()),
+            WebSocketMessageRef::Close { code, reason } => WebSocketMessage::Close {
+                code,
+                reason: reason.to_string(),
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ParsedWebSocketUrl {
     scheme: String,
@@ -618,10 +1053,16 @@ fn parse_websocket_url(url: &str) -> Result<ParsedWebSocketUrl> {
     })
 }
 
+/// Socket timeout for reads after the handshake: `read_timeout`, but never
+/// longer than `HEARTBEAT_TICK`.
+fn heartbeat_read_timeout(read_timeout: Option<Duration>) -> Option<Duration> {
+    Some(read_timeout.map_or(HEARTBEAT_TICK, |timeout| timeout.min(HEARTBEAT_TICK)))
+}
+
 fn generate_websocket_key() -> String {
     let mut bytes = [0u8; 16];
     rand::thread_rng().fill_bytes(&mut bytes);
-    BASE64_STANDARD.encode(&bytes)
+    BASE64_STANDARD.encode(bytes)
 }
 
 fn generate_accept_key(key: &str) -> String {
@@ -631,6 +1072,130 @@ fn generate_accept_key(key: &str) -> String {
     BASE64_STANDARD.encode(hasher.finalize())
 }
 
+/// Parses a frame header from the start of `buf`. Returns `None` if more
+/// bytes are needed to decode it.
+fn parse_frame_header(buf: &[u8], max_frame_size: usize) -> Result<Option<FrameHeader>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+
+    let fin = (buf[0] & 0x80) != 0;
+    let rsv = (buf[0] & 0x70) >> 4;
+    let opcode = buf[0] & 0x0f;
+    let masked = (buf[1] & 0x80) != 0;
+    let mut payload_len = (buf[1] & 0x7f) as u64;
+    let mut header_len = 2;
+
+    if rsv != 0 {
+        return Err(WebSocketError::ProtocolError(
+            "Reserved bits must be zero".to_string()
+        ));
+    }
+
+    if masked {
+        return Err(WebSocketError::ProtocolError(
+            "Server frames must not be masked".to_string()
+        ));
+    }
+
+    if payload_len == 126 {
+        if buf.len() < 4 {
+            return Ok(None);
+        }
+        payload_len = u16::from_be_bytes([buf[2], buf[3]]) as u64;
+        header_len = 4;
+    } else if payload_len == 127 {
+        if buf.len() < 10 {
+            return Ok(None);
+        }
+        let mut len_bytes = [0u8; 8];
+        len_bytes.copy_from_slice(&buf[2..10]);
+        payload_len = u64::from_be_bytes(len_bytes);
+        header_len = 10;
+
+        if payload_len & 0x8000_0000_0000_0000 != 0 {
+            return Err(WebSocketError::ProtocolError(
+                "Invalid payload length".to_string()
+            ));
+        }
+    }
+
+    if payload_len > max_frame_size as u64 {
+        return Err(WebSocketError::FrameTooLarge);
+    }
+
+    if is_control_frame(opcode) {
+        if !fin {
+            return Err(WebSocketError::ProtocolError(
+                "Control frames must not be fragmented".to_string()
+            ));
+        }
+        if payload_len > MAX_CONTROL_PAYLOAD as u64 {
+            return Err(WebSocketError::ProtocolError(
+                "Control frame payload too large".to_string()
+            ));
+        }
+    }
+
+    Ok(Some(FrameHeader {
+        fin,
+        opcode,
+        header_len,
+        payload_start: header_len,
+        payload_len: payload_len as usize,
+    }))
+}
+
+/// XORs `buf` with the repeating 4-byte mask, eight bytes at a time. The
+/// chunked loop is simple enough for the compiler to vectorize.
+fn apply_mask(buf: &mut [u8], mask: [u8; 4]) {
+    let mask_word = u64::from_ne_bytes([
+        mask[0], mask[1], mask[2], mask[3], mask[0], mask[1], mask[2], mask[3],
+    ]);
+
+    let mut chunks = buf.chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        let word = u64::from_ne_bytes(chunk.try_into().unwrap()) ^ mask_word;
+        chunk.copy_from_slice(&word.to_ne_bytes());
+    }
+
+    // The remainder starts on a multiple of 8, so the mask phase is unchanged
+    for (i, byte) in chunks.into_remainder().iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Writes a close frame payload (code + reason) into `buf` and returns its length.
+fn encode_close_payload(buf: &mut [u8; MAX_CONTROL_PAYLOAD], code: Option<u16>, reason: &str) -> Result<usize> {
+    let Some(code) = code else {
+        return Ok(0);
+    };
+
+    if !is_valid_close_code(code) {
+        return Err(WebSocketError::InvalidCloseCode(code));
+    }
+
+    let reason_bytes = reason.as_bytes();
+    if reason_bytes.len() > MAX_CONTROL_PAYLOAD - 2 {
+        return Err(WebSocketError::ProtocolError(
+            "Close reason too long (max 123 bytes)".to_string()
+        ));
+    }
+
+    buf[..2].copy_from_slice(&code.to_be_bytes());
+    buf[2..2 + reason_bytes.len()].copy_from_slice(reason_bytes);
+    Ok(2 + reason_bytes.len())
+}
+
+/// Returns the length of an HTTP response head, including the blank line.
+fn find_header_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|pos| pos + 4)
+}
+
+fn is_retryable(err: &std::io::Error) -> bool {
+    matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted)
+}
+
 fn is_control_frame(opcode: u8) -> bool {
     opcode >= 0x8
 }
@@ -638,3 +1203,373 @@ fn is_control_frame(opcode: u8) -> bool {
 fn is_valid_close_code(code: u16) -> bool {
     matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Server end of a loopback connection, speaking just enough of RFC 6455
+    // for the tests
+    struct Server {
+        stream: TcpStream,
+    }
+
+    impl Server {
+        fn send(&mut self, opcode: u8, payload: &[u8]) {
+            self.stream.write_all(&frame(opcode, payload)).unwrap();
+        }
+
+        // Next client frame, unmasked; `None` if nothing arrives in `timeout`
+        fn receive(&mut self, timeout: Duration) -> Option<(u8, Vec<u8>)> {
+            self.receive_frame(timeout).map(|(_, opcode, payload)| (opcode, payload))
+        }
+
+        // As `receive`, with the FIN bit
+        fn receive_frame(&mut self, timeout: Duration) -> Option<(bool, u8, Vec<u8>)> {
+            self.stream.set_read_timeout(Some(timeout)).unwrap();
+            let mut header = [0u8; 2];
+            if self.stream.read_exact(&mut header).is_err() {
+                return None;
+            }
+            let mut len = (header[1] & 0x7f) as usize;
+            if len == 126 {
+                let mut extended = [0u8; 2];
+                self.stream.read_exact(&mut extended).unwrap();
+                len = u16::from_be_bytes(extended) as usize;
+            }
+            let mut mask = [0u8; 4];
+            self.stream.read_exact(&mut mask).unwrap();
+            let mut payload = vec![0u8; len];
+            self.stream.read_exact(&mut payload).unwrap();
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+            Some((header[0] & 0x80 != 0, header[0] & 0x0f, payload))
+        }
+    }
+
+    // Unmasked server frame with a 7-bit or 16-bit length
+    fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x80 | opcode];
+        if payload.len() < 126 {
+            frame.push(payload.len() as u8);
+        } else {
+            frame.push(126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    // Accepts one client, completes its handshake and runs `script`
+    fn serve(script: impl FnOnce(Server) + Send + 'static) -> (String, thread::JoinHandle<()>) {
+        serve_with(Vec::new(), script)
+    }
+
+    // As `serve`, with `leftover` written in the same write as the handshake response
+    fn serve_with(leftover: Vec<u8>, script: impl FnOnce(Server) + Send + 'static) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            let key = request.lines()
+                .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                .unwrap();
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\n\
+                 Upgrade: websocket\r\n\
+                 Connection: Upgrade\r\n\
+                 Sec-WebSocket-Accept: {}\r\n\
+                 \r\n",
+                generate_accept_key(key.trim())
+            );
+            let mut response = response.into_bytes();
+            response.extend_from_slice(&leftover);
+            stream.write_all(&response).unwrap();
+            script(Server { stream });
+        });
+        (url, handle)
+    }
+
+    fn config(heartbeat: Heartbeat) -> WebSocketConfig {
+        WebSocketConfig {
+            read_timeout: Some(Duration::from_millis(50)),
+            ping_interval: Duration::from_millis(100),
+            heartbeat,
+            ..WebSocketConfig::default()
+        }
+    }
+
+    // Reads past read timeouts until a message or another error arrives
+    fn next(client: &mut WebSocketClient) -> Result<WebSocketMessage> {
+        loop {
+            match client.read_message() {
+                Err(WebSocketError::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn text_heartbeat_pings_and_maps_pongs() {
+        let (url, server) = serve(|mut server| {
+            let (opcode, payload) = server.receive(Duration::from_secs(5)).unwrap();
+            assert_eq!((opcode, payload.as_slice()), (OPCODE_TEXT, &b"ping"[..]));
+            server.send(OPCODE_TEXT, b"pong");
+            server.send(OPCODE_TEXT, b"{\"data\":1}");
+            server.receive(Duration::from_secs(5));
+        });
+        let heartbeat = Heartbeat::Text { ping: "ping".to_string(), pong: Some("pong".to_string()) };
+        let mut client = WebSocketClient::connect_with_config(&url, config(heartbeat)).unwrap();
+
+        assert!(matches!(next(&mut client).unwrap(), WebSocketMessage::Pong(pong) if pong == b"pong"));
+        assert!(matches!(next(&mut client).unwrap(), WebSocketMessage::Text(text) if text == "{\"data\":1}"));
+        client.close().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn respond_heartbeat_only_answers_pings() {
+        let (url, server) = serve(|mut server| {
+            // Well past the ping interval: nothing may be sent unprompted
+            assert!(server.receive(Duration::from_millis(400)).is_none());
+            server.send(OPCODE_PING, b"hb");
+            let (opcode, payload) = server.receive(Duration::from_secs(5)).unwrap();
+            assert_eq!((opcode, payload.as_slice()), (OPCODE_PONG, &b"hb"[..]));
+            server.send(OPCODE_TEXT, b"done");
+            server.receive(Duration::from_secs(5));
+        });
+        let mut client = WebSocketClient::connect_with_config(&url, config(Heartbeat::Respond)).unwrap();
+
+        assert!(matches!(next(&mut client).unwrap(), WebSocketMessage::Ping(ping) if ping == b"hb"));
+        assert!(matches!(next(&mut client).unwrap(), WebSocketMessage::Text(text) if text == "done"));
+        client.close().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn quiet_connection_goes_stale() {
+        let (url, server) = serve(|mut server| {
+            // Keep the connection open, sending nothing, until the client drops it
+            while server.receive(Duration::from_secs(5)).is_some() {}
+        });
+        let stale_timeout = Duration::from_millis(300);
+        let config = WebSocketConfig { stale_timeout: Some(stale_timeout), ..config(Heartbeat::Respond) };
+        let mut client = WebSocketClient::connect_with_config(&url, config).unwrap();
+
+        let started = Instant::now();
+        assert!(matches!(next(&mut client), Err(WebSocketError::Stale(timeout)) if timeout == stale_timeout));
+        assert!(started.elapsed() >= stale_timeout);
+        assert!(client.is_closed());
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn reads_frames_split_across_reads() {
+        let payload = "x".repeat(300);
+        let bytes = frame(OPCODE_TEXT, payload.as_bytes());
+        let (url, server) = serve(move |mut server| {
+            server.stream.set_nodelay(true).unwrap();
+            // Header split inside the extended length, then the payload in pieces
+            for piece in [&bytes[..1], &bytes[1..3], &bytes[3..100], &bytes[100..]] {
+                server.stream.write_all(piece).unwrap();
+                thread::sleep(Duration::from_millis(20));
+            }
+            server.send(OPCODE_BINARY, b"next");
+            server.receive(Duration::from_secs(5));
+        });
+        let mut client = WebSocketClient::connect_with_config(&url, config(Heartbeat::Respond)).unwrap();
+
+        assert!(matches!(next(&mut client).unwrap(), WebSocketMessage::Text(text) if text == "x".repeat(300)));
+        assert!(matches!(next(&mut client).unwrap(), WebSocketMessage::Binary(data) if data == b"next"));
+        client.close().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn reads_several_frames_from_one_read() {
+        let mut bytes = frame(OPCODE_TEXT, b"one");
+        bytes.extend_from_slice(&frame(OPCODE_BINARY, b"two"));
+        bytes.extend_from_slice(&frame(OPCODE_TEXT, b"three"));
+        let (url, server) = serve(move |mut server| {
+            server.stream.write_all(&bytes).unwrap();
+            server.receive(Duration::from_secs(5));
+        });
+        let mut client = WebSocketClient::connect_with_config(&url, config(Heartbeat::Respond)).unwrap();
+
+        assert!(matches!(client.read_message_ref().unwrap(), WebSocketMessageRef::Text("one")));
+        assert!(client.has_buffered_frame());
+        assert!(matches!(client.read_message_ref().unwrap(), WebSocketMessageRef::Binary(b"two")));
+        assert!(client.has_buffered_frame());
+        assert!(matches!(client.read_message_ref().unwrap(), WebSocketMessageRef::Text("three")));
+        assert!(!client.has_buffered_frame());
+        client.close().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn delivers_handshake_leftover_as_first_frame() {
+        let mut leftover = frame(OPCODE_TEXT, b"welcome");
+        leftover.extend_from_slice(&frame(OPCODE_TEXT, b"second"));
+        let (url, server) = serve_with(leftover, |mut server| {
+            server.receive(Duration::from_secs(5));
+        });
+        let mut client = WebSocketClient::connect_with_config(&url, config(Heartbeat::Respond)).unwrap();
+
+        // Buffered during the handshake, before any frame read
+        assert!(client.has_buffered_frame());
+        assert!(matches!(client.read_message_ref().unwrap(), WebSocketMessageRef::Text("welcome")));
+        assert!(matches!(client.read_message_ref().unwrap(), WebSocketMessageRef::Text("second")));
+        client.close().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn mask_matches_bytewise_reference() {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let data: Vec<u8> = (0..32u8).map(|i| i.wrapping_mul(37)).collect();
+        for offset in 0..8 {
+            for len in 0..=17 {
+                let mut masked = data.clone();
+                apply_mask(&mut masked[offset..offset + len], mask);
+                let mut expected = data.clone();
+                for (i, byte) in expected[offset..offset + len].iter_mut().enumerate() {
+                    *byte ^= mask[i % 4];
+                }
+                assert_eq!(masked, expected, "offset {} len {}", offset, len);
+            }
+        }
+    }
+
+    #[test]
+    fn server_decodes_batched_and_fragmented_frames() {
+        let (url, server) = serve(|mut server| {
+            let mut frames = Vec::new();
+            while let Some(frame) = server.receive_frame(Duration::from_secs(5)) {
+                let closing = frame.1 == OPCODE_CLOSE;
+                frames.push(frame);
+                if closing {
+                    break;
+                }
+            }
+            let mut close = CLOSE_NORMAL.to_be_bytes().to_vec();
+            close.extend_from_slice(b"bye");
+            assert_eq!(frames, [
+                (false, OPCODE_TEXT, b"frag".to_vec()),
+                (false, OPCODE_CONTINUATION, b"mented ".to_vec()),
+                (true, OPCODE_CONTINUATION, b"text".to_vec()),
+                (false, OPCODE_BINARY, vec![1, 2]),
+                (true, OPCODE_CONTINUATION, vec![3; 200]),
+                (true, OPCODE_TEXT, b"{\"op\":\"subscribe\"}".to_vec()),
+                (true, OPCODE_BINARY, vec![0, 1, 2, 3, 4, 5, 6, 7, 8]),
+                (true, OPCODE_PING, b"hb".to_vec()),
+                (true, OPCODE_PONG, Vec::new()),
+                (true, OPCODE_CLOSE, close),
+            ]);
+        });
+        let mut client = WebSocketClient::connect_with_config(&url, config(Heartbeat::Respond)).unwrap();
+
+        client.send_text_fragmented(&["frag", "mented ", "text"]).unwrap();
+        client.send_binary_fragmented(&[&[1, 2], &[3; 200]]).unwrap();
+        client.send_batch(&[
+            WebSocketMessageRef::Text("{\"op\":\"subscribe\"}"),
+            WebSocketMessageRef::Binary(&[0, 1, 2, 3, 4, 5, 6, 7, 8]),
+            WebSocketMessageRef::Ping(b"hb"),
+            WebSocketMessageRef::Pong(b""),
+            WebSocketMessageRef::Close { code: Some(CLOSE_NORMAL), reason: "bye" },
+        ]).unwrap();
+        assert!(client.is_closed());
+        server.join().unwrap();
+    }
+
+    fn non_blocking() -> WebSocketConfig {
+        WebSocketConfig { non_blocking: true, ..config(Heartbeat::Respond) }
+    }
+
+    // Busy-polls `try_read_message` until a message arrives
+    fn poll_next(client: &mut WebSocketClient) -> WebSocketMessage {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(message) = client.try_read_message().unwrap() {
+                return message.into_owned();
+            }
+            assert!(Instant::now() < deadline, "no message");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn try_read_returns_none_on_partial_frame() {
+        let (resume, resumed) = std::sync::mpsc::channel();
+        let bytes = frame(OPCODE_TEXT, b"partial");
+        let (url, server) = serve(move |mut server| {
+            server.stream.write_all(&bytes[..4]).unwrap();
+            resumed.recv().unwrap();
+            server.stream.write_all(&bytes[4..]).unwrap();
+            server.receive(Duration::from_secs(5));
+        });
+        let mut client = WebSocketClient::connect_with_config(&url, non_blocking()).unwrap();
+
+        assert!(client.try_read_message().unwrap().is_none());
+        thread::sleep(Duration::from_millis(50));
+        // The first bytes are buffered but do not make a message
+        assert!(client.try_read_message().unwrap().is_none());
+        assert!(!client.has_buffered_frame());
+        resume.send(()).unwrap();
+        assert!(matches!(poll_next(&mut client), WebSocketMessage::Text(text) if text == "partial"));
+        assert!(client.try_read_message().unwrap().is_none());
+        client.close().unwrap();
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn poller_reports_ready_sockets() {
+        let (resume, resumed) = std::sync::mpsc::channel();
+        let (url_a, server_a) = serve(move |mut server| {
+            resumed.recv().unwrap();
+            server.send(OPCODE_TEXT, b"a");
+            server.receive(Duration::from_secs(5));
+        });
+        let mut burst = frame(OPCODE_TEXT, b"b1");
+        burst.extend_from_slice(&frame(OPCODE_TEXT, b"b2"));
+        let (url_b, server_b) = serve(move |mut server| {
+            server.stream.write_all(&burst).unwrap();
+            server.receive(Duration::from_secs(5));
+        });
+        let mut clients = vec![
+            WebSocketClient::connect_with_config(&url_a, non_blocking()).unwrap(),
+            WebSocketClient::connect_with_config(&url_b, non_blocking()).unwrap(),
+        ];
+        let mut poller = WebSocketPoller::new();
+
+        assert_eq!(poller.wait(&clients, Some(Duration::from_secs(5))).unwrap(), [1]);
+        assert!(matches!(poll_next(&mut clients[1]), WebSocketMessage::Text(text) if text == "b1"));
+        // The second frame came in the same read and is reported without polling
+        assert_eq!(poller.wait(&clients, Some(Duration::ZERO)).unwrap(), [1]);
+        assert!(matches!(poll_next(&mut clients[1]), WebSocketMessage::Text(text) if text == "b2"));
+        assert!(clients[1].try_read_message().unwrap().is_none());
+        assert!(poller.wait(&clients, Some(Duration::from_millis(50))).unwrap().is_empty());
+
+        resume.send(()).unwrap();
+        assert_eq!(poller.wait(&clients, Some(Duration::from_secs(5))).unwrap(), [0]);
+        assert!(matches!(poll_next(&mut clients[0]), WebSocketMessage::Text(text) if text == "a"));
+
+        for client in &mut clients {
+            client.close().unwrap();
+        }
+        server_a.join().unwrap();
+        server_b.join().unwrap();
+    }
+}